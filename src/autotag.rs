@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+
+use crate::cache::{Cache, CacheType};
+use crate::links::{read_links, write_links};
+use crate::output::{emit, progress, Report};
+
+/// Common English words that never make useful keywords
+const STOPWORDS: &[&str] = &[
+    "about", "above", "after", "again", "against", "all", "also", "and", "any", "are", "because",
+    "been", "before", "being", "below", "between", "both", "but", "can", "could", "did", "does",
+    "doing", "down", "during", "each", "even", "every", "few", "for", "from", "further", "get",
+    "gets", "had", "has", "have", "having", "her", "here", "hers", "him", "his", "how", "into",
+    "its", "just", "like", "make", "many", "more", "most", "much", "must", "not", "now", "off",
+    "once", "one", "only", "other", "our", "ours", "out", "over", "own", "same", "she", "should",
+    "some", "such", "than", "that", "the", "their", "theirs", "them", "then", "there", "these",
+    "they", "this", "those", "through", "too", "two", "under", "until", "use", "used", "using",
+    "very", "was", "way", "were", "what", "when", "where", "which", "while", "who", "whom", "why",
+    "will", "with", "would", "you", "your", "yours",
+];
+
+pub struct AutotagOptions {
+    /// JSON object mapping each tag to the words and phrases that suggest it
+    pub vocabulary: String,
+    pub dry_run: bool,
+    /// Also propose extra tags for links that already have some
+    pub all: bool,
+    pub max_tags: usize,
+    /// How many of each article's top keywords are matched against the vocabulary
+    pub keywords: usize,
+}
+
+/// Lower-cased words of at least three letters, skipping stopwords and numbers.
+/// Returns each word plus every pair of adjacent words, so two-word phrases
+/// like "machine learning" can be matched.
+pub fn terms(text: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    let mut previous: Option<String> = None;
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let word = word.to_lowercase();
+        if word.chars().count() < 3
+            || word.chars().all(|c| c.is_numeric())
+            || STOPWORDS.contains(&word.as_str())
+        {
+            previous = None;
+            continue;
+        }
+        if let Some(previous) = &previous {
+            terms.push(format!("{previous} {word}"));
+        }
+        terms.push(word.clone());
+        previous = Some(word);
+    }
+    terms
+}
+
+/// Document frequencies over the cached articles.
+pub struct Corpus {
+    doc_freq: HashMap<String, usize>,
+    docs: usize,
+}
+
+impl Corpus {
+    pub fn new<'a>(texts: impl Iterator<Item = &'a str>) -> Self {
+        let mut doc_freq = HashMap::new();
+        let mut docs = 0;
+        for text in texts {
+            docs += 1;
+            for term in terms(text).into_iter().collect::<HashSet<_>>() {
+                *doc_freq.entry(term).or_default() += 1;
+            }
+        }
+        Corpus { doc_freq, docs }
+    }
+
+    /// Inverse document frequency of `term`, smoothed so unseen terms score highest.
+    pub fn idf(&self, term: &str) -> f64 {
+        let df = self.doc_freq.get(term).copied().unwrap_or(0);
+        ((1 + self.docs) as f64 / (1 + df) as f64).ln() + 1.0
+    }
+
+    /// The `n` terms of `text` with the highest TF-IDF, best first.
+    pub fn keywords(&self, text: &str, n: usize) -> Vec<(String, f64)> {
+        let terms = terms(text);
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for term in &terms {
+            *counts.entry(term).or_default() += 1;
+        }
+
+        let mut scored: Vec<(String, f64)> = counts
+            .into_iter()
+            .map(|(term, count)| {
+                let tf = count as f64 / terms.len() as f64;
+                (term.to_string(), tf * self.idf(term))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(n);
+        scored
+    }
+}
+
+/// Tags and the normalized terms that suggest each of them.
+struct Vocabulary {
+    tags: Vec<(String, HashSet<String>)>,
+    /// (tag, entry) pairs that normalize to no term, like "ai" or "go", and so never match
+    unmatchable: Vec<(String, String)>,
+}
+
+impl Vocabulary {
+    /// Each tag is suggested by its own name (with `-` and `_` read as spaces)
+    /// and by its synonyms, each of one or two words. Keywords skip short and
+    /// common words, so entries made only of those are listed as unmatchable.
+    fn new(entries: HashMap<String, Vec<String>>) -> Self {
+        let normalize = |phrase: &str| {
+            let words: Vec<String> = terms(phrase)
+                .into_iter()
+                .filter(|term| !term.contains(' '))
+                .collect();
+            (1..=2).contains(&words.len()).then(|| words.join(" "))
+        };
+        let mut unmatchable = Vec::new();
+        let mut tags: Vec<(String, HashSet<String>)> = entries
+            .into_iter()
+            .map(|(tag, synonyms)| {
+                let name = tag.replace(['-', '_'], " ");
+                let mut terms = HashSet::new();
+                for entry in std::iter::once(&name).chain(&synonyms) {
+                    match normalize(entry) {
+                        Some(term) => {
+                            terms.insert(term);
+                        }
+                        None => unmatchable.push((tag.clone(), entry.clone())),
+                    }
+                }
+                (tag, terms)
+            })
+            .collect();
+        tags.sort_by(|a, b| a.0.cmp(&b.0));
+        unmatchable.sort();
+        Vocabulary { tags, unmatchable }
+    }
+
+    /// Vocabulary tags matching `keywords`, strongest first.
+    fn propose(&self, keywords: &[(String, f64)], max_tags: usize) -> Vec<String> {
+        let mut scored: Vec<(&str, f64)> = self
+            .tags
+            .iter()
+            .filter_map(|(tag, terms)| {
+                let score: f64 = keywords
+                    .iter()
+                    .filter(|(keyword, _)| terms.contains(keyword))
+                    .map(|(_, score)| score)
+                    .sum();
+                (score > 0.0).then_some((tag.as_str(), score))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        scored
+            .into_iter()
+            .take(max_tags)
+            .map(|(tag, _)| tag.to_string())
+            .collect()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ProposedTags {
+    url: String,
+    title: String,
+    /// Tags not already on the link
+    tags: Vec<String>,
+}
+
+impl Report for ProposedTags {
+    const KIND: &'static str = "proposed_tags";
+
+    fn text(&self) -> String {
+        format!("{} ({}): +{}", self.title, self.url, self.tags.join(" +"))
+    }
+}
+
+#[derive(serde::Serialize)]
+struct AutotagReport {
+    tagged: usize,
+    /// Cached links without tags, or every cached link with `--all`
+    considered: usize,
+    dry_run: bool,
+}
+
+impl Report for AutotagReport {
+    const KIND: &'static str = "autotag";
+
+    fn text(&self) -> String {
+        if self.dry_run {
+            format!(
+                "\nWould tag {} of {} cached links; run without --dry-run to apply",
+                self.tagged, self.considered
+            )
+        } else {
+            format!(
+                "\nTagged {} of {} cached links; `raindrop` will push the new tags",
+                self.tagged, self.considered
+            )
+        }
+    }
+}
+
+pub fn autotag(options: &AutotagOptions) -> anyhow::Result<()> {
+    let vocabulary: HashMap<String, Vec<String>> = serde_json::from_str(
+        &std::fs::read_to_string(&options.vocabulary).with_context(|| {
+            format!(
+                "Failed to read {}; create it as a JSON object mapping tags to synonyms",
+                options.vocabulary
+            )
+        })?,
+    )
+    .with_context(|| format!("Failed to parse {}", options.vocabulary))?;
+    let vocabulary = Vocabulary::new(vocabulary);
+    for (tag, entry) in &vocabulary.unmatchable {
+        progress!(
+            "Vocabulary entry \"{entry}\" for tag {tag} never matches: it needs one or two words \
+            of three or more letters that are not common words"
+        );
+    }
+
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let cached = cache.query_all()?;
+    let corpus = Corpus::new(cached.iter().map(|link| link.text_content.as_str()));
+    let texts: HashMap<&str, &str> = cached
+        .iter()
+        .map(|link| (link.url.as_str(), link.text_content.as_str()))
+        .collect();
+
+    let mut links = read_links()?;
+    let mut tagged = 0;
+    let mut considered = 0;
+    for link in links
+        .iter_mut()
+        .filter(|link| options.all || link.tags.is_empty())
+    {
+        let Some(text) = texts.get(link.url.as_str()) else {
+            continue;
+        };
+        considered += 1;
+        let keywords = corpus.keywords(text, options.keywords);
+        let new_tags: Vec<String> = vocabulary
+            .propose(&keywords, options.max_tags)
+            .into_iter()
+            .filter(|tag| !link.tags.contains(tag))
+            .collect();
+        if new_tags.is_empty() {
+            continue;
+        }
+
+        emit(&ProposedTags {
+            url: link.url.clone(),
+            title: link.title.clone(),
+            tags: new_tags.clone(),
+        })?;
+        tagged += 1;
+        if !options.dry_run {
+            link.tags.extend(new_tags);
+            cache.update_tags(&link.url, &link.tags)?;
+        }
+    }
+
+    if !options.dry_run {
+        write_links(&links)?;
+    }
+    emit(&AutotagReport {
+        tagged,
+        considered,
+        dry_run: options.dry_run,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_terms_include_adjacent_pairs() {
+        assert_eq!(
+            terms("The Machine-Learning of 2024 models"),
+            ["machine", "machine learning", "learning", "models"]
+        );
+    }
+
+    #[test]
+    fn test_keywords_prefer_distinctive_terms() {
+        let texts = [
+            "rust compiler borrow checker rust ownership",
+            "python interpreter garbage collector",
+            "compiler design for python",
+        ];
+        let corpus = Corpus::new(texts.into_iter());
+
+        let keywords = corpus.keywords(texts[0], 2);
+        assert_eq!(keywords[0].0, "rust");
+        assert!(keywords.iter().all(|(term, _)| term != "compiler"));
+    }
+
+    #[test]
+    fn test_vocabulary_matches_names_and_synonyms() {
+        let vocabulary = Vocabulary::new(HashMap::from([
+            (
+                "machine-learning".to_string(),
+                vec!["Neural Networks".to_string()],
+            ),
+            ("rust".to_string(), vec!["cargo".to_string()]),
+            ("cooking".to_string(), Vec::new()),
+            (
+                "ai".to_string(),
+                vec!["artificial intelligence".to_string()],
+            ),
+        ]));
+        let keywords = vec![
+            ("neural networks".to_string(), 0.5),
+            ("cargo".to_string(), 0.2),
+            ("machine learning".to_string(), 0.1),
+        ];
+
+        assert_eq!(
+            vocabulary.propose(&keywords, 3),
+            ["machine-learning", "rust"]
+        );
+        assert_eq!(vocabulary.propose(&keywords, 1), ["machine-learning"]);
+        assert_eq!(
+            vocabulary.unmatchable,
+            [("ai".to_string(), "ai".to_string())]
+        );
+    }
+}
@@ -1,9 +1,12 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::{Context, Ok};
 use rusqlite::{named_params, Connection};
 
-use crate::models::CachedLink;
+use crate::models::{
+    Annotation, CachedLink, FetchError, FetchFailure, LinkCheck, LinkSummary, PageMetadata,
+    RelatedLink, SearchFilters, SearchResult, Snapshot, StaleLink,
+};
 
 pub enum CacheType {
     Disk(String),
@@ -14,6 +17,209 @@ pub struct Cache {
     conn: Connection,
 }
 
+/// Schema changes applied in order on top of the original `cache` table.
+/// The index of each entry plus one is recorded in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // Drop the CHECK constraint on `source` so new link sources can be stored
+    "CREATE TABLE cache_new (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT UNIQUE,
+        title TEXT,
+        parsed_content TEXT,
+        source TEXT,
+        tags JSON,
+        archived_at DATETIME
+    );
+    INSERT INTO cache_new SELECT id, url, title, parsed_content, source, tags, archived_at FROM cache;
+    DROP TABLE cache;
+    ALTER TABLE cache_new RENAME TO cache;",
+    "CREATE TABLE annotations (
+        id TEXT PRIMARY KEY,
+        url TEXT REFERENCES cache(url),
+        quote TEXT,
+        note TEXT,
+        tags JSON,
+        selector JSON,
+        position INTEGER,
+        created_at DATETIME
+    );
+    CREATE INDEX annotations_url ON annotations(url);",
+    "CREATE TABLE fetch_attempts (
+        url TEXT PRIMARY KEY,
+        status_code INTEGER,
+        error_class TEXT,
+        message TEXT,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        last_attempt_at DATETIME,
+        next_retry_at DATETIME
+    );",
+    "CREATE TABLE link_checks (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL,
+        checked_at DATETIME NOT NULL DEFAULT (datetime('now')),
+        status TEXT NOT NULL,
+        status_code INTEGER,
+        final_url TEXT,
+        message TEXT,
+        wayback_url TEXT
+    );
+    CREATE INDEX link_checks_url ON link_checks(url, checked_at);",
+    "ALTER TABLE cache ADD COLUMN final_url TEXT;
+    CREATE TABLE redirects (
+        url TEXT NOT NULL,
+        hop INTEGER NOT NULL,
+        location TEXT NOT NULL,
+        PRIMARY KEY (url, hop)
+    );",
+    "ALTER TABLE cache ADD COLUMN content_html TEXT;
+    ALTER TABLE cache ADD COLUMN author TEXT;
+    ALTER TABLE cache ADD COLUMN published_at TEXT;
+    ALTER TABLE cache ADD COLUMN site_name TEXT;
+    ALTER TABLE cache ADD COLUMN language TEXT;
+    ALTER TABLE cache ADD COLUMN lead_image TEXT;
+    ALTER TABLE cache ADD COLUMN description TEXT;
+    ALTER TABLE cache ADD COLUMN word_count INTEGER;",
+    "ALTER TABLE cache ADD COLUMN content_type TEXT;",
+    "CREATE TABLE snapshots (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL,
+        captured_at DATETIME NOT NULL DEFAULT (datetime('now')),
+        format TEXT NOT NULL,
+        path TEXT NOT NULL,
+        warc_offset INTEGER
+    );
+    CREATE INDEX snapshots_url ON snapshots(url, captured_at);",
+    "ALTER TABLE cache ADD COLUMN etag TEXT;
+    ALTER TABLE cache ADD COLUMN last_modified TEXT;
+    ALTER TABLE cache ADD COLUMN content_hash TEXT;
+    ALTER TABLE cache ADD COLUMN fetched_at DATETIME;
+    CREATE TABLE content_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        url TEXT NOT NULL,
+        title TEXT,
+        parsed_content TEXT,
+        content_hash TEXT,
+        fetched_at DATETIME,
+        replaced_at DATETIME NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX content_history_url ON content_history(url, replaced_at);",
+    "CREATE VIRTUAL TABLE cache_fts USING fts5(
+        title,
+        parsed_content,
+        content = 'cache',
+        content_rowid = 'id',
+        tokenize = 'porter unicode61'
+    );
+    CREATE TRIGGER cache_fts_insert AFTER INSERT ON cache BEGIN
+        INSERT INTO cache_fts (rowid, title, parsed_content)
+        VALUES (new.id, new.title, new.parsed_content);
+    END;
+    CREATE TRIGGER cache_fts_delete AFTER DELETE ON cache BEGIN
+        INSERT INTO cache_fts (cache_fts, rowid, title, parsed_content)
+        VALUES ('delete', old.id, old.title, old.parsed_content);
+    END;
+    CREATE TRIGGER cache_fts_update AFTER UPDATE OF title, parsed_content ON cache BEGIN
+        INSERT INTO cache_fts (cache_fts, rowid, title, parsed_content)
+        VALUES ('delete', old.id, old.title, old.parsed_content);
+        INSERT INTO cache_fts (rowid, title, parsed_content)
+        VALUES (new.id, new.title, new.parsed_content);
+    END;
+    INSERT INTO cache_fts (cache_fts) VALUES ('rebuild');",
+    // Hosts banned from the command line, on top of the built-in list
+    "CREATE TABLE banned_hosts (
+        host TEXT PRIMARY KEY,
+        banned_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );",
+    // When a link was first cached, which unlike fetched_at survives refreshes
+    "ALTER TABLE cache ADD COLUMN added_at DATETIME;
+    UPDATE cache SET added_at = fetched_at;",
+    // Most similar cached articles to each cached article, rebuilt by `related`
+    "CREATE TABLE related (
+        url TEXT NOT NULL,
+        related_url TEXT NOT NULL,
+        score REAL NOT NULL,
+        PRIMARY KEY (url, related_url)
+    );",
+    // SimHash of each article's text, and duplicate URLs merged into a canonical one
+    "ALTER TABLE cache ADD COLUMN simhash INTEGER;
+    CREATE TABLE aliases (
+        url TEXT PRIMARY KEY,
+        canonical_url TEXT NOT NULL,
+        merged_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    );",
+    // Extractive summary of each article; empty when the text has no usable sentences
+    "ALTER TABLE cache ADD COLUMN summary TEXT;",
+    // Whether each article's current text is in the related index
+    "ALTER TABLE cache ADD COLUMN related_indexed INTEGER;",
+];
+
+/// Leaves out cached rows of duplicate URLs merged into a canonical link.
+const NOT_ALIAS_SQL: &str = "cache.url NOT IN (SELECT url FROM aliases)";
+
+/// A link's publication date, or the day it was fetched when unknown, as YYYY-MM-DD.
+const DATE_SQL: &str = "COALESCE(substr(cache.published_at, 1, 10), date(cache.fetched_at))";
+
+/// Adds the SQL conditions and parameters restricting a query of `cache` to `filters`.
+fn filter_conditions<'a>(
+    filters: &'a SearchFilters,
+    conditions: &mut Vec<String>,
+    params: &mut Vec<(String, &'a dyn rusqlite::ToSql)>,
+) {
+    // Duplicates merged into a canonical link are only kept to recognize re-imports
+    conditions.push(NOT_ALIAS_SQL.to_string());
+    if let Some(source) = &filters.source {
+        conditions.push("cache.source = :source".to_string());
+        params.push((":source".to_string(), source));
+    }
+    for (i, tag) in filters.tags.iter().enumerate() {
+        conditions.push(format!(
+            "EXISTS (SELECT 1 FROM json_each(cache.tags) WHERE value = :tag{i})"
+        ));
+        params.push((format!(":tag{i}"), tag));
+    }
+    if let Some(since) = &filters.since {
+        conditions.push("date >= :since".to_string());
+        params.push((":since".to_string(), since));
+    }
+    if let Some(until) = &filters.until {
+        conditions.push("date <= :until".to_string());
+        params.push((":until".to_string(), until));
+    }
+}
+
+fn link_summary(row: &rusqlite::Row) -> anyhow::Result<LinkSummary> {
+    let tags_sql: String = row.get(4)?;
+    Ok(LinkSummary {
+        id: row.get(0)?,
+        url: row.get(1)?,
+        title: row.get(2)?,
+        source: row.get(3)?,
+        tags: serde_json::from_str(&tags_sql)?,
+        date: row.get(5)?,
+    })
+}
+
+/// Failed fetches are retried after RETRY_BASE_SECONDS, doubling with each
+/// attempt, until MAX_FETCH_ATTEMPTS is reached and the link is considered dead.
+pub const MAX_FETCH_ATTEMPTS: u32 = 6;
+const RETRY_BASE_SECONDS: u64 = 6 * 60 * 60;
+
+fn migrate(conn: &Connection) -> anyhow::Result<()> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .context("Failed to read schema version")?;
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        conn.execute_batch(&format!(
+            "BEGIN; {migration} PRAGMA user_version = {}; COMMIT;",
+            i + 1
+        ))
+        .with_context(|| format!("Failed to apply migration {}", i + 1))?;
+    }
+
+    Ok(())
+}
+
 impl Cache {
     pub fn new(cache_type: CacheType) -> anyhow::Result<Self> {
         let conn = match cache_type {
@@ -36,9 +242,20 @@ impl Cache {
         )
         .context("Failed to create table")?;
 
+        migrate(&conn)?;
+
         Ok(Cache { conn })
     }
 
+    /// Runs `f` in one transaction, so either all of its writes are stored or,
+    /// if it fails or the process stops midway, none are.
+    pub fn atomically<T>(&self, f: impl FnOnce() -> anyhow::Result<T>) -> anyhow::Result<T> {
+        let tx = self.conn.unchecked_transaction()?;
+        let result = f()?;
+        tx.commit()?;
+        Ok(result)
+    }
+
     pub fn query(&self, url: &str) -> anyhow::Result<Option<CachedLink>> {
         let mut stmt = self
             .conn
@@ -83,14 +300,785 @@ impl Cache {
         Ok(links)
     }
 
-    pub fn query_unarchived(&self) -> anyhow::Result<Vec<CachedLink>> {
+    pub fn query_all_urls(&self) -> anyhow::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT url FROM cache UNION SELECT final_url FROM cache WHERE final_url IS NOT NULL")
+            .context("Failed to prepare query for all URLs")?;
+
+        let urls = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to query all URLs")?
+            .collect::<Result<HashSet<String>, _>>()?;
+
+        Ok(urls)
+    }
+
+    /// Maps each cached URL, and each final URL a cached link redirected to,
+    /// to the cached URL that owns it.
+    pub fn query_url_owners(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT url, final_url FROM cache")
+            .context("Failed to prepare query for URL owners")?;
+
+        let mut owners = HashMap::new();
+        let mut cached = Vec::new();
+        let mut rows = stmt.query([]).context("Failed to query URL owners")?;
+        while let Some(row) = rows.next()? {
+            let url: String = row.get(0)?;
+            if let Some(final_url) = row.get::<_, Option<String>>(1)? {
+                owners.insert(final_url, url.clone());
+            }
+            cached.push(url);
+        }
+        // A cached row for a URL owns it even if another link redirected there
+        for url in cached {
+            owners.insert(url.clone(), url);
+        }
+        Ok(owners)
+    }
+
+    pub fn insert_annotation(&self, annotation: &Annotation) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO annotations (id, url, quote, note, tags, selector, position, created_at) VALUES (:id, :url, :quote, :note, :tags, :selector, :position, :created_at)",
+            named_params![
+                ":id": annotation.id,
+                ":url": annotation.url,
+                ":quote": annotation.quote,
+                ":note": annotation.note,
+                ":tags": serde_json::to_string(&annotation.tags)?,
+                ":selector": annotation.selector.to_string(),
+                ":position": annotation.position,
+                ":created_at": annotation.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Annotations on `url` in document order, falling back to creation order.
+    pub fn query_annotations(&self, url: &str) -> anyhow::Result<Vec<Annotation>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, url, quote, note, tags, selector, position, created_at FROM annotations
+                WHERE url = :url
+                ORDER BY position IS NULL, position, created_at",
+            )
+            .with_context(|| format!("Failed to prepare query for annotations on {url}"))?;
+
+        let mut annotations = Vec::new();
+        let mut rows = stmt
+            .query(named_params![":url": url])
+            .with_context(|| format!("Failed to query annotations on {url}"))?;
+        while let Some(row) = rows.next()? {
+            let tags_sql: String = row.get(4)?;
+            let selector_sql: String = row.get(5)?;
+            annotations.push(Annotation {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                quote: row.get(2)?,
+                note: row.get(3)?,
+                tags: serde_json::from_str(&tags_sql)?,
+                selector: serde_json::from_str(&selector_sql)?,
+                position: row.get(6)?,
+                created_at: row.get(7)?,
+            });
+        }
+        Ok(annotations)
+    }
+
+    pub fn query_annotated_urls(&self) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT url FROM annotations ORDER BY url")
+            .context("Failed to prepare query for annotated URLs")?;
+
+        let urls = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to query annotated URLs")?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(urls)
+    }
+
+    pub fn record_fetch_failure(&self, url: &str, error: &FetchError) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO fetch_attempts (url, status_code, error_class, message, attempts, last_attempt_at, next_retry_at)
+            VALUES (:url, :status_code, :error_class, :message, 1, datetime('now'), datetime('now', '+' || :base || ' seconds'))
+            ON CONFLICT(url) DO UPDATE SET
+                status_code = excluded.status_code,
+                error_class = excluded.error_class,
+                message = excluded.message,
+                attempts = attempts + 1,
+                last_attempt_at = excluded.last_attempt_at,
+                next_retry_at = datetime('now', '+' || (:base << min(attempts, 10)) || ' seconds')",
+            named_params![
+                ":url": url,
+                ":status_code": error.status_code,
+                ":error_class": error.class.as_str(),
+                ":message": error.message,
+                ":base": RETRY_BASE_SECONDS,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_fetch_failure(&self, url: &str) -> anyhow::Result<()> {
+        self.conn.execute(
+            "DELETE FROM fetch_attempts WHERE url = :url",
+            named_params![":url": url],
+        )?;
+        Ok(())
+    }
+
+    /// URLs that should not be fetched yet: still backing off, or failed too often.
+    pub fn query_backoff_urls(&self) -> anyhow::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url FROM fetch_attempts
+                WHERE next_retry_at > datetime('now')
+                    OR attempts >= :max_attempts
+                    OR error_class IN ('banned', 'unsupported')",
+            )
+            .context("Failed to prepare query for backed-off URLs")?;
+
+        let urls = stmt
+            .query_map(named_params![":max_attempts": MAX_FETCH_ATTEMPTS], |row| {
+                row.get(0)
+            })
+            .context("Failed to query backed-off URLs")?
+            .collect::<Result<HashSet<String>, _>>()?;
+
+        Ok(urls)
+    }
+
+    pub fn ban_host(&self, host: &str) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO banned_hosts (host) VALUES (:host)",
+            named_params![":host": host],
+        )?;
+        Ok(())
+    }
+
+    pub fn query_banned_hosts(&self) -> anyhow::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT host FROM banned_hosts")
+            .context("Failed to prepare query for banned hosts")?;
+
+        let hosts = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to query banned hosts")?
+            .collect::<Result<HashSet<String>, _>>()?;
+
+        Ok(hosts)
+    }
+
+    pub fn update_tags(&self, url: &str, tags: &[String]) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE cache SET tags = :tags WHERE url = :url",
+            named_params![":url": url, ":tags": serde_json::to_string(tags)?],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the readable HTML and metadata of a cached link.
+    pub fn record_page_details(
+        &self,
+        url: &str,
+        content_html: &str,
+        metadata: &PageMetadata,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE cache SET
+                content_html = :content_html,
+                author = :author,
+                published_at = :published_at,
+                site_name = :site_name,
+                language = :language,
+                lead_image = :lead_image,
+                description = :description,
+                word_count = :word_count,
+                content_type = :content_type
+            WHERE url = :url",
+            named_params![
+                ":url": url,
+                ":content_html": content_html,
+                ":author": metadata.author,
+                ":published_at": metadata.published_at,
+                ":site_name": metadata.site_name,
+                ":language": metadata.language,
+                ":lead_image": metadata.lead_image,
+                ":description": metadata.description,
+                ":word_count": metadata.word_count,
+                ":content_type": metadata.content_type,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stores a link's summary, or an empty one when its text could not be summarized.
+    pub fn record_summary(&self, url: &str, summary: Option<&str>) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE cache SET summary = :summary WHERE url = :url",
+            named_params![":url": url, ":summary": summary.unwrap_or_default()],
+        )?;
+        Ok(())
+    }
+
+    /// The stored summary of `url`, or `None` if it has not been summarized.
+    pub fn query_summary(&self, url: &str) -> anyhow::Result<Option<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT summary FROM cache WHERE url = :url AND summary IS NOT NULL")
+            .context("Failed to prepare query for summary")?;
+
+        let mut rows = stmt
+            .query(named_params![":url": url])
+            .with_context(|| format!("Failed to query summary of {url}"))?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get(0)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn query_page_metadata(&self, url: &str) -> anyhow::Result<Option<PageMetadata>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT url, title, source, tags, parsed_content FROM cache WHERE archived_at IS NULL")
-            .context("Failed to prepare query looking for unarchived links")?;
+            .prepare(
+                "SELECT author, published_at, site_name, language, lead_image, description, word_count, content_type
+                FROM cache WHERE url = :url",
+            )
+            .context("Failed to prepare query for page metadata")?;
+
+        let mut rows = stmt
+            .query(named_params![":url": url])
+            .with_context(|| format!("Failed to query page metadata for {url}"))?;
+
+        if let Some(row) = rows.next()? {
+            return Ok(Some(PageMetadata {
+                author: row.get(0)?,
+                published_at: row.get(1)?,
+                site_name: row.get(2)?,
+                language: row.get(3)?,
+                lead_image: row.get(4)?,
+                description: row.get(5)?,
+                word_count: row.get(6)?,
+                content_type: row.get(7)?,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Marks `url` as fetched now, with the validators and hash of what was fetched.
+    pub fn record_validators(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+        content_hash: &str,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE cache SET
+                etag = :etag,
+                last_modified = :last_modified,
+                content_hash = :content_hash,
+                fetched_at = datetime('now')
+            WHERE url = :url",
+            named_params![
+                ":url": url,
+                ":etag": etag,
+                ":last_modified": last_modified,
+                ":content_hash": content_hash,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Cached links last fetched more than `max_age_days` ago, or never timestamped.
+    pub fn query_stale_links(&self, max_age_days: u32) -> anyhow::Result<Vec<StaleLink>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, parsed_content, etag, last_modified, content_hash FROM cache
+                WHERE fetched_at IS NULL OR fetched_at < datetime('now', '-' || :days || ' days')
+                ORDER BY fetched_at",
+            )
+            .context("Failed to prepare query for stale links")?;
 
         let mut links = Vec::new();
-        let mut rows = stmt.query([]).context("Failed to query for all links")?;
+        let mut rows = stmt
+            .query(named_params![":days": max_age_days])
+            .context("Failed to query stale links")?;
+        while let Some(row) = rows.next()? {
+            links.push(StaleLink {
+                url: row.get(0)?,
+                text_content: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                etag: row.get(2)?,
+                last_modified: row.get(3)?,
+                content_hash: row.get(4)?,
+            });
+        }
+        Ok(links)
+    }
+
+    /// Moves the current content of `url` into its history and stores the new version.
+    pub fn replace_content(
+        &self,
+        url: &str,
+        title: &str,
+        text_content: &str,
+    ) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO content_history (url, title, parsed_content, content_hash, fetched_at)
+            SELECT url, title, parsed_content, content_hash, fetched_at FROM cache WHERE url = :url",
+            named_params![":url": url],
+        )?;
+        self.conn.execute(
+            "UPDATE cache SET title = :title, parsed_content = :parsed_content,
+                simhash = NULL, summary = NULL, related_indexed = NULL
+            WHERE url = :url",
+            named_params![
+                ":url": url,
+                ":title": title,
+                ":parsed_content": text_content,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_snapshot(&self, snapshot: &Snapshot) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO snapshots (url, format, path, warc_offset)
+            VALUES (:url, :format, :path, :warc_offset)",
+            named_params![
+                ":url": snapshot.url,
+                ":format": snapshot.format,
+                ":path": snapshot.path,
+                ":warc_offset": snapshot.warc_offset,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// URLs with at least one local snapshot.
+    pub fn query_snapshotted_urls(&self) -> anyhow::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT url FROM snapshots")
+            .context("Failed to prepare query for snapshotted URLs")?;
+
+        let urls = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to query snapshotted URLs")?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(urls)
+    }
+
+    /// URLs for which a link check found a Wayback Machine copy.
+    pub fn query_wayback_urls(&self) -> anyhow::Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT url FROM link_checks WHERE wayback_url IS NOT NULL")
+            .context("Failed to prepare query for Wayback URLs")?;
+
+        let urls = stmt
+            .query_map([], |row| row.get(0))
+            .context("Failed to query Wayback URLs")?
+            .collect::<Result<HashSet<String>, _>>()?;
+        Ok(urls)
+    }
+
+    pub fn query_latest_snapshot(&self, url: &str) -> anyhow::Result<Option<Snapshot>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, format, path, warc_offset, captured_at FROM snapshots
+                WHERE url = :url
+                ORDER BY captured_at DESC, id DESC
+                LIMIT 1",
+            )
+            .context("Failed to prepare query for snapshots")?;
+
+        let mut rows = stmt
+            .query(named_params![":url": url])
+            .with_context(|| format!("Failed to query snapshots of {url}"))?;
+
+        if let Some(row) = rows.next()? {
+            return Ok(Some(Snapshot {
+                url: row.get(0)?,
+                format: row.get(1)?,
+                path: row.get(2)?,
+                warc_offset: row.get(3)?,
+                captured_at: row.get(4)?,
+            }));
+        }
+        Ok(None)
+    }
+
+    /// Stores the redirect chain of a cached link, from `url` through to its final URL.
+    pub fn record_redirects(&self, url: &str, chain: &[String]) -> anyhow::Result<()> {
+        let Some(final_url) = chain.last() else {
+            return Ok(());
+        };
+
+        self.conn.execute(
+            "DELETE FROM redirects WHERE url = :url",
+            named_params![":url": url],
+        )?;
+        for (hop, location) in chain.iter().enumerate() {
+            self.conn.execute(
+                "INSERT INTO redirects (url, hop, location) VALUES (:url, :hop, :location)",
+                named_params![":url": url, ":hop": hop, ":location": location],
+            )?;
+        }
+        self.conn.execute(
+            "UPDATE cache SET final_url = :final_url WHERE url = :url",
+            named_params![":url": url, ":final_url": final_url],
+        )?;
+        Ok(())
+    }
+
+    pub fn query_redirect_chain(&self, url: &str) -> anyhow::Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT location FROM redirects WHERE url = :url ORDER BY hop")
+            .context("Failed to prepare query for redirect chain")?;
+
+        let chain = stmt
+            .query_map(named_params![":url": url], |row| row.get(0))
+            .context("Failed to query redirect chain")?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        Ok(chain)
+    }
+
+    /// Maps each cached URL that redirected to its final URL.
+    pub fn query_final_urls(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, final_url FROM cache WHERE final_url IS NOT NULL AND final_url != url",
+            )
+            .context("Failed to prepare query for final URLs")?;
+
+        let mut rows = stmt.query([]).context("Failed to query final URLs")?;
+        let mut final_urls = HashMap::new();
+        while let Some(row) = rows.next()? {
+            final_urls.insert(row.get(0)?, row.get(1)?);
+        }
+
+        Ok(final_urls)
+    }
+
+    pub fn query_fetch_failures(&self, min_attempts: u32) -> anyhow::Result<Vec<FetchFailure>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, status_code, error_class, message, attempts, last_attempt_at, next_retry_at
+                FROM fetch_attempts
+                WHERE attempts >= :min_attempts
+                ORDER BY attempts DESC, url",
+            )
+            .context("Failed to prepare query for fetch failures")?;
+
+        let mut failures = Vec::new();
+        let mut rows = stmt
+            .query(named_params![":min_attempts": min_attempts])
+            .context("Failed to query fetch failures")?;
+        while let Some(row) = rows.next()? {
+            failures.push(FetchFailure {
+                url: row.get(0)?,
+                status_code: row.get(1)?,
+                error_class: row.get(2)?,
+                message: row.get(3)?,
+                attempts: row.get(4)?,
+                last_attempt_at: row.get(5)?,
+                next_retry_at: row.get(6)?,
+            });
+        }
+        Ok(failures)
+    }
+
+    pub fn insert_link_check(&self, check: &LinkCheck) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT INTO link_checks (url, status, status_code, final_url, message, wayback_url) VALUES (:url, :status, :status_code, :final_url, :message, :wayback_url)",
+            named_params![
+                ":url": check.url,
+                ":status": check.status,
+                ":status_code": check.status_code,
+                ":final_url": check.final_url,
+                ":message": check.message,
+                ":wayback_url": check.wayback_url,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// The most recent check of every checked URL.
+    pub fn query_latest_link_checks(&self) -> anyhow::Result<Vec<LinkCheck>> {
+        self.query_link_checks(
+            "SELECT url, status, status_code, final_url, message, wayback_url, checked_at
+            FROM link_checks
+            WHERE id IN (SELECT max(id) FROM link_checks GROUP BY url)
+            ORDER BY url",
+            named_params![],
+        )
+    }
+
+    pub fn query_link_check_history(&self, url: &str) -> anyhow::Result<Vec<LinkCheck>> {
+        self.query_link_checks(
+            "SELECT url, status, status_code, final_url, message, wayback_url, checked_at
+            FROM link_checks
+            WHERE url = :url
+            ORDER BY id",
+            named_params![":url": url],
+        )
+    }
+
+    /// Runs an FTS5 `query` over titles and text, best matches first. Matches
+    /// in snippets are wrapped in `highlight`.
+    pub fn search(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        highlight: (&str, &str),
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let mut conditions = vec!["cache_fts MATCH :query".to_string()];
+        let mut params: Vec<(String, &dyn rusqlite::ToSql)> = vec![
+            (":query".to_string(), &query),
+            (":open".to_string(), &highlight.0),
+            (":close".to_string(), &highlight.1),
+            (":limit".to_string(), &filters.limit),
+        ];
+        filter_conditions(filters, &mut conditions, &mut params);
+
+        // Title matches count for more than matches in the body
+        let sql = format!(
+            "SELECT cache.id, cache.url, cache.title, cache.source, cache.tags, {DATE_SQL} AS date,
+                snippet(cache_fts, 1, :open, :close, '…', 16),
+                bm25(cache_fts, 10.0, 1.0) AS rank
+            FROM cache_fts JOIN cache ON cache.id = cache_fts.rowid
+            WHERE {}
+            ORDER BY rank
+            LIMIT :limit",
+            conditions.join(" AND ")
+        );
+        let mut stmt = self
+            .conn
+            .prepare(&sql)
+            .context("Failed to prepare search query")?;
+
+        let params: Vec<(&str, &dyn rusqlite::ToSql)> = params
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        let mut rows = stmt
+            .query(params.as_slice())
+            .with_context(|| format!("Failed to search for {query}"))?;
+
+        let mut results = Vec::new();
+        while let Some(row) = rows.next()? {
+            let tags_sql: String = row.get(4)?;
+            results.push(SearchResult {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                title: row.get(2)?,
+                source: row.get(3)?,
+                tags: serde_json::from_str(&tags_sql)?,
+                date: row.get(5)?,
+                snippet: row.get(6)?,
+                rank: row.get(7)?,
+            });
+        }
+        Ok(results)
+    }
+
+    /// Cached links matching `filters`, most recently added first.
+    pub fn query_links(
+        &self,
+        filters: &SearchFilters,
+        offset: usize,
+    ) -> anyhow::Result<Vec<LinkSummary>> {
+        let mut conditions = vec!["1".to_string()];
+        let mut params: Vec<(String, &dyn rusqlite::ToSql)> = vec![
+            (":limit".to_string(), &filters.limit),
+            (":offset".to_string(), &offset),
+        ];
+        filter_conditions(filters, &mut conditions, &mut params);
+
+        let sql = format!(
+            "SELECT id, url, title, source, tags, {DATE_SQL} AS date FROM cache
+            WHERE {}
+            ORDER BY id DESC
+            LIMIT :limit OFFSET :offset",
+            conditions.join(" AND ")
+        );
+        let mut stmt = self
+            .conn
+            .prepare(&sql)
+            .context("Failed to prepare query for links")?;
+
+        let params: Vec<(&str, &dyn rusqlite::ToSql)> = params
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        let mut rows = stmt
+            .query(params.as_slice())
+            .context("Failed to query links")?;
+
+        let mut links = Vec::new();
+        while let Some(row) = rows.next()? {
+            links.push(link_summary(row)?);
+        }
+        Ok(links)
+    }
+
+    pub fn query_link_by_id(&self, id: i64) -> anyhow::Result<Option<LinkSummary>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT id, url, title, source, tags, {DATE_SQL} AS date FROM cache WHERE id = :id"
+            ))
+            .context("Failed to prepare query for link")?;
+
+        let mut rows = stmt
+            .query(named_params![":id": id])
+            .with_context(|| format!("Failed to query link {id}"))?;
+        match rows.next()? {
+            Some(row) => Ok(Some(link_summary(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The publication date, or failing that the fetch date, of every cached link.
+    pub fn query_dates(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT url, {DATE_SQL} AS date FROM cache WHERE date IS NOT NULL"
+            ))
+            .context("Failed to prepare query for link dates")?;
+
+        let mut dates = HashMap::new();
+        let mut rows = stmt.query([]).context("Failed to query link dates")?;
+        while let Some(row) = rows.next()? {
+            dates.insert(row.get(0)?, row.get(1)?);
+        }
+        Ok(dates)
+    }
+
+    /// When each cached link was first cached, in SQLite's `YYYY-MM-DD HH:MM:SS` UTC format.
+    pub fn query_added_dates(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT url, added_at FROM cache WHERE added_at IS NOT NULL")
+            .context("Failed to prepare query for added dates")?;
+
+        let mut dates = HashMap::new();
+        let mut rows = stmt.query([]).context("Failed to query added dates")?;
+        while let Some(row) = rows.next()? {
+            dates.insert(row.get(0)?, row.get(1)?);
+        }
+        Ok(dates)
+    }
+
+    /// Every tag on a cached link with the number of links carrying it, most used first.
+    pub fn query_tag_counts(&self) -> anyhow::Result<Vec<(String, usize)>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT tag.value, COUNT(*) AS links FROM cache, json_each(cache.tags) AS tag
+                WHERE {NOT_ALIAS_SQL}
+                GROUP BY tag.value
+                ORDER BY links DESC, tag.value"
+            ))
+            .context("Failed to prepare query for tags")?;
+
+        let mut tags = Vec::new();
+        let mut rows = stmt.query([]).context("Failed to query tags")?;
+        while let Some(row) = rows.next()? {
+            tags.push((row.get(0)?, row.get(1)?));
+        }
+        Ok(tags)
+    }
+
+    /// Replaces the whole related-links index with `pairs` of (url, related url, score).
+    pub fn replace_related(&self, pairs: &[(String, String, f64)]) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM related", [])?;
+        tx.execute("UPDATE cache SET related_indexed = 1", [])?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO related (url, related_url, score) VALUES (:url, :related_url, :score)",
+            )?;
+            for (url, related_url, score) in pairs {
+                stmt.execute(named_params![
+                    ":url": url,
+                    ":related_url": related_url,
+                    ":score": score,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The indexed links most similar to `url`, best first.
+    pub fn query_related(&self, url: &str, limit: usize) -> anyhow::Result<Vec<RelatedLink>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT related.related_url, cache.title, related.score
+                FROM related JOIN cache ON cache.url = related.related_url
+                WHERE related.url = :url
+                ORDER BY related.score DESC, related.related_url
+                LIMIT :limit",
+            )
+            .context("Failed to prepare query for related links")?;
+
+        let mut related = Vec::new();
+        let mut rows = stmt
+            .query(named_params![":url": url, ":limit": limit as i64])
+            .context("Failed to query related links")?;
+        while let Some(row) = rows.next()? {
+            related.push(RelatedLink {
+                url: row.get(0)?,
+                title: row.get(1)?,
+                score: row.get(2)?,
+            });
+        }
+        Ok(related)
+    }
+
+    /// Cached articles added or changed since the related index was built.
+    pub fn count_unindexed_related(&self) -> anyhow::Result<usize> {
+        let count: i64 = self
+            .conn
+            .query_row(
+                "SELECT COUNT(*) FROM cache WHERE related_indexed IS NULL",
+                [],
+                |row| row.get(0),
+            )
+            .context("Failed to count articles missing from the related index")?;
+        Ok(count as usize)
+    }
+
+    /// Cached links whose text has not been fingerprinted since it last changed.
+    pub fn query_unfingerprinted(&self) -> anyhow::Result<Vec<CachedLink>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT url, title, source, tags, parsed_content FROM cache WHERE simhash IS NULL",
+            )
+            .context("Failed to prepare query for unfingerprinted links")?;
+
+        let mut links = Vec::new();
+        let mut rows = stmt
+            .query([])
+            .context("Failed to query unfingerprinted links")?;
         while let Some(row) = rows.next()? {
             let tags_sql: String = row.get(3)?;
             links.push(CachedLink::new(
@@ -104,24 +1092,146 @@ impl Cache {
         Ok(links)
     }
 
-    pub fn query_all_urls(&self) -> anyhow::Result<HashSet<String>> {
+    pub fn record_simhash(&self, url: &str, simhash: u64) -> anyhow::Result<()> {
+        self.conn.execute(
+            "UPDATE cache SET simhash = :simhash WHERE url = :url",
+            named_params![":url": url, ":simhash": simhash as i64],
+        )?;
+        Ok(())
+    }
+
+    /// The URL, title and SimHash of every fingerprinted link that has not been
+    /// merged into another. Links too short to fingerprint are stored as 0 and left out.
+    pub fn query_simhashes(&self) -> anyhow::Result<Vec<(String, String, u64)>> {
         let mut stmt = self
             .conn
-            .prepare("SELECT url FROM cache")
-            .context("Failed to prepare query for all URLs")?;
+            .prepare(&format!(
+                "SELECT url, title, simhash FROM cache
+                WHERE simhash IS NOT NULL AND simhash != 0 AND {NOT_ALIAS_SQL}
+                ORDER BY url"
+            ))
+            .context("Failed to prepare query for fingerprints")?;
 
-        let urls = stmt
-            .query_map([], |row| row.get(0))
-            .context("Failed to query all URLs")?
-            .collect::<Result<HashSet<String>, _>>()?;
+        let mut fingerprints = Vec::new();
+        let mut rows = stmt.query([]).context("Failed to query fingerprints")?;
+        while let Some(row) = rows.next()? {
+            let simhash: i64 = row.get(2)?;
+            fingerprints.push((row.get(0)?, row.get(1)?, simhash as u64));
+        }
+        Ok(fingerprints)
+    }
 
-        Ok(urls)
+    /// Moves everything stored for `url` to the `final_url` it redirected to,
+    /// and records `url` as an alias of it. If `final_url` is already cached,
+    /// its row is kept and the one for `url` dropped.
+    pub fn resolve_redirect(&self, url: &str, final_url: &str) -> anyhow::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let params = named_params![":url": url, ":final_url": final_url];
+        let final_cached: bool = tx.query_row(
+            "SELECT EXISTS (SELECT 1 FROM cache WHERE url = :final_url)",
+            named_params![":final_url": final_url],
+            |row| row.get(0),
+        )?;
+        if final_cached {
+            tx.execute(
+                "DELETE FROM cache WHERE url = :url",
+                named_params![":url": url],
+            )?;
+            tx.execute(
+                "DELETE FROM redirects WHERE url = :url",
+                named_params![":url": url],
+            )?;
+        } else {
+            tx.execute("UPDATE cache SET url = :final_url WHERE url = :url", params)?;
+            tx.execute(
+                "UPDATE redirects SET url = :final_url WHERE url = :url",
+                params,
+            )?;
+        }
+        for table in ["annotations", "link_checks", "snapshots", "content_history"] {
+            tx.execute(
+                &format!("UPDATE {table} SET url = :final_url WHERE url = :url"),
+                params,
+            )?;
+        }
+        // Rows keyed by URL stay with `final_url` when both have one
+        for (table, column) in [
+            ("fetch_attempts", "url"),
+            ("related", "url"),
+            ("related", "related_url"),
+        ] {
+            tx.execute(
+                &format!(
+                    "UPDATE OR IGNORE {table} SET {column} = :final_url WHERE {column} = :url"
+                ),
+                params,
+            )?;
+            tx.execute(
+                &format!("DELETE FROM {table} WHERE {column} = :url"),
+                named_params![":url": url],
+            )?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO aliases (url, canonical_url) VALUES (:url, :final_url)",
+            params,
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn record_alias(&self, url: &str, canonical_url: &str) -> anyhow::Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO aliases (url, canonical_url) VALUES (:url, :canonical_url)",
+            named_params![":url": url, ":canonical_url": canonical_url],
+        )?;
+        Ok(())
+    }
+
+    /// Each merged duplicate URL mapped to the canonical URL it was merged into.
+    pub fn query_aliases(&self) -> anyhow::Result<HashMap<String, String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT url, canonical_url FROM aliases")
+            .context("Failed to prepare query for aliases")?;
+
+        let mut aliases = HashMap::new();
+        let mut rows = stmt.query([]).context("Failed to query aliases")?;
+        while let Some(row) = rows.next()? {
+            aliases.insert(row.get(0)?, row.get(1)?);
+        }
+        Ok(aliases)
+    }
+
+    fn query_link_checks(
+        &self,
+        sql: &str,
+        params: &[(&str, &dyn rusqlite::ToSql)],
+    ) -> anyhow::Result<Vec<LinkCheck>> {
+        let mut stmt = self
+            .conn
+            .prepare(sql)
+            .context("Failed to prepare query for link checks")?;
+
+        let mut checks = Vec::new();
+        let mut rows = stmt.query(params).context("Failed to query link checks")?;
+        while let Some(row) = rows.next()? {
+            checks.push(LinkCheck {
+                url: row.get(0)?,
+                status: row.get(1)?,
+                status_code: row.get(2)?,
+                final_url: row.get(3)?,
+                message: row.get(4)?,
+                wayback_url: row.get(5)?,
+                checked_at: row.get(6)?,
+            });
+        }
+        Ok(checks)
     }
 
     pub fn insert(&self, link: &CachedLink) -> anyhow::Result<()> {
         let tags_sql = serde_json::to_string(&link.tags)?;
         self.conn.execute(
-            "INSERT INTO cache (url, title, source, tags, parsed_content, archived_at) VALUES (:url, :title, :source, :tags, :parsed_content, NULL)",
+            "INSERT INTO cache (url, title, source, tags, parsed_content, archived_at, added_at) VALUES (:url, :title, :source, :tags, :parsed_content, NULL, datetime('now'))",
             named_params![
                 ":url": link.url,
                 ":title": link.title,
@@ -136,7 +1246,7 @@ impl Cache {
 
 #[cfg(test)]
 mod tests {
-    use crate::models::LinkSource;
+    use crate::models::{ErrorClass, LinkSource, LinkStatus};
 
     use super::*;
 
@@ -157,6 +1267,46 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_insert_and_query_zotero_source() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let link = CachedLink {
+            url: "https://doi.org/10.1000/xyz".to_string(),
+            title: "A Paper".to_string(),
+            source: LinkSource::Zotero,
+            tags: vec!["Computing".to_string()],
+            text_content: "Empty".to_string(),
+        };
+        cache.insert(&link)?;
+        let query_result = cache.query(&link.url)?.unwrap();
+        assert_eq!(link, query_result);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomically_rolls_back_on_error() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let link = CachedLink::new(
+            "https://example.com".to_string(),
+            "Example".to_string(),
+            LinkSource::Manual,
+            Vec::new(),
+            "Text".to_string(),
+        );
+        let result: anyhow::Result<()> = cache.atomically(|| {
+            cache.insert(&link)?;
+            anyhow::bail!("Interrupted")
+        });
+        assert!(result.is_err());
+        assert!(cache.query(&link.url)?.is_none());
+
+        cache.atomically(|| cache.insert(&link))?;
+        assert!(cache.query(&link.url)?.is_some());
+
+        Ok(())
+    }
+
     #[test]
     fn test_query_empty() -> anyhow::Result<()> {
         let cache = Cache::new(CacheType::Memory)?;
@@ -193,4 +1343,390 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_insert_and_query_annotations_in_document_order() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink {
+            url: "https://example.com".to_string(),
+            title: "Example".to_string(),
+            source: LinkSource::GoodLinks,
+            tags: Vec::new(),
+            text_content: "Empty".to_string(),
+        })?;
+        let annotation = |id: &str, position: Option<i64>| Annotation {
+            id: id.to_string(),
+            url: "https://example.com".to_string(),
+            quote: Some(format!("quote {id}")),
+            note: None,
+            tags: Vec::new(),
+            selector: serde_json::Value::Array(Vec::new()),
+            position,
+            created_at: None,
+        };
+        cache.insert_annotation(&annotation("page-note", None))?;
+        cache.insert_annotation(&annotation("second", Some(200)))?;
+        cache.insert_annotation(&annotation("first", Some(10)))?;
+
+        let annotations = cache.query_annotations("https://example.com")?;
+        let ids: Vec<_> = annotations.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, vec!["first", "second", "page-note"]);
+        assert_eq!(annotations[0], annotation("first", Some(10)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_failures_back_off_and_clear() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let error = FetchError {
+            status_code: Some(404),
+            class: ErrorClass::Http,
+            message: "http status: 404".to_string(),
+        };
+        cache.record_fetch_failure("https://example.com/gone", &error)?;
+        cache.record_fetch_failure("https://example.com/gone", &error)?;
+
+        assert!(cache
+            .query_backoff_urls()?
+            .contains("https://example.com/gone"));
+        let failures = cache.query_fetch_failures(1)?;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].attempts, 2);
+        assert_eq!(failures[0].status_code, Some(404));
+        assert_eq!(failures[0].error_class, "http");
+        assert!(failures[0].next_retry_at > failures[0].last_attempt_at);
+        assert!(cache.query_fetch_failures(MAX_FETCH_ATTEMPTS)?.is_empty());
+
+        cache.clear_fetch_failure("https://example.com/gone")?;
+        assert!(cache.query_fetch_failures(1)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_check_history_and_latest() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let check = |url: &str, status: LinkStatus, status_code: Option<u16>| LinkCheck {
+            url: url.to_string(),
+            status,
+            status_code,
+            final_url: None,
+            message: None,
+            wayback_url: None,
+            checked_at: None,
+        };
+        cache.insert_link_check(&check("https://a.example.com", LinkStatus::Ok, Some(200)))?;
+        cache.insert_link_check(&check("https://a.example.com", LinkStatus::Gone, Some(404)))?;
+        cache.insert_link_check(&check("https://b.example.com", LinkStatus::Dns, None))?;
+
+        let latest = cache.query_latest_link_checks()?;
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].status, LinkStatus::Gone);
+        assert_eq!(latest[1].status, LinkStatus::Dns);
+
+        let history = cache.query_link_check_history("https://a.example.com")?;
+        let statuses: Vec<_> = history.iter().map(|c| c.status).collect();
+        assert_eq!(statuses, vec![LinkStatus::Ok, LinkStatus::Gone]);
+        assert!(history[0].checked_at.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_redirects_record_final_url() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://short.example/a".to_string(),
+            "Post".to_string(),
+            LinkSource::GoodLinks,
+            Vec::new(),
+            "Text".to_string(),
+        ))?;
+        let chain = vec![
+            "https://short.example/a".to_string(),
+            "http://blog.example.org/post".to_string(),
+            "https://blog.example.org/post".to_string(),
+        ];
+
+        cache.record_redirects("https://short.example/a", &chain)?;
+
+        assert_eq!(
+            cache.query_redirect_chain("https://short.example/a")?,
+            chain
+        );
+        assert_eq!(
+            cache.query_final_urls()?.get("https://short.example/a"),
+            Some(&"https://blog.example.org/post".to_string())
+        );
+        assert!(cache
+            .query_all_urls()?
+            .contains("https://blog.example.org/post"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_url_owners_maps_final_urls() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        for url in ["https://short.example/a", "https://example.org/b"] {
+            cache.insert(&CachedLink::new(
+                url.to_string(),
+                "Post".to_string(),
+                LinkSource::GoodLinks,
+                Vec::new(),
+                "Text".to_string(),
+            ))?;
+        }
+        cache.record_redirects(
+            "https://short.example/a",
+            &[
+                "https://short.example/a".to_string(),
+                "https://blog.example.org/post".to_string(),
+            ],
+        )?;
+
+        let owners = cache.query_url_owners()?;
+        assert_eq!(
+            owners
+                .get("https://blog.example.org/post")
+                .map(String::as_str),
+            Some("https://short.example/a")
+        );
+        assert_eq!(
+            owners.get("https://example.org/b").map(String::as_str),
+            Some("https://example.org/b")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliases_are_not_fingerprinted_or_listed() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        for url in ["https://example.org/story", "https://example.org/amp/story"] {
+            cache.insert(&CachedLink::new(
+                url.to_string(),
+                "Story".to_string(),
+                LinkSource::Manual,
+                vec!["news".to_string()],
+                "Text".to_string(),
+            ))?;
+            cache.record_simhash(url, 1)?;
+        }
+        cache.record_alias("https://example.org/amp/story", "https://example.org/story")?;
+
+        let fingerprinted = cache.query_simhashes()?;
+        assert_eq!(fingerprinted.len(), 1);
+        assert_eq!(fingerprinted[0].0, "https://example.org/story");
+        let filters = SearchFilters {
+            limit: 10,
+            ..Default::default()
+        };
+        let listed = cache.query_links(&filters, 0)?;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(cache.query_tag_counts()?, [("news".to_string(), 1)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_redirect_moves_cached_row() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let url = "https://short.example/a";
+        let final_url = "https://blog.example.org/post";
+        cache.insert(&CachedLink::new(
+            url.to_string(),
+            "Post".to_string(),
+            LinkSource::GoodLinks,
+            Vec::new(),
+            "Text".to_string(),
+        ))?;
+        cache.record_redirects(url, &[url.to_string(), final_url.to_string()])?;
+        cache.record_summary(url, Some("Summary"))?;
+
+        cache.resolve_redirect(url, final_url)?;
+
+        assert!(cache.query(url)?.is_none());
+        let link = cache.query(final_url)?.unwrap();
+        assert_eq!(link.text_content, "Text");
+        assert_eq!(cache.query_summary(final_url)?.as_deref(), Some("Summary"));
+        assert!(cache.query_final_urls()?.is_empty());
+        assert_eq!(
+            cache.query_aliases()?.get(url).map(String::as_str),
+            Some(final_url)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_redirect_keeps_existing_final_row() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let url = "https://short.example/a";
+        let final_url = "https://blog.example.org/post";
+        for (url, text) in [(url, "Old"), (final_url, "Current")] {
+            cache.insert(&CachedLink::new(
+                url.to_string(),
+                "Post".to_string(),
+                LinkSource::GoodLinks,
+                Vec::new(),
+                text.to_string(),
+            ))?;
+        }
+
+        cache.resolve_redirect(url, final_url)?;
+
+        assert!(cache.query(url)?.is_none());
+        assert_eq!(cache.query(final_url)?.unwrap().text_content, "Current");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_details_round_trip() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://example.com/post".to_string(),
+            "Post".to_string(),
+            LinkSource::Manual,
+            Vec::new(),
+            "Some words here".to_string(),
+        ))?;
+        let metadata = PageMetadata {
+            author: Some("Ada Lovelace".to_string()),
+            site_name: Some("Example".to_string()),
+            word_count: Some(3),
+            ..Default::default()
+        };
+
+        cache.record_page_details(
+            "https://example.com/post",
+            "<p>Some words here</p>",
+            &metadata,
+        )?;
+
+        assert_eq!(
+            cache.query_page_metadata("https://example.com/post")?,
+            Some(metadata)
+        );
+        assert_eq!(
+            cache.query_page_metadata("https://example.com/other")?,
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_snapshot() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let snapshot = |path: &str, warc_offset| Snapshot {
+            url: "https://example.com/post".to_string(),
+            format: "warc".to_string(),
+            path: path.to_string(),
+            warc_offset,
+            captured_at: None,
+        };
+        cache.insert_snapshot(&snapshot("snapshots/2026-09.warc.gz", Some(0)))?;
+        cache.insert_snapshot(&snapshot("snapshots/2026-10.warc.gz", Some(1234)))?;
+
+        let latest = cache
+            .query_latest_snapshot("https://example.com/post")?
+            .unwrap();
+        assert_eq!(latest.path, "snapshots/2026-10.warc.gz");
+        assert_eq!(latest.warc_offset, Some(1234));
+        assert!(latest.captured_at.is_some());
+        assert!(cache
+            .query_latest_snapshot("https://example.com/other")?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_refresh_keeps_content_history() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let url = "https://example.com/post";
+        cache.insert(&CachedLink::new(
+            url.to_string(),
+            "Post".to_string(),
+            LinkSource::Manual,
+            Vec::new(),
+            "First draft".to_string(),
+        ))?;
+        assert_eq!(cache.query_stale_links(30)?.len(), 1);
+
+        cache.record_validators(url, Some("\"abc\""), None, "hash-1")?;
+        assert!(cache.query_stale_links(30)?.is_empty());
+
+        cache.replace_content(url, "Post (updated)", "Second draft")?;
+        let current = cache.query(url)?.unwrap();
+        assert_eq!(current.title, "Post (updated)");
+        assert_eq!(current.text_content, "Second draft");
+
+        let (previous, hash): (String, String) = cache.conn.query_row(
+            "SELECT parsed_content, content_hash FROM content_history WHERE url = :url",
+            named_params![":url": url],
+            |row| rusqlite::Result::Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        assert_eq!(previous, "First draft");
+        assert_eq!(hash, "hash-1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_ranks_and_filters() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let link = |url: &str, title: &str, source, tags: &[&str], text: &str| {
+            CachedLink::new(
+                url.to_string(),
+                title.to_string(),
+                source,
+                tags.iter().map(|t| t.to_string()).collect(),
+                text.to_string(),
+            )
+        };
+        cache.insert(&link(
+            "https://a.example.com",
+            "Error handling in Rust",
+            LinkSource::GoodLinks,
+            &["rust"],
+            "Using anyhow and thiserror for errors.",
+        ))?;
+        cache.insert(&link(
+            "https://b.example.com",
+            "Gardening notes",
+            LinkSource::Obsidian,
+            &["garden"],
+            "Tomatoes need handling with care. Rust spots on leaves.",
+        ))?;
+
+        let filters = SearchFilters {
+            limit: 10,
+            ..Default::default()
+        };
+        let results = cache.search("rust", &filters, ("[", "]"))?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].url, "https://a.example.com");
+        assert!(results[1].snippet.contains("[Rust]"));
+
+        let results = cache.search("\"error handling\"", &filters, ("[", "]"))?;
+        assert_eq!(results.len(), 1);
+
+        let tagged = SearchFilters {
+            tags: vec!["garden".to_string()],
+            ..filters
+        };
+        let results = cache.search("handl*", &tagged, ("[", "]"))?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].source, LinkSource::Obsidian);
+
+        // The index follows updates to the cache
+        cache.replace_content("https://b.example.com", "Gardening notes", "Only tomatoes")?;
+        assert!(cache.search("rust", &tagged, ("[", "]"))?.is_empty());
+
+        Ok(())
+    }
 }
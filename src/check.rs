@@ -0,0 +1,433 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
+use ureq::ResponseExt;
+
+use crate::cache::{Cache, CacheType};
+use crate::fetch::banned_hosts;
+use crate::links::{normalize_url, read_links};
+use crate::models::{LinkCheck, LinkStatus};
+use crate::output::{emit, progress, Report};
+use crate::scheduler::{host_of, run_politely, Politeness};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(20);
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; sync_bookmarks)";
+/// Only the start of a page is read, enough to find its <title>.
+const SNIFF_BYTES: u64 = 64 * 1024;
+const SOFT_404_TITLE_PATTERNS: &[&str] = &[
+    "not found",
+    "page cannot be found",
+    "no longer available",
+    "does not exist",
+    "doesn't exist",
+];
+/// A bare "404" is too often part of a name or number, like "Peugeot 404"
+static SOFT_404_CODE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b404\b\W{0,3}(?:error|page)\b|\b(?:error|page)\W{0,3}\b404\b").unwrap()
+});
+static TITLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+const WAYBACK_AVAILABLE_API: &str = "https://archive.org/wayback/available";
+const WAYBACK_DELAY: Duration = Duration::from_secs(1);
+
+pub struct CheckOptions {
+    pub politeness: Politeness,
+    /// Send HEAD requests, skipping the soft-404 check that needs the page body
+    pub head_only: bool,
+    /// Look up Wayback Machine snapshots for dead links
+    pub wayback: bool,
+    /// Print the stored results without checking anything
+    pub report_only: bool,
+    /// Print the status history of this URL and exit
+    pub history: Option<String>,
+    /// Include live links in the printed report
+    pub all: bool,
+    /// Write the report to this file as JSON (.json) or CSV (anything else)
+    pub output: Option<String>,
+}
+
+fn check_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(CHECK_TIMEOUT))
+        .user_agent(USER_AGENT)
+        .http_status_as_error(false)
+        .build()
+        .into()
+}
+
+fn extract_title(html: &str) -> Option<String> {
+    TITLE_REGEX
+        .captures(html)
+        .map(|captures| captures[1].trim().to_string())
+}
+
+fn is_soft_404_title(title: &str) -> bool {
+    let title = title.to_lowercase();
+    SOFT_404_TITLE_PATTERNS
+        .iter()
+        .any(|pattern| title.contains(pattern))
+        || SOFT_404_CODE_REGEX.is_match(&title)
+}
+
+/// Classifies an HTTP response. A page that answers 200 but is titled like an
+/// error page, or a deep link redirected to the site's front page, is a soft 404.
+fn classify_response(
+    url: &str,
+    status_code: u16,
+    final_url: &str,
+    title: Option<&str>,
+) -> LinkStatus {
+    match status_code {
+        404 | 410 => LinkStatus::Gone,
+        400..=499 => LinkStatus::ClientError,
+        500..=599 => LinkStatus::ServerError,
+        200..=299 => {
+            if title.is_some_and(is_soft_404_title) {
+                return LinkStatus::SoftNotFound;
+            }
+            if normalize_url(url) == normalize_url(final_url) {
+                return LinkStatus::Ok;
+            }
+            let path_of = |url: &str| url::Url::parse(url).map(|u| u.path().to_string()).ok();
+            match (path_of(url).as_deref(), path_of(final_url).as_deref()) {
+                (Some(original), Some("/")) if original != "/" => LinkStatus::SoftNotFound,
+                _ => LinkStatus::Redirect,
+            }
+        }
+        _ => LinkStatus::Other,
+    }
+}
+
+fn classify_error(error: &ureq::Error) -> LinkStatus {
+    match error {
+        ureq::Error::HostNotFound => LinkStatus::Dns,
+        ureq::Error::Timeout(_) => LinkStatus::Timeout,
+        ureq::Error::ConnectionFailed | ureq::Error::Io(_) => LinkStatus::ConnectionError,
+        _ => LinkStatus::Other,
+    }
+}
+
+fn check_url(agent: &ureq::Agent, url: &str, head_only: bool) -> LinkCheck {
+    let mut response = if head_only {
+        agent.head(url).call()
+    } else {
+        agent.get(url).call()
+    };
+    // Some servers refuse HEAD outright
+    if head_only && matches!(&response, Ok(r) if matches!(r.status().as_u16(), 405 | 501)) {
+        response = agent.get(url).call();
+    }
+
+    match response {
+        Err(e) => LinkCheck {
+            url: url.to_string(),
+            status: classify_error(&e),
+            status_code: None,
+            final_url: None,
+            message: Some(e.to_string()),
+            wayback_url: None,
+            checked_at: None,
+        },
+        Ok(mut response) => {
+            let status_code = response.status().as_u16();
+            let final_url = response.get_uri().to_string();
+            let is_html = response
+                .headers()
+                .get("content-type")
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("html"));
+
+            let title = if !head_only && is_html && response.status().is_success() {
+                // Lossy, as the cut-off may split a character
+                let mut head = Vec::new();
+                let _ = response
+                    .body_mut()
+                    .as_reader()
+                    .take(SNIFF_BYTES)
+                    .read_to_end(&mut head);
+                extract_title(&String::from_utf8_lossy(&head))
+            } else {
+                None
+            };
+
+            LinkCheck {
+                url: url.to_string(),
+                status: classify_response(url, status_code, &final_url, title.as_deref()),
+                status_code: Some(status_code),
+                final_url: (normalize_url(&final_url) != normalize_url(url)).then_some(final_url),
+                message: title,
+                wayback_url: None,
+                checked_at: None,
+            }
+        }
+    }
+}
+
+fn wayback_snapshot(agent: &ureq::Agent, url: &str) -> anyhow::Result<Option<String>> {
+    let response: serde_json::Value = agent
+        .get(WAYBACK_AVAILABLE_API)
+        .query("url", url)
+        .call()?
+        .body_mut()
+        .read_json()
+        .context("Failed to parse Wayback availability response")?;
+
+    let closest = &response["archived_snapshots"]["closest"];
+    if closest["available"].as_bool() != Some(true) {
+        return Ok(None);
+    }
+    Ok(closest["url"].as_str().map(str::to_string))
+}
+
+impl Report for LinkCheck {
+    const KIND: &'static str = "link_check";
+
+    fn text(&self) -> String {
+        let code = self.status_code.map(|c| c.to_string()).unwrap_or_default();
+        let mut out = format!("{:<16} {code:>3} {}", self.status.as_str(), self.url);
+        if let Some(final_url) = &self.final_url {
+            out.push_str(&format!("\n{:<20} -> {final_url}", ""));
+        }
+        if let Some(wayback_url) = &self.wayback_url {
+            out.push_str(&format!("\n{:<20} archived at {wayback_url}", ""));
+        }
+        out
+    }
+}
+
+/// One past check of a URL, as listed by `check --history`.
+#[derive(serde::Serialize)]
+struct CheckHistoryEntry {
+    #[serde(flatten)]
+    check: LinkCheck,
+}
+
+impl Report for CheckHistoryEntry {
+    const KIND: &'static str = "link_check_history";
+
+    fn text(&self) -> String {
+        let check = &self.check;
+        let code = check.status_code.map(|c| c.to_string()).unwrap_or_default();
+        format!(
+            "{} {:<16} {code:>3} {}",
+            check.checked_at.as_deref().unwrap_or_default(),
+            check.status.as_str(),
+            check
+                .final_url
+                .as_deref()
+                .or(check.message.as_deref())
+                .unwrap_or_default()
+        )
+    }
+}
+
+#[derive(serde::Serialize)]
+struct CheckSummary {
+    checked: usize,
+    /// Links by the status of their latest check
+    by_status: BTreeMap<&'static str, usize>,
+}
+
+impl Report for CheckSummary {
+    const KIND: &'static str = "check_summary";
+
+    fn text(&self) -> String {
+        let mut out = format!("\nChecked {} links:", self.checked);
+        for (status, count) in &self.by_status {
+            out.push_str(&format!("\n  {status:<16} {count}"));
+        }
+        out
+    }
+}
+
+fn print_report(checks: &[LinkCheck], all: bool) -> anyhow::Result<()> {
+    let mut counts: BTreeMap<LinkStatus, usize> = BTreeMap::new();
+    for check in checks {
+        *counts.entry(check.status).or_default() += 1;
+    }
+
+    for check in checks.iter().filter(|c| all || c.status.is_dead()) {
+        emit(check)?;
+    }
+
+    emit(&CheckSummary {
+        checked: checks.len(),
+        by_status: counts
+            .into_iter()
+            .map(|(status, count)| (status.as_str(), count))
+            .collect(),
+    })
+}
+
+fn export_report(checks: &[LinkCheck], path: &str) -> anyhow::Result<()> {
+    let file = std::fs::File::create(path).with_context(|| format!("Failed to create {path}"))?;
+    if path.ends_with(".json") {
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), checks)
+            .with_context(|| format!("Failed to write {path}"))?;
+    } else {
+        let mut writer = csv::Writer::from_writer(file);
+        for check in checks {
+            writer.serialize(check)?;
+        }
+        writer.flush()?;
+    }
+    progress!("Wrote report to {path}");
+    Ok(())
+}
+
+fn print_history(cache: &Cache, url: &str) -> anyhow::Result<()> {
+    let history = cache.query_link_check_history(url)?;
+    if history.is_empty() {
+        progress!("{url} has never been checked");
+    }
+    for check in history {
+        emit(&CheckHistoryEntry { check })?;
+    }
+    Ok(())
+}
+
+pub fn check_links(options: &CheckOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+
+    if let Some(url) = &options.history {
+        return print_history(&cache, url);
+    }
+
+    if !options.report_only {
+        let banned = banned_hosts(&cache)?;
+        let urls: Vec<String> = read_links()?
+            .into_iter()
+            .map(|link| link.url)
+            .filter(|url| !banned.contains(&host_of(url)))
+            .collect();
+
+        progress!("Checking {} links", urls.len());
+
+        let pb = ProgressBar::new(urls.len().try_into()?);
+        pb.set_style(ProgressStyle::with_template(
+            "{bar:40} {pos}/{len} [{elapsed_precise}, {per_sec}, eta {eta}] {msg}",
+        )?);
+
+        let agent = check_agent();
+        let mut dead = Vec::new();
+        run_politely(
+            urls,
+            |url| url.as_str(),
+            &options.politeness,
+            |url| check_url(&agent, url, options.head_only),
+            |_, check| {
+                if check.status.is_dead() {
+                    dead.push(check);
+                } else {
+                    cache.insert_link_check(&check)?;
+                }
+                pb.set_message(format!("{} dead", dead.len()));
+                pb.inc(1);
+                Ok(())
+            },
+        )?;
+        pb.finish();
+
+        if options.wayback && !dead.is_empty() {
+            progress!(
+                "Looking up Wayback Machine snapshots for {} dead links",
+                dead.len()
+            );
+        }
+        for mut check in dead {
+            if options.wayback {
+                check.wayback_url = wayback_snapshot(&agent, &check.url).unwrap_or_else(|e| {
+                    progress!("Wayback lookup failed for {}: {e}", check.url);
+                    None
+                });
+                std::thread::sleep(WAYBACK_DELAY);
+            }
+            cache.insert_link_check(&check)?;
+        }
+    }
+
+    let checks = cache.query_latest_link_checks()?;
+    print_report(&checks, options.all)?;
+    if let Some(path) = &options.output {
+        export_report(&checks, path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_status_codes() {
+        let url = "https://example.com/post";
+        assert_eq!(
+            classify_response(url, 200, url, Some("A Post")),
+            LinkStatus::Ok
+        );
+        assert_eq!(classify_response(url, 404, url, None), LinkStatus::Gone);
+        assert_eq!(classify_response(url, 410, url, None), LinkStatus::Gone);
+        assert_eq!(
+            classify_response(url, 403, url, None),
+            LinkStatus::ClientError
+        );
+        assert_eq!(
+            classify_response(url, 503, url, None),
+            LinkStatus::ServerError
+        );
+    }
+
+    #[test]
+    fn test_classify_redirects() {
+        let url = "https://example.com/post";
+        assert_eq!(
+            classify_response(url, 200, "https://www.example.com/2020/post", None),
+            LinkStatus::Redirect
+        );
+        assert_eq!(
+            classify_response(url, 200, "https://example.com/post/?utm=1", None),
+            LinkStatus::Ok
+        );
+        assert_eq!(
+            classify_response(url, 200, "https://example.com/", None),
+            LinkStatus::SoftNotFound
+        );
+    }
+
+    #[test]
+    fn test_soft_404_titles() {
+        let url = "https://example.com/post";
+        assert_eq!(
+            classify_response(url, 200, url, Some("Page Not Found | Example")),
+            LinkStatus::SoftNotFound
+        );
+        for title in ["404 Error", "Error 404 | Example", "404 - Page Not Here"] {
+            assert_eq!(
+                classify_response(url, 200, url, Some(title)),
+                LinkStatus::SoftNotFound,
+                "{title}"
+            );
+        }
+        for title in [
+            "Peugeot 404 review",
+            "2404 Main Street",
+            "HTTP 404 explained",
+        ] {
+            assert_eq!(
+                classify_response(url, 200, url, Some(title)),
+                LinkStatus::Ok,
+                "{title}"
+            );
+        }
+        assert_eq!(
+            extract_title("<html><head><TITLE>\n Oops! 404 </TITLE></head>").as_deref(),
+            Some("Oops! 404")
+        );
+    }
+}
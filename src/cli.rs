@@ -1,8 +1,11 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
+    /// Print results as text, or as one JSON object per line for scripts
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -18,9 +21,347 @@ pub enum Commands {
         #[arg(long)]
         dry_run: bool,
     },
-    /// Import bookmarks from GoodLinks and Obsidian
+    /// Import bookmarks from GoodLinks, Obsidian and Zotero, plus any given social exports
     Import {
         #[arg(short, long)]
         verbose: bool,
+        /// Zotero data directory containing zotero.sqlite [default: ~/Zotero]
+        #[arg(long)]
+        zotero_dir: Option<String>,
+        /// Unpacked Mastodon account archive containing outbox.json, bookmarks.json and likes.json
+        #[arg(long)]
+        mastodon_archive: Option<String>,
+        /// Bluesky repo export (.car) downloaded from account settings
+        #[arg(long)]
+        bluesky_car: Option<String>,
+        /// GitHub user whose starred repositories to import (uses $GITHUB_TOKEN if set)
+        #[arg(long)]
+        github_user: Option<String>,
+        /// Previously saved starred-repos JSON to import instead of calling the API
+        #[arg(long)]
+        github_stars_json: Option<String>,
+        /// Base URL of the GitHub API, e.g. for a local mock
+        #[arg(long, default_value = "https://api.github.com")]
+        github_api_base: String,
+        /// Hypothesis annotation export (JSON) to attach to cached articles
+        #[arg(long)]
+        hypothesis_export: Option<String>,
+        #[command(flatten)]
+        politeness: PolitenessArgs,
+        /// Retry links whose previous fetches failed, ignoring their backoff
+        #[arg(long)]
+        retry_failed: bool,
+        /// Rewrite links.json entries that redirect to their final URL
+        #[arg(long)]
+        resolve_redirects: bool,
+        /// Also keep a local copy of each newly fetched page
+        #[arg(long, value_enum)]
+        snapshot: Option<SnapshotFormat>,
+        /// Directory local snapshots are written to
+        #[arg(long, default_value = "snapshots")]
+        snapshot_dir: String,
+        /// Tagging and routing rules applied to links.json before fetching;
+        /// rules with keywords run again once new links are cached
+        #[arg(long, default_value = "rules.json")]
+        rules: String,
+    },
+    /// Refetch cached pages that may have changed and report the ones that did
+    Refresh {
+        #[arg(short, long)]
+        verbose: bool,
+        #[command(flatten)]
+        politeness: PolitenessArgs,
+        /// Refetch pages last fetched more than this many days ago
+        #[arg(long, default_value_t = 30)]
+        max_age_days: u32,
+        /// Only report pages where at least this percentage of words changed
+        #[arg(long, default_value_t = 10.0)]
+        min_change_percent: f64,
+    },
+    /// Check links.json for dead links and report them
+    Check {
+        #[command(flatten)]
+        politeness: PolitenessArgs,
+        /// Only send HEAD requests; skips detecting error pages served with 200
+        #[arg(long)]
+        head_only: bool,
+        /// Don't look up Wayback Machine snapshots for dead links
+        #[arg(long)]
+        no_wayback: bool,
+        /// Print the results of the last check without checking again
+        #[arg(long)]
+        report_only: bool,
+        /// Print every recorded check of a single URL instead
+        #[arg(long, value_name = "URL")]
+        history: Option<String>,
+        /// Include live links in the report
+        #[arg(long)]
+        all: bool,
+        /// Also write the report to a .json or .csv file
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// List links that repeatedly failed to fetch
+    Failures {
+        /// Include links that are still being retried
+        #[arg(long)]
+        all: bool,
+    },
+    /// Add a link to links.json by hand
+    Add {
+        url: String,
+        /// Title to store; fetched from the page when omitted
+        #[arg(long)]
+        title: Option<String>,
+        /// Tag to apply; may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Remove a link from links.json
+    Remove { url: String },
+    /// Add or remove tags on a link in links.json
+    Tag {
+        url: String,
+        /// Tag to add; may be repeated
+        #[arg(long = "add")]
+        add: Vec<String>,
+        /// Tag to remove; may be repeated
+        #[arg(long = "remove")]
+        remove: Vec<String>,
+    },
+    /// Propose tags for untagged links from the keywords of their cached text
+    ///
+    /// Keywords are matched against a vocabulary of tags and synonyms, such as
+    /// {"machine-learning": ["neural network", "deep learning"]}. Keywords skip
+    /// words under three letters, so give tags like "ai" a longer synonym.
+    Autotag {
+        /// JSON file mapping each tag to the words and phrases that suggest it
+        #[arg(long, default_value = "tag-vocabulary.json")]
+        vocabulary: String,
+        /// Print the proposed tags without changing links.json
+        #[arg(long)]
+        dry_run: bool,
+        /// Also add tags to links that already have some
+        #[arg(long)]
+        all: bool,
+        /// Most tags to add to any one link
+        #[arg(long, default_value_t = 3)]
+        max_tags: usize,
+        /// Number of top keywords per article to match against the vocabulary
+        #[arg(long, default_value_t = 20)]
+        keywords: usize,
+    },
+    /// Work with the tagging and routing rules applied during import
+    ///
+    /// Rules are a JSON list; each has a name, a "match" object (host, url
+    /// and title regexes, source, note_path prefix, keywords) and actions
+    /// (add_tags, remove_tags, collection, ban, skip). ban bans the host of
+    /// every matched link, so no link on that host is fetched again; use skip
+    /// to drop only the matched links.
+    Rules {
+        #[command(subcommand)]
+        command: RulesCommand,
+    },
+    /// Stop fetching, refreshing and checking links on a host
+    Ban {
+        /// Host name, or any URL on the host
+        host: String,
+    },
+    /// Browse, filter and triage links interactively
+    Tui,
+    /// Serve a web UI and JSON API over the cache
+    ///
+    /// Adding links needs the token in the front page's bookmarklet; reading
+    /// has no authentication, so bind to 0.0.0.0 only on a trusted network.
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+    },
+    /// List cached articles with text similar to a link's
+    ///
+    /// Similarity is TF-IDF cosine over the cached text, computed locally and
+    /// kept in an index that is rebuilt whenever cached articles change.
+    Related {
+        #[arg(required_unless_present = "rebuild")]
+        url: Option<String>,
+        /// Number of related links to show
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Recompute the index even if no cached article has changed
+        #[arg(long)]
+        rebuild: bool,
+    },
+    /// Report cached articles with near-identical text under different URLs
+    ///
+    /// Catches syndicated copies, AMP pages and mirrors that URL normalization
+    /// cannot, by comparing SimHash fingerprints of the cached text.
+    Duplicates {
+        /// Keep one link of each group and record the others as its aliases
+        #[arg(long)]
+        merge: bool,
     },
+    /// Summarize the library: counts by source, tag, domain and month, word
+    /// counts, and how much of it is cached and archived
+    Stats {
+        /// Print the statistics as JSON; the same as --format json
+        #[arg(long)]
+        json: bool,
+        /// Number of tags and domains to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Search the text of cached articles
+    ///
+    /// Words are matched by stem; use "quotes" for phrases, a trailing * for
+    /// prefixes, and AND/OR/NOT to combine terms.
+    Search {
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Only search links imported from this source
+        #[arg(long)]
+        source: Option<String>,
+        /// Only search links with this tag; may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only links published (or fetched) on or after this date, as YYYY-MM-DD
+        #[arg(long)]
+        since: Option<String>,
+        /// Only links published (or fetched) on or before this date, as YYYY-MM-DD
+        #[arg(long)]
+        until: Option<String>,
+        /// Maximum number of results
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Print results as JSON lines; the same as --format json
+        #[arg(long)]
+        json: bool,
+    },
+    /// Work with local page snapshots
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommand,
+    },
+    /// Publish the archive in other formats
+    Export {
+        #[command(subcommand)]
+        command: ExportCommand,
+    },
+    /// Render annotations on cached articles through the highlight template
+    Highlights {
+        /// Print the highlights for this URL instead of exporting every annotated link
+        url: Option<String>,
+        /// Template to render with instead of templates/highlights.md
+        #[arg(long)]
+        template: Option<String>,
+        /// Directory to write one Markdown file per annotated link into
+        #[arg(long, default_value = "highlights")]
+        out_dir: String,
+        /// Add a Related section listing this many similar cached articles
+        #[arg(long, default_value_t = 0)]
+        related: usize,
+    },
+}
+
+#[derive(Args)]
+pub struct PolitenessArgs {
+    /// Number of requests to make concurrently
+    #[arg(long, default_value_t = 8)]
+    pub jobs: usize,
+    /// Maximum concurrent requests to any one host
+    #[arg(long, default_value_t = 2)]
+    pub per_host: usize,
+    /// Minimum delay in milliseconds between requests to the same host
+    #[arg(long, default_value_t = 1000)]
+    pub host_delay_ms: u64,
+}
+
+#[derive(Subcommand)]
+pub enum RulesCommand {
+    /// Show which rules fire for a URL and what they would do
+    Test {
+        url: String,
+        /// Rules file to test
+        #[arg(long, default_value = "rules.json")]
+        rules: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SnapshotCommand {
+    /// Open the most recent local snapshot of a URL
+    Open {
+        url: String,
+        /// Only print where the snapshot is stored
+        #[arg(long)]
+        print: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ExportCommand {
+    /// Render links.json and cached metadata as a static HTML site
+    Site {
+        /// Directory to write the site into
+        #[arg(long, default_value = "site")]
+        out_dir: String,
+        /// Directory of templates overriding the built-in layout.html, list.html,
+        /// link.html, tags.html and search.html
+        #[arg(long, default_value = "site-templates")]
+        templates: String,
+        /// Leave out links with this tag, as well as those tagged private; may be repeated
+        #[arg(long = "exclude-tag")]
+        exclude_tags: Vec<String>,
+        /// Site title shown on every page
+        #[arg(long, default_value = "Reading log")]
+        title: String,
+    },
+    /// Write a feed of links.json, newest first by read or first-cached date
+    Feed {
+        /// Named apart from the global --format, which picks text or JSON output
+        #[arg(long = "feed-format", value_enum, default_value_t = FeedFormat::Atom)]
+        format: FeedFormat,
+        /// Only include links imported from this source
+        #[arg(long)]
+        source: Option<String>,
+        /// Only include links with this tag; may be repeated
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Maximum number of items
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Feed title
+        #[arg(long, default_value = "Reading log")]
+        title: String,
+        /// Home page URL of the feed, also used as its id; required for RSS
+        #[arg(long)]
+        url: Option<String>,
+        /// File to write the feed to instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    /// JSON lines on stdout, with progress messages on stderr
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    Atom,
+    /// RSS 2.0
+    Rss,
+    /// JSON Feed 1.1
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotFormat {
+    /// One self-contained HTML file per page, with styles and images inlined
+    Html,
+    /// Raw responses appended to a gzipped WARC file per month
+    Warc,
 }
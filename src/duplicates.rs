@@ -0,0 +1,381 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::cache::{Cache, CacheType};
+use crate::links::{read_links, write_links};
+use crate::models::SerializedLink;
+use crate::output::{emit, Report};
+
+/// Articles shorter than this are too alike by chance to compare
+const MIN_WORDS: usize = 50;
+/// Fingerprints differing in at most this many of their 64 bits are near-duplicates
+const MAX_DISTANCE: u32 = 3;
+/// Words per shingle hashed into the fingerprint
+const SHINGLE_WORDS: usize = 3;
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across builds.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// SimHash over overlapping word shingles of `text`, or `None` when the
+/// text is too short to fingerprint reliably.
+fn simhash(text: &str) -> Option<u64> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_WORDS {
+        return None;
+    }
+
+    let mut counts = [0i64; 64];
+    for shingle in words.windows(SHINGLE_WORDS) {
+        let hash = fnv1a(&shingle.join(" "));
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if hash & (1 << bit) != 0 {
+                *count += 1;
+            } else {
+                *count -= 1;
+            }
+        }
+    }
+    Some(
+        counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .fold(0, |hash, (bit, _)| hash | (1 << bit)),
+    )
+}
+
+/// Whether two fingerprints are close enough for their articles to be near-duplicates.
+fn is_near(a: u64, b: u64) -> bool {
+    (a ^ b).count_ones() <= MAX_DISTANCE
+}
+
+/// Groups of two or more indices into `fingerprints` that are within
+/// MAX_DISTANCE of each other, directly or through other members.
+fn near_duplicates(fingerprints: &[u64]) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..fingerprints.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for i in 0..fingerprints.len() {
+        for j in i + 1..fingerprints.len() {
+            if is_near(fingerprints[i], fingerprints[j]) {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[b] = a;
+            }
+        }
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..fingerprints.len() {
+        groups.entry(root(&mut parent, i)).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    groups.sort();
+    groups
+}
+
+/// Whether `url` looks like an AMP rendering of a page.
+fn is_amp(url: &str) -> bool {
+    let Ok(url) = url::Url::parse(url) else {
+        return false;
+    };
+    url.host_str().is_some_and(|host| host.starts_with("amp."))
+        || url
+            .path_segments()
+            .is_some_and(|mut segments| segments.any(|segment| segment == "amp"))
+        || url.query_pairs().any(|(key, _)| key == "amp")
+}
+
+/// The URL to keep from a group: not an AMP page, already in links.json, then shortest.
+fn canonical<'a>(urls: &[&'a str], in_links: &HashSet<&str>) -> &'a str {
+    urls.iter()
+        .min_by_key(|url| (is_amp(url), !in_links.contains(**url), url.len(), **url))
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Rewrites links whose URL is an alias to its canonical URL. Aliases of a
+/// link already in the list are dropped, with their tags, read date, note and
+/// collection added to it.
+pub fn merge_aliases(
+    links: Vec<SerializedLink>,
+    aliases: &HashMap<String, String>,
+) -> (Vec<SerializedLink>, usize) {
+    let mut merged = 0;
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut kept: Vec<SerializedLink> = Vec::with_capacity(links.len());
+    for mut link in links {
+        if let Some(canonical) = aliases.get(&link.url) {
+            link.url = canonical.clone();
+        }
+        match positions.get(&link.url) {
+            Some(&position) => {
+                let existing = &mut kept[position];
+                for tag in link.tags {
+                    if !existing.tags.contains(&tag) {
+                        existing.tags.push(tag);
+                    }
+                }
+                existing.read_at = existing.read_at.take().or(link.read_at);
+                existing.note_path = existing.note_path.take().or(link.note_path);
+                existing.collection = existing.collection.take().or(link.collection);
+                merged += 1;
+            }
+            None => {
+                positions.insert(link.url.clone(), kept.len());
+                kept.push(link);
+            }
+        }
+    }
+    (kept, merged)
+}
+
+#[derive(serde::Serialize)]
+struct AliasReport {
+    /// Re-imported duplicates folded into their canonical link
+    merged: usize,
+}
+
+impl Report for AliasReport {
+    const KIND: &'static str = "aliases";
+
+    fn text(&self) -> String {
+        format!(
+            "Merged {} re-imported duplicates into their canonical links",
+            self.merged
+        )
+    }
+}
+
+/// Folds links that were merged as duplicates back into their canonical link,
+/// so re-imported copies do not reappear.
+pub fn apply_aliases() -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let aliases = cache.query_aliases()?;
+    if aliases.is_empty() {
+        return Ok(());
+    }
+    let (links, merged) = merge_aliases(read_links()?, &aliases);
+    if merged > 0 {
+        write_links(&links)?;
+        emit(&AliasReport { merged })?;
+    }
+    Ok(())
+}
+
+#[derive(serde::Serialize)]
+struct GroupMember {
+    url: String,
+    title: String,
+    /// Within MAX_DISTANCE of the kept link, rather than only similar through
+    /// other members, so merged with `--merge`
+    near_kept: bool,
+}
+
+#[derive(serde::Serialize)]
+struct DuplicateGroup {
+    number: usize,
+    /// The link kept when merging
+    keep: String,
+    links: Vec<GroupMember>,
+}
+
+impl Report for DuplicateGroup {
+    const KIND: &'static str = "duplicate_group";
+
+    fn text(&self) -> String {
+        let mut out = format!("\nGroup {} ({} links):", self.number, self.links.len());
+        for link in &self.links {
+            let marker = if link.url == self.keep { "*" } else { " " };
+            out.push_str(&format!("\n  {marker} {} ({})", link.title, link.url));
+            if !link.near_kept {
+                out.push_str(" [only similar through others; not merged]");
+            }
+        }
+        out
+    }
+}
+
+#[derive(serde::Serialize)]
+struct DuplicatesReport {
+    cached: usize,
+    groups: usize,
+    merge: bool,
+    aliases: usize,
+    merged: usize,
+}
+
+impl Report for DuplicatesReport {
+    const KIND: &'static str = "duplicates";
+
+    fn text(&self) -> String {
+        if self.groups == 0 {
+            format!(
+                "No near-duplicate articles among {} cached links",
+                self.cached
+            )
+        } else if !self.merge {
+            format!(
+                "\n{} groups; run with --merge to keep the starred links and alias the rest",
+                self.groups
+            )
+        } else {
+            format!(
+                "\nRecorded {} aliases; merged {} links.json entries",
+                self.aliases, self.merged
+            )
+        }
+    }
+}
+
+/// Reports groups of cached articles with near-identical text, and with
+/// `merge` keeps one link of each group and records the rest as its aliases.
+pub fn report_duplicates(merge: bool) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    for link in cache.query_unfingerprinted()? {
+        // Too-short texts are stored as 0 so they are not fingerprinted again
+        cache.record_simhash(&link.url, simhash(&link.text_content).unwrap_or(0))?;
+    }
+
+    let fingerprinted = cache.query_simhashes()?;
+    let fingerprints: Vec<u64> = fingerprinted.iter().map(|(_, _, hash)| *hash).collect();
+    let groups = near_duplicates(&fingerprints);
+    let mut report = DuplicatesReport {
+        cached: fingerprinted.len(),
+        groups: groups.len(),
+        merge,
+        aliases: 0,
+        merged: 0,
+    };
+    if groups.is_empty() {
+        return emit(&report);
+    }
+
+    let links = read_links()?;
+    let in_links: HashSet<&str> = links.iter().map(|link| link.url.as_str()).collect();
+    let mut aliases = HashMap::new();
+    for (i, group) in groups.iter().enumerate() {
+        let urls: Vec<&str> = group.iter().map(|&j| fingerprinted[j].0.as_str()).collect();
+        let keep = canonical(&urls, &in_links);
+        let kept_hash = fingerprints[group[urls.iter().position(|url| *url == keep).unwrap_or(0)]];
+        let mut members = Vec::new();
+        for &j in group {
+            let (url, title, hash) = &fingerprinted[j];
+            // Groups can chain through articles unlike the kept one, which are left alone
+            let near_kept = is_near(*hash, kept_hash);
+            if url != keep && near_kept {
+                aliases.insert(url.clone(), keep.to_string());
+            }
+            members.push(GroupMember {
+                url: url.clone(),
+                title: title.clone(),
+                near_kept,
+            });
+        }
+        emit(&DuplicateGroup {
+            number: i + 1,
+            keep: keep.to_string(),
+            links: members,
+        })?;
+    }
+
+    if !merge {
+        return emit(&report);
+    }
+
+    for (url, canonical_url) in &aliases {
+        cache.record_alias(url, canonical_url)?;
+    }
+    let (links, merged) = merge_aliases(links, &aliases);
+    write_links(&links)?;
+    report.aliases = aliases.len();
+    report.merged = merged;
+    emit(&report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LinkSource;
+
+    fn article(words: &[&str]) -> String {
+        (0..1000)
+            .map(|i| format!("{}{}", words[i % words.len()], i))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_simhash_groups_near_identical_texts() {
+        let original = article(&["river", "mountain", "forest"]);
+        let syndicated = format!("Reposted from the author's blog. {original} Share this post.");
+        let other = article(&["compiler", "parser", "lexer"]);
+
+        let fingerprints: Vec<u64> = [&original, &syndicated, &other]
+            .iter()
+            .map(|text| simhash(text).unwrap())
+            .collect();
+        assert_eq!(near_duplicates(&fingerprints), [vec![0, 1]]);
+        assert_eq!(simhash("too short"), None);
+    }
+
+    #[test]
+    fn test_chained_groups_are_not_all_near() {
+        // Each is 3 bits from the next, but the ends are 6 bits apart
+        let fingerprints = [0, 0b111, 0b111111];
+        assert_eq!(near_duplicates(&fingerprints), [vec![0, 1, 2]]);
+        assert!(is_near(fingerprints[0], fingerprints[1]));
+        assert!(!is_near(fingerprints[0], fingerprints[2]));
+    }
+
+    #[test]
+    fn test_canonical_and_merge_aliases() {
+        let urls = [
+            "https://example.org/amp/story",
+            "https://example.org/story?utm_source=feed",
+            "https://mirror.example.com/a/very/long/story",
+        ];
+        let in_links = HashSet::from([urls[0], urls[2]]);
+        assert_eq!(canonical(&urls, &in_links), urls[2]);
+
+        let link = |url: &str, tag: &str| {
+            SerializedLink::new(
+                url.to_string(),
+                url.to_string(),
+                vec![tag.to_string()],
+                LinkSource::Manual,
+            )
+        };
+        let aliases = HashMap::from([
+            (urls[0].to_string(), urls[2].to_string()),
+            (urls[1].to_string(), urls[2].to_string()),
+        ]);
+        let mut canonical_link = link(urls[2], "news");
+        canonical_link.collection = Some("Reading".to_string());
+        let mut amp_link = link(urls[0], "amp");
+        amp_link.read_at = Some("2024-03-01T00:00:00Z".to_string());
+        amp_link.collection = Some("Other".to_string());
+        let (links, merged) = merge_aliases(vec![canonical_link, amp_link], &aliases);
+        assert_eq!(merged, 1);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url, urls[2]);
+        assert_eq!(links[0].tags, ["news", "amp"]);
+        assert_eq!(links[0].read_at.as_deref(), Some("2024-03-01T00:00:00Z"));
+        assert_eq!(links[0].collection.as_deref(), Some("Reading"));
+    }
+}
@@ -0,0 +1,211 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use anyhow::{anyhow, Context};
+use pulldown_cmark::{html, Event, HeadingLevel, Parser, Tag, TagEnd};
+use url::Url;
+
+const MAX_TITLE_CHARS: usize = 100;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ContentKind {
+    Html,
+    Pdf,
+    PlainText,
+    Markdown,
+    Unsupported(String),
+}
+
+/// Text pulled out of a non-HTML document.
+pub struct Extracted {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub text: String,
+    pub html: String,
+}
+
+/// Decides how to read a response from its Content-Type, falling back on the
+/// URL's extension when the server sends none or a generic binary type.
+pub fn content_kind(content_type: Option<&str>, url: &Url) -> ContentKind {
+    let mime = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|mime| mime.trim().to_lowercase())
+        .filter(|mime| !mime.is_empty() && mime != "application/octet-stream");
+
+    let extension = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_lowercase());
+
+    match (mime.as_deref(), extension.as_deref()) {
+        (Some("text/html" | "application/xhtml+xml"), _) => ContentKind::Html,
+        (Some("application/pdf" | "application/x-pdf"), _) | (None, Some("pdf")) => {
+            ContentKind::Pdf
+        }
+        (Some("text/markdown" | "text/x-markdown"), _)
+        | (Some("text/plain") | None, Some("md" | "markdown")) => ContentKind::Markdown,
+        (Some("text/plain"), _) | (None, Some("txt")) => ContentKind::PlainText,
+        (None, _) => ContentKind::Html,
+        (Some(mime), _) => ContentKind::Unsupported(mime.to_string()),
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Wraps each blank-line separated block of `text` in a paragraph.
+pub fn paragraphs_to_html(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|block| !block.is_empty())
+        .map(|block| format!("<p>{}</p>\n", escape_html(block)))
+        .collect()
+}
+
+fn first_line(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    Some(line.chars().take(MAX_TITLE_CHARS).collect())
+}
+
+pub fn extract_plain_text(text: &str) -> Extracted {
+    Extracted {
+        title: first_line(text),
+        author: None,
+        text: text.to_string(),
+        html: paragraphs_to_html(text),
+    }
+}
+
+/// Renders Markdown to HTML, taking the first top-level heading as the title.
+pub fn extract_markdown(markdown: &str) -> Extracted {
+    let mut title = None;
+    let mut in_title = false;
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            }) if title.is_none() => {
+                in_title = true;
+                title = Some(String::new());
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if in_title {
+                    title.get_or_insert_with(String::new).push_str(&t);
+                }
+                text.push_str(&t);
+            }
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Item) => {
+                in_title = false;
+                text.push_str("\n\n");
+            }
+            _ => {}
+        }
+    }
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, Parser::new(markdown));
+
+    Extracted {
+        title: title.or_else(|| first_line(markdown)),
+        author: None,
+        text: text.trim().to_string(),
+        html: rendered,
+    }
+}
+
+fn info_string(doc: &pdf_extract::Document, key: &[u8]) -> Option<String> {
+    let info = doc.trailer.get(b"Info").ok()?.as_reference().ok()?;
+    let value = doc.get_dictionary(info).ok()?.get(key).ok()?;
+    let value = pdf_extract::decode_text_string(value).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Extracts the text of a PDF, with its title and author from the document info.
+pub fn extract_pdf(bytes: &[u8]) -> anyhow::Result<Extracted> {
+    let doc = pdf_extract::Document::load_mem(bytes).context("Failed to read PDF")?;
+
+    // pdf-extract panics on some malformed fonts rather than returning an error
+    let text = catch_unwind(AssertUnwindSafe(|| {
+        pdf_extract::extract_text_from_mem(bytes)
+    }))
+    .map_err(|_| anyhow!("PDF text extraction panicked"))?
+    .context("Failed to extract text from PDF")?;
+
+    Ok(Extracted {
+        title: info_string(&doc, b"Title").or_else(|| first_line(&text)),
+        author: info_string(&doc, b"Author"),
+        html: paragraphs_to_html(&text),
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_content_kind_from_header_and_extension() {
+        let page = url("https://example.org/post");
+        let paper = url("https://example.org/papers/attention.pdf");
+
+        assert_eq!(
+            content_kind(Some("text/html; charset=utf-8"), &page),
+            ContentKind::Html
+        );
+        assert_eq!(
+            content_kind(Some("application/pdf"), &page),
+            ContentKind::Pdf
+        );
+        assert_eq!(
+            content_kind(Some("application/octet-stream"), &paper),
+            ContentKind::Pdf
+        );
+        assert_eq!(content_kind(None, &page), ContentKind::Html);
+        assert_eq!(
+            content_kind(Some("text/plain"), &url("https://example.org/README.md")),
+            ContentKind::Markdown
+        );
+        assert_eq!(
+            content_kind(Some("text/plain"), &page),
+            ContentKind::PlainText
+        );
+        assert_eq!(
+            content_kind(Some("image/png"), &page),
+            ContentKind::Unsupported("image/png".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_markdown() {
+        let extracted = extract_markdown("Intro line\n\n# The *Title*\n\nSome text with `code`.\n");
+
+        assert_eq!(extracted.title.as_deref(), Some("The Title"));
+        assert_eq!(
+            extracted.text,
+            "Intro line\n\nThe Title\n\nSome text with code."
+        );
+        assert!(extracted.html.contains("<h1>The <em>Title</em></h1>"));
+    }
+
+    #[test]
+    fn test_extract_plain_text() {
+        let extracted = extract_plain_text("\n  First line\nmore <text>\n\nSecond paragraph\n");
+
+        assert_eq!(extracted.title.as_deref(), Some("First line"));
+        assert_eq!(
+            extracted.html,
+            "<p>First line\nmore &lt;text&gt;</p>\n<p>Second paragraph</p>\n"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use anyhow::Context;
+use serde::Serialize;
+use time::format_description::well_known::{Rfc2822, Rfc3339};
+use time::{OffsetDateTime, PrimitiveDateTime};
+
+use crate::cache::{Cache, CacheType};
+use crate::cli::FeedFormat;
+use crate::links::read_links;
+use crate::models::SerializedLink;
+use crate::output::{emit, Report};
+use crate::search::parse_source;
+use crate::site::excerpt;
+use crate::summary::summary_for;
+
+pub struct FeedOptions {
+    pub format: FeedFormat,
+    pub source: Option<String>,
+    /// Every tag must be present
+    pub tags: Vec<String>,
+    pub limit: usize,
+    pub title: String,
+    /// Home page of the feed, also used as its id
+    pub url: Option<String>,
+    /// File to write to instead of stdout
+    pub output: Option<String>,
+}
+
+struct FeedItem {
+    url: String,
+    title: String,
+    date: OffsetDateTime,
+    summary: Option<String>,
+    tags: Vec<String>,
+}
+
+/// Reads RFC 3339 dates, SQLite's `YYYY-MM-DD HH:MM:SS` (UTC) and Unix timestamps.
+pub fn parse_date(value: &str) -> Option<OffsetDateTime> {
+    let value = value.trim();
+    if let Ok(date) = OffsetDateTime::parse(value, &Rfc3339) {
+        return Some(date);
+    }
+    let sqlite = time::format_description::parse_borrowed::<2>(
+        "[year]-[month]-[day] [hour]:[minute]:[second]",
+    )
+    .ok()?;
+    if let Ok(date) = PrimitiveDateTime::parse(value, &sqlite) {
+        return Some(date.assume_utc());
+    }
+    let seconds: f64 = value.parse().ok()?;
+    OffsetDateTime::from_unix_timestamp(seconds as i64).ok()
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rfc3339(date: OffsetDateTime) -> String {
+    date.format(&Rfc3339).unwrap_or_default()
+}
+
+/// The feed's own date: that of its newest item, so output only changes with the items.
+fn updated(items: &[FeedItem]) -> OffsetDateTime {
+    items
+        .iter()
+        .map(|item| item.date)
+        .max()
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+fn render_atom(options: &FeedOptions, items: &[FeedItem]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    out.push_str(&format!(
+        "  <title>{}</title>\n",
+        xml_escape(&options.title)
+    ));
+    match &options.url {
+        Some(url) => {
+            out.push_str(&format!("  <id>{}</id>\n", xml_escape(url)));
+            out.push_str(&format!("  <link href=\"{}\"/>\n", xml_escape(url)));
+        }
+        None => out.push_str("  <id>urn:sync-bookmarks:feed</id>\n"),
+    }
+    out.push_str(&format!(
+        "  <updated>{}</updated>\n",
+        rfc3339(updated(items))
+    ));
+    out.push_str(&format!(
+        "  <author><name>{}</name></author>\n",
+        xml_escape(&options.title)
+    ));
+    for item in items {
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <title>{}</title>\n", xml_escape(&item.title)));
+        out.push_str(&format!("    <id>{}</id>\n", xml_escape(&item.url)));
+        out.push_str(&format!("    <link href=\"{}\"/>\n", xml_escape(&item.url)));
+        out.push_str(&format!("    <updated>{}</updated>\n", rfc3339(item.date)));
+        for tag in &item.tags {
+            out.push_str(&format!("    <category term=\"{}\"/>\n", xml_escape(tag)));
+        }
+        if let Some(summary) = &item.summary {
+            out.push_str(&format!("    <summary>{}</summary>\n", xml_escape(summary)));
+        }
+        out.push_str("  </entry>\n");
+    }
+    out.push_str("</feed>\n");
+    out
+}
+
+/// RSS 2.0 requires a channel link, so this needs `options.url`.
+fn render_rss(options: &FeedOptions, items: &[FeedItem]) -> anyhow::Result<String> {
+    let link = options
+        .url
+        .as_deref()
+        .context("RSS feeds need a home page link; pass --url")?;
+    let rfc2822 = |date: OffsetDateTime| date.format(&Rfc2822).unwrap_or_default();
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    out.push_str("<rss version=\"2.0\">\n<channel>\n");
+    out.push_str(&format!(
+        "  <title>{}</title>\n",
+        xml_escape(&options.title)
+    ));
+    out.push_str(&format!("  <link>{}</link>\n", xml_escape(link)));
+    out.push_str(&format!(
+        "  <description>{}</description>\n",
+        xml_escape(&options.title)
+    ));
+    out.push_str(&format!(
+        "  <lastBuildDate>{}</lastBuildDate>\n",
+        rfc2822(updated(items))
+    ));
+    for item in items {
+        out.push_str("  <item>\n");
+        out.push_str(&format!("    <title>{}</title>\n", xml_escape(&item.title)));
+        out.push_str(&format!("    <link>{}</link>\n", xml_escape(&item.url)));
+        out.push_str(&format!(
+            "    <guid isPermaLink=\"true\">{}</guid>\n",
+            xml_escape(&item.url)
+        ));
+        out.push_str(&format!("    <pubDate>{}</pubDate>\n", rfc2822(item.date)));
+        for tag in &item.tags {
+            out.push_str(&format!("    <category>{}</category>\n", xml_escape(tag)));
+        }
+        if let Some(summary) = &item.summary {
+            out.push_str(&format!(
+                "    <description>{}</description>\n",
+                xml_escape(summary)
+            ));
+        }
+        out.push_str("  </item>\n");
+    }
+    out.push_str("</channel>\n</rss>\n");
+    Ok(out)
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    home_page_url: Option<&'a str>,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: &'a str,
+    url: &'a str,
+    title: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<&'a str>,
+    date_published: String,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+}
+
+fn render_json(options: &FeedOptions, items: &[FeedItem]) -> anyhow::Result<String> {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: &options.title,
+        home_page_url: options.url.as_deref(),
+        items: items
+            .iter()
+            .map(|item| JsonFeedItem {
+                id: &item.url,
+                url: &item.url,
+                title: &item.title,
+                summary: item.summary.as_deref(),
+                date_published: rfc3339(item.date),
+                tags: &item.tags,
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&feed)? + "\n")
+}
+
+/// Picks the links for the feed, newest first. Links are dated by when they
+/// were read, or failing that when they were first cached; links with
+/// neither are left out. Summaries not stored yet are computed and saved to
+/// the cache, so exporting a feed may write to cache.db.
+fn feed_items(
+    links: Vec<SerializedLink>,
+    cache: &Cache,
+    options: &FeedOptions,
+) -> anyhow::Result<Vec<FeedItem>> {
+    let source = options.source.as_deref().map(parse_source).transpose()?;
+    let added_dates = cache.query_added_dates()?;
+
+    let mut dated = Vec::new();
+    for link in links {
+        if source.is_some_and(|source| link.source != source)
+            || !options.tags.iter().all(|tag| link.tags.contains(tag))
+        {
+            continue;
+        }
+        let Some(date) = link
+            .read_at
+            .as_deref()
+            .and_then(parse_date)
+            .or_else(|| added_dates.get(&link.url).and_then(|date| parse_date(date)))
+        else {
+            continue;
+        };
+        dated.push((date, link));
+    }
+    dated.sort_by(|(a_date, a), (b_date, b)| b_date.cmp(a_date).then_with(|| a.url.cmp(&b.url)));
+    dated.truncate(options.limit);
+
+    // Only the kept links are summarized, since summarizing stores to the cache
+    let mut items = Vec::with_capacity(dated.len());
+    for (date, link) in dated {
+        let text = cache.query(&link.url)?.map(|cached| cached.text_content);
+        let summary = summary_for(cache, &link.url)?;
+        items.push(FeedItem {
+            summary: excerpt(None, summary.as_deref(), text.as_deref()),
+            url: link.url,
+            title: link.title,
+            date,
+            tags: link.tags,
+        });
+    }
+    Ok(items)
+}
+
+#[derive(Serialize)]
+struct FeedReport {
+    items: usize,
+    path: String,
+}
+
+impl Report for FeedReport {
+    const KIND: &'static str = "feed";
+
+    fn text(&self) -> String {
+        format!("Wrote {} items to {}", self.items, self.path)
+    }
+}
+
+/// A feed written to stdout rather than a file.
+#[derive(Serialize)]
+struct RenderedFeed {
+    feed: String,
+}
+
+impl Report for RenderedFeed {
+    const KIND: &'static str = "rendered_feed";
+
+    fn text(&self) -> String {
+        self.feed.clone()
+    }
+}
+
+pub fn export_feed(options: &FeedOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let items = feed_items(read_links()?, &cache, options)?;
+
+    let feed = match options.format {
+        FeedFormat::Atom => render_atom(options, &items),
+        FeedFormat::Rss => render_rss(options, &items)?,
+        FeedFormat::Json => render_json(options, &items)?,
+    };
+
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, feed).with_context(|| format!("Failed to write {path}"))?;
+            emit(&FeedReport {
+                items: items.len(),
+                path: path.clone(),
+            })
+        }
+        None => emit(&RenderedFeed { feed }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CachedLink, LinkSource};
+
+    fn options(format: FeedFormat) -> FeedOptions {
+        FeedOptions {
+            format,
+            source: None,
+            tags: Vec::new(),
+            limit: 10,
+            title: "Reading log".to_string(),
+            url: Some("https://example.org/".to_string()),
+            output: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_date_formats() {
+        let expected = OffsetDateTime::from_unix_timestamp(1_700_000_000).ok();
+        assert_eq!(parse_date("2023-11-14T22:13:20Z"), expected);
+        assert_eq!(parse_date("2023-11-14 22:13:20"), expected);
+        assert_eq!(parse_date("1700000000"), expected);
+        assert_eq!(parse_date("last week"), None);
+    }
+
+    #[test]
+    fn test_feed_items_order_filter_and_render() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://example.org/cached".to_string(),
+            "Cached".to_string(),
+            LinkSource::Manual,
+            Vec::new(),
+            "Cached article text.".to_string(),
+        ))?;
+
+        let read = SerializedLink {
+            read_at: Some("2001-02-03T04:05:06Z".to_string()),
+            ..SerializedLink::new(
+                "https://example.org/read?a=1&b=2".to_string(),
+                "Tom & Jerry".to_string(),
+                vec!["cartoons".to_string()],
+                LinkSource::GoodLinks,
+            )
+        };
+        let links = vec![
+            read.clone(),
+            SerializedLink::new(
+                "https://example.org/cached".to_string(),
+                "Cached".to_string(),
+                Vec::new(),
+                LinkSource::Manual,
+            ),
+            SerializedLink::new(
+                "https://example.org/undated".to_string(),
+                "Undated".to_string(),
+                Vec::new(),
+                LinkSource::Manual,
+            ),
+        ];
+
+        let items = feed_items(links.clone(), &cache, &options(FeedFormat::Atom))?;
+        let urls: Vec<_> = items.iter().map(|item| item.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.org/cached",
+                "https://example.org/read?a=1&b=2"
+            ]
+        );
+        assert_eq!(items[0].summary.as_deref(), Some("Cached article text."));
+
+        let mut by_tag = options(FeedFormat::Atom);
+        by_tag.tags = vec!["cartoons".to_string()];
+        let items = feed_items(links, &cache, &by_tag)?;
+        assert_eq!(items.len(), 1);
+
+        let atom = render_atom(&by_tag, &items);
+        assert!(atom.contains("<title>Tom &amp; Jerry</title>"));
+        assert!(atom.contains("<updated>2001-02-03T04:05:06Z</updated>"));
+        assert_eq!(atom, render_atom(&by_tag, &items));
+
+        let rss = render_rss(&by_tag, &items)?;
+        assert!(rss.contains("<pubDate>Sat, 03 Feb 2001 04:05:06 +0000</pubDate>"));
+        by_tag.url = None;
+        assert!(render_rss(&by_tag, &items).is_err());
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&by_tag, &items)?)?;
+        assert_eq!(json["items"][0]["id"], "https://example.org/read?a=1&b=2");
+        assert_eq!(json["items"][0]["tags"][0], "cartoons");
+
+        Ok(())
+    }
+}
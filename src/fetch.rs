@@ -1,11 +1,29 @@
-use anyhow::Context;
-use indicatif::ProgressBar;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use indicatif::{ProgressBar, ProgressStyle};
 use readability::extractor;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use ureq::ResponseExt;
 use url::Url;
 
 use crate::{
-    cache::{Cache, CacheType},
-    models::{Article, CachedLink, SerializedLink},
+    cache::{Cache, CacheType, MAX_FETCH_ATTEMPTS},
+    extract::{
+        content_kind, extract_markdown, extract_pdf, extract_plain_text, ContentKind, Extracted,
+    },
+    links::{read_links, resolve_redirects, write_links},
+    metadata::extract_metadata,
+    models::{
+        Article, CachedLink, CapturedResponse, ErrorClass, FetchError, FetchFailure, PageMetadata,
+        SerializedLink,
+    },
+    output::{emit, progress, Report},
+    scheduler::{host_of, run_politely, Politeness},
+    snapshot::{SnapshotOptions, Snapshotter},
+    summary::summarize,
 };
 
 pub const BANNED_HOSTS: &[&str] = &[
@@ -21,7 +39,322 @@ pub const BANNED_HOSTS: &[&str] = &[
     "xn--url-u63b6dn8esao8c4jh9d2c1a0lk29262bmhrb.com",
 ];
 
-pub fn fetch_to_cache(verbose: bool) -> anyhow::Result<()> {
+pub struct FetchOptions {
+    pub verbose: bool,
+    pub politeness: Politeness,
+    /// Retry previously failed links even if they are backing off or considered dead
+    pub retry_failed: bool,
+    /// Keep a local copy of every page fetched
+    pub snapshot: Option<SnapshotOptions>,
+}
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const USER_AGENT: &str = "Mozilla/5.0 (compatible; sync_bookmarks)";
+/// PDFs and text files may be larger than ureq's default 10MB body limit
+const MAX_DOCUMENT_BYTES: u64 = 100 * 1024 * 1024;
+
+/// The built-in banned hosts plus any banned with `ban`.
+pub fn banned_hosts(cache: &Cache) -> anyhow::Result<HashSet<String>> {
+    let mut hosts = cache.query_banned_hosts()?;
+    hosts.extend(BANNED_HOSTS.iter().map(|host| host.to_string()));
+    Ok(hosts)
+}
+
+#[derive(Serialize)]
+struct BanReport {
+    host: String,
+}
+
+impl Report for BanReport {
+    const KIND: &'static str = "ban";
+
+    fn text(&self) -> String {
+        format!("Banned {}", self.host)
+    }
+}
+
+/// Bans the host of `url_or_host` so its links are no longer fetched,
+/// refreshed or checked.
+pub fn ban_host(url_or_host: &str) -> anyhow::Result<()> {
+    let host = match host_of(url_or_host) {
+        host if host.is_empty() => url_or_host.trim().to_lowercase(),
+        host => host,
+    };
+    let cache = Cache::new(CacheType::Disk("cache.db".to_owned()))?;
+    cache.ban_host(&host)?;
+    emit(&BanReport { host })
+}
+
+pub fn http_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(FETCH_TIMEOUT))
+        .user_agent(USER_AGENT)
+        .save_redirect_history(true)
+        .build()
+        .into()
+}
+
+/// Fetches and extracts `url`, keeping the raw response in the article if `capture` is set.
+fn fetch_article(
+    agent: &ureq::Agent,
+    url: &str,
+    banned: &HashSet<String>,
+    capture: bool,
+) -> Result<Article, FetchError> {
+    let response = agent.get(url).call()?;
+    article_from_response(url, response, banned, capture)
+}
+
+/// Fetches `url` again, returning `None` if the server says it hasn't changed.
+pub fn refetch_article(
+    agent: &ureq::Agent,
+    url: &str,
+    banned: &HashSet<String>,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> Result<Option<Article>, FetchError> {
+    let mut request = agent.get(url);
+    if let Some(etag) = etag {
+        request = request.header("If-None-Match", etag);
+    }
+    if let Some(last_modified) = last_modified {
+        request = request.header("If-Modified-Since", last_modified);
+    }
+
+    let response = request.call()?;
+    if response.status() == ureq::http::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    article_from_response(url, response, banned, false).map(Some)
+}
+
+/// Extracts the article from `response`, failing if any redirect on the way
+/// went through a host in `banned`.
+fn article_from_response(
+    url: &str,
+    mut response: ureq::http::Response<ureq::Body>,
+    banned: &HashSet<String>,
+    capture: bool,
+) -> Result<Article, FetchError> {
+    let parsed_url = Url::parse(url).map_err(|e| FetchError {
+        status_code: None,
+        class: ErrorClass::Other,
+        message: e.to_string(),
+    })?;
+
+    let redirects: Vec<String> = response
+        .get_redirect_history()
+        .filter(|history| history.len() > 1)
+        .map(|history| history.iter().map(|uri| uri.to_string()).collect())
+        .unwrap_or_default();
+    if let Some(location) = redirects
+        .iter()
+        .find(|location| banned.contains(&host_of(location)))
+    {
+        return Err(FetchError {
+            status_code: None,
+            class: ErrorClass::Banned,
+            message: format!("Redirected to banned host {}", host_of(location)),
+        });
+    }
+
+    let final_url = Url::parse(&response.get_uri().to_string()).unwrap_or(parsed_url);
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let content_type = header("content-type");
+    let etag = header("etag");
+    let last_modified = header("last-modified");
+
+    let kind = content_kind(content_type.as_deref(), &final_url);
+    if let ContentKind::Unsupported(mime) = &kind {
+        return Err(FetchError {
+            status_code: None,
+            class: ErrorClass::Unsupported,
+            message: format!("Unsupported content type {mime}"),
+        });
+    }
+
+    let status = response.status().as_u16();
+    let headers: Vec<(String, String)> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            (name.to_string(), value)
+        })
+        .collect();
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOCUMENT_BYTES)
+        .read_to_vec()?;
+
+    let (title, text_content, content_html, mut metadata) = match kind {
+        ContentKind::Html => {
+            let html = String::from_utf8_lossy(&body);
+            let product =
+                extractor::extract(&mut html.as_bytes(), &final_url).map_err(parse_error)?;
+            let metadata = extract_metadata(&html, &final_url);
+            (product.title, product.text, product.content, metadata)
+        }
+        ContentKind::Pdf => from_document(extract_pdf(&body).map_err(parse_error)?, &final_url),
+        ContentKind::Markdown => from_document(
+            extract_markdown(&String::from_utf8_lossy(&body)),
+            &final_url,
+        ),
+        ContentKind::PlainText => from_document(
+            extract_plain_text(&String::from_utf8_lossy(&body)),
+            &final_url,
+        ),
+        ContentKind::Unsupported(_) => unreachable!("unsupported content is rejected above"),
+    };
+
+    metadata.word_count = Some(text_content.split_whitespace().count());
+    metadata.content_type = content_type.and_then(|ct| ct.split(';').next().map(str::to_string));
+
+    Ok(Article {
+        title,
+        text_content,
+        content_html,
+        metadata,
+        redirects,
+        captured: capture.then(|| CapturedResponse {
+            url: final_url.to_string(),
+            status,
+            headers,
+            body,
+        }),
+        etag,
+        last_modified,
+    })
+}
+
+/// Hash of an article's text, ignoring differences in whitespace.
+pub fn content_hash(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    for word in text.split_whitespace() {
+        hasher.update(word.as_bytes());
+        hasher.update(b" ");
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn parse_error(e: impl std::fmt::Display) -> FetchError {
+    FetchError {
+        status_code: None,
+        class: ErrorClass::Parse,
+        message: e.to_string(),
+    }
+}
+
+/// Documents without a title of their own are named after their file.
+fn from_document(extracted: Extracted, url: &Url) -> (String, String, String, PageMetadata) {
+    let title = extracted.title.unwrap_or_else(|| {
+        url.path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .filter(|name| !name.is_empty())
+            .map_or_else(|| url.to_string(), str::to_string)
+    });
+    let metadata = PageMetadata {
+        author: extracted.author,
+        ..Default::default()
+    };
+    (title, extracted.text, extracted.html, metadata)
+}
+
+/// Writes a freshly fetched article to the cache, replacing any earlier copy.
+/// The writes share a transaction, so an interrupted store leaves no
+/// half-written row for `refresh` to mistake for a stale one.
+fn store_article(cache: &Cache, link: &SerializedLink, article: Article) -> anyhow::Result<()> {
+    let hash = content_hash(&article.text_content);
+    let summary = summarize(&article.text_content);
+    cache.atomically(|| store_fetched(cache, link, article, &hash, summary.as_deref()))
+}
+
+fn store_fetched(
+    cache: &Cache,
+    link: &SerializedLink,
+    article: Article,
+    hash: &str,
+    summary: Option<&str>,
+) -> anyhow::Result<()> {
+    cache.clear_fetch_failure(&link.url)?;
+    match cache.query(&link.url)? {
+        Some(cached) if content_hash(&cached.text_content) == hash => {}
+        Some(_) => cache.replace_content(&link.url, &article.title, &article.text_content)?,
+        None => cache.insert(&CachedLink::new(
+            link.url.clone(),
+            article.title,
+            link.source,
+            link.tags.clone(),
+            article.text_content,
+        ))?,
+    }
+    cache.record_summary(&link.url, summary)?;
+    cache.record_page_details(&link.url, &article.content_html, &article.metadata)?;
+    cache.record_redirects(&link.url, &article.redirects)?;
+    cache.record_validators(
+        &link.url,
+        article.etag.as_deref(),
+        article.last_modified.as_deref(),
+        hash,
+    )
+}
+
+/// Fetches a single link right away, ignoring any backoff, and caches it.
+pub fn fetch_link(cache: &Cache, link: &SerializedLink) -> anyhow::Result<()> {
+    match fetch_article(&http_agent(), &link.url, &banned_hosts(cache)?, false) {
+        Ok(article) => store_article(cache, link, article),
+        Err(e) => {
+            cache.record_fetch_failure(&link.url, &e)?;
+            bail!("Failed to fetch {}: {}", link.url, e.message)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct FetchReport {
+    /// Links in links.json
+    total: usize,
+    /// Links not fetched because they are cached, banned or waiting to retry
+    skipped: usize,
+    fetched: usize,
+    failed: usize,
+}
+
+impl Report for FetchReport {
+    const KIND: &'static str = "fetch";
+
+    fn text(&self) -> String {
+        format!("Done! {} fetched, {} failed", self.fetched, self.failed)
+    }
+}
+
+#[derive(Serialize)]
+struct RedirectReport {
+    rewritten: usize,
+    /// Rewritten links dropped because their final URL was already in links.json
+    merged: usize,
+}
+
+impl Report for RedirectReport {
+    const KIND: &'static str = "redirects";
+
+    fn text(&self) -> String {
+        format!(
+            "Rewrote {} redirected links ({} merged into existing entries)",
+            self.rewritten + self.merged,
+            self.merged
+        )
+    }
+}
+
+pub fn fetch_to_cache(options: &FetchOptions) -> anyhow::Result<()> {
     let cache = Cache::new(CacheType::Disk("cache.db".to_owned()))?;
 
     let serialized_links: Vec<SerializedLink> = serde_json::from_str::<Vec<SerializedLink>>(
@@ -29,57 +362,170 @@ pub fn fetch_to_cache(verbose: bool) -> anyhow::Result<()> {
     )
     .context("Failed to parse links.json")?;
 
-    let pb = ProgressBar::new(serialized_links.len().try_into()?);
+    let banned = banned_hosts(&cache)?;
+    let cached_urls = cache.query_all_urls()?;
+    let backoff_urls = if options.retry_failed {
+        HashSet::new()
+    } else {
+        cache.query_backoff_urls()?
+    };
+    let total = serialized_links.len();
+    let to_fetch: Vec<SerializedLink> = serialized_links
+        .into_iter()
+        .filter(|link| {
+            !banned.contains(&host_of(&link.url))
+                && !cached_urls.contains(&link.url)
+                && !backoff_urls.contains(&link.url)
+        })
+        .collect();
 
-    for link in serialized_links {
-        if let Ok(parsed_url) = Url::parse(&link.url) {
-            if let Some(host) = parsed_url.host_str() {
-                if BANNED_HOSTS.contains(&host) {
-                    continue;
-                }
-            }
-        }
+    let skipped = total - to_fetch.len();
+    progress!(
+        "Fetching {} of {total} links ({skipped} cached, banned or waiting to retry)",
+        to_fetch.len(),
+    );
 
-        let cached_link = cache.query(&link.url)?;
+    let pb = ProgressBar::new(to_fetch.len().try_into()?);
+    pb.set_style(ProgressStyle::with_template(
+        "{bar:40} {pos}/{len} [{elapsed_precise}, {per_sec}, eta {eta}] {msg}",
+    )?);
 
-        let article = match &cached_link {
-            Some(link) => Article {
-                title: link.title.clone(),
-                text_content: link.text_content.clone(),
-            },
-            None => {
-                if verbose {
-                    println!("Fetching {}", link.url);
-                }
-                let product = match extractor::scrape(&link.url) {
-                    Ok(product) => product,
-                    Err(e) => {
-                        if verbose {
-                            println!("Failed to parse link {}: {e}", link.url);
+    let agent = http_agent();
+    let snapshotter = options.snapshot.as_ref().map(|snapshot| {
+        Snapshotter::new(
+            snapshot.format,
+            &snapshot.dir,
+            banned.clone(),
+            options.politeness.host_delay,
+        )
+    });
+
+    let mut fetched = 0;
+    let mut failed = 0;
+
+    // The cache is only written from this thread, as results come back from the workers
+    run_politely(
+        to_fetch,
+        |link| link.url.as_str(),
+        &options.politeness,
+        |link| {
+            if options.verbose {
+                pb.println(format!("Fetching {}", link.url));
+            }
+            let article = fetch_article(&agent, &link.url, &banned, snapshotter.is_some());
+            // Single-file snapshots fetch the page's images and styles, so save them here
+            let snapshot = match (&article, &snapshotter) {
+                (Ok(article), Some(snapshotter)) => article
+                    .captured
+                    .as_ref()
+                    .map(|captured| snapshotter.save(&agent, &link.url, captured)),
+                _ => None,
+            };
+            (article, snapshot)
+        },
+        |link, (result, snapshot)| {
+            match result {
+                Ok(article) => {
+                    store_article(&cache, &link, article)?;
+                    match snapshot {
+                        Some(Ok(snapshot)) => cache.insert_snapshot(&snapshot)?,
+                        Some(Err(e)) => {
+                            pb.println(format!("Failed to snapshot {}: {e:#}", link.url))
                         }
-                        continue;
+                        None => {}
+                    }
+                    fetched += 1;
+                }
+                Err(e) => {
+                    if options.verbose {
+                        pb.println(format!("Failed to fetch link {}: {}", link.url, e.message));
                     }
-                };
-                Article {
-                    title: product.title,
-                    text_content: product.text,
+                    cache.record_fetch_failure(&link.url, &e)?;
+                    failed += 1;
                 }
             }
+            pb.set_message(format!("{failed} failed"));
+            pb.inc(1);
+            Ok(())
+        },
+    )?;
+
+    pb.finish_and_clear();
+
+    emit(&FetchReport {
+        total,
+        skipped,
+        fetched,
+        failed,
+    })
+}
+
+/// Rewrites links.json entries to the final URL recorded when they were fetched.
+pub fn rewrite_redirects(verbose: bool) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_owned()))?;
+    let final_urls = cache.query_final_urls()?;
+
+    let links = read_links()?;
+    for link in &links {
+        let Some(final_url) = final_urls.get(&link.url) else {
+            continue;
         };
+        if verbose {
+            progress!("{}", cache.query_redirect_chain(&link.url)?.join(" -> "));
+        }
+        // Imports map the original URL to the rewritten entry from now on
+        cache.resolve_redirect(&link.url, final_url)?;
+    }
+
+    let (links, stats) = resolve_redirects(links, &final_urls);
+    write_links(&links)?;
 
-        if cached_link.is_none() {
-            cache.insert(&CachedLink::new(
-                link.url,
-                article.title,
-                link.source,
-                link.tags,
-                article.text_content,
-            ))?;
+    emit(&RedirectReport {
+        rewritten: stats.rewritten,
+        merged: stats.merged,
+    })
+}
+
+impl Report for FetchFailure {
+    const KIND: &'static str = "fetch_failure";
+
+    fn text(&self) -> String {
+        let status = self
+            .status_code
+            .map_or(self.error_class.clone(), |code| code.to_string());
+        format!(
+            "{:>2}x {status:<10} {} (last tried {})",
+            self.attempts, self.url, self.last_attempt_at
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct FailureSummary {
+    failing: usize,
+}
+
+impl Report for FailureSummary {
+    const KIND: &'static str = "fetch_failures";
+
+    fn text(&self) -> String {
+        if self.failing == 0 {
+            "No failing links.".to_string()
+        } else {
+            format!("\n{} failing links", self.failing)
         }
-        pb.inc(1);
     }
+}
 
-    pb.finish_with_message("Done!");
+/// Lists links that have failed MAX_FETCH_ATTEMPTS times, or every failing link if `all`.
+pub fn report_failures(all: bool) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_owned()))?;
+    let failures = cache.query_fetch_failures(if all { 1 } else { MAX_FETCH_ATTEMPTS })?;
 
-    Ok(())
+    for failure in &failures {
+        emit(failure)?;
+    }
+    emit(&FailureSummary {
+        failing: failures.len(),
+    })
 }
@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use anyhow::Context;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::cache::{Cache, CacheType};
+use crate::metadata::{byline, reading_time_minutes};
+use crate::models::{Annotation, CachedLink, PageMetadata, RelatedLink};
+use crate::output::{emit, progress, Report};
+use crate::related::ensure_index;
+use crate::summary::summary_for;
+use crate::template::Template;
+
+const DEFAULT_TEMPLATE: &str = include_str!("../templates/highlights.md");
+
+fn render_highlights(
+    template: &Template,
+    link: &CachedLink,
+    metadata: &PageMetadata,
+    annotations: &[Annotation],
+    summary: Option<&str>,
+    related: &[RelatedLink],
+) -> String {
+    let highlights: Vec<_> = annotations
+        .iter()
+        .enumerate()
+        .map(|(i, annotation)| {
+            json!({
+                "content_md": annotation.quote.clone().unwrap_or_default(),
+                "note": annotation.note,
+                "tags": annotation.tags,
+                "is_last": i + 1 == annotations.len(),
+            })
+        })
+        .collect();
+
+    template.render(&json!({
+        "title": link.title,
+        "url": link.url,
+        "author": metadata.author,
+        "site_name": metadata.site_name,
+        "published_at": metadata.published_at,
+        "lead_image": metadata.lead_image,
+        "reading_time": metadata.word_count.map(reading_time_minutes),
+        "byline": byline(metadata),
+        "summary": summary,
+        "highlights": highlights,
+        "has_related": !related.is_empty(),
+        "related": related,
+    }))
+}
+
+/// The note's file name: the title, then a short hash of the URL so links
+/// with the same title, or none, get notes of their own.
+fn file_name_for(title: &str, url: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '-',
+            c => c,
+        })
+        .collect();
+    let hash = Sha256::digest(url.as_bytes());
+    let hex: String = hash.iter().take(4).map(|b| format!("{b:02x}")).collect();
+    match name.trim() {
+        "" => format!("{hex}.md"),
+        name => format!("{name} {hex}.md"),
+    }
+}
+
+/// The rendered note for one link, printed rather than written to a file.
+#[derive(serde::Serialize)]
+struct HighlightNote {
+    url: String,
+    note: String,
+}
+
+impl Report for HighlightNote {
+    const KIND: &'static str = "highlight_note";
+
+    fn text(&self) -> String {
+        // `emit` adds the final newline
+        self.note.trim_end_matches('\n').to_string()
+    }
+}
+
+#[derive(serde::Serialize)]
+struct HighlightsReport {
+    written: usize,
+    out_dir: String,
+}
+
+impl Report for HighlightsReport {
+    const KIND: &'static str = "highlights";
+
+    fn text(&self) -> String {
+        format!(
+            "Wrote highlights for {} links to {}",
+            self.written, self.out_dir
+        )
+    }
+}
+
+/// Prints the highlights for `url`, or writes one Markdown file per annotated link into `out_dir`.
+/// With `related` above zero, each note also lists that many similar cached articles.
+pub fn export_highlights(
+    url: Option<&str>,
+    template_path: Option<&str>,
+    out_dir: &str,
+    related: usize,
+) -> anyhow::Result<()> {
+    let template_src = match template_path {
+        Some(path) => {
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?
+        }
+        None => DEFAULT_TEMPLATE.to_string(),
+    };
+    let template = Template::parse(&template_src).context("Failed to parse highlight template")?;
+
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    if related > 0 {
+        ensure_index(&cache)?;
+    }
+
+    if let Some(url) = url {
+        let link = cache
+            .query(url)?
+            .with_context(|| format!("{url} is not in the cache"))?;
+        let metadata = cache.query_page_metadata(url)?.unwrap_or_default();
+        let annotations = cache.query_annotations(url)?;
+        let summary = summary_for(&cache, &link.url)?;
+        let related = cache.query_related(&link.url, related)?;
+        let note = render_highlights(
+            &template,
+            &link,
+            &metadata,
+            &annotations,
+            summary.as_deref(),
+            &related,
+        );
+        return emit(&HighlightNote {
+            url: link.url,
+            note,
+        });
+    }
+
+    std::fs::create_dir_all(out_dir).with_context(|| format!("Failed to create {out_dir}"))?;
+
+    let mut written = 0;
+    for url in cache.query_annotated_urls()? {
+        let Some(link) = cache.query(&url)? else {
+            progress!("Skipping annotations on {url}, which is not cached");
+            continue;
+        };
+        let metadata = cache.query_page_metadata(&url)?.unwrap_or_default();
+        let annotations = cache.query_annotations(&url)?;
+        let summary = summary_for(&cache, &url)?;
+        let related = cache.query_related(&url, related)?;
+        let path = Path::new(out_dir).join(file_name_for(&link.title, &url));
+        std::fs::write(
+            &path,
+            render_highlights(
+                &template,
+                &link,
+                &metadata,
+                &annotations,
+                summary.as_deref(),
+                &related,
+            ),
+        )
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+        written += 1;
+    }
+
+    emit(&HighlightsReport {
+        written,
+        out_dir: out_dir.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_names_are_unique_per_url() {
+        let a = file_name_for("Notes: Part 1", "https://a.example/notes");
+        let b = file_name_for("Notes: Part 1", "https://b.example/notes");
+        assert_ne!(a, b);
+        assert!(a.starts_with("Notes- Part 1 "));
+        assert!(a.ends_with(".md"));
+        assert_eq!(
+            file_name_for("  ", "https://a.example/").len(),
+            "01234567.md".len()
+        );
+    }
+}
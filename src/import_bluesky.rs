@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context};
+use ciborium::Value;
+
+use crate::links::{merge_source, read_links, resolve_aliases, write_links, ImportSummary};
+use crate::models::{LinkSource, SerializedLink, SocialPost};
+use crate::output::{emit, progress};
+
+/// DAG-CBOR encodes links to other blocks as tag 42 wrapping a 0x00-prefixed CID.
+const CID_TAG: u64 = 42;
+
+const POST_COLLECTION: &str = "app.bsky.feed.post";
+const LIKE_COLLECTION: &str = "app.bsky.feed.like";
+
+type Blocks = HashMap<Vec<u8>, Vec<u8>>;
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*pos)
+            .context("Unexpected end of CAR file in varint")?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("Varint too long in CAR file")
+}
+
+fn cid_len(bytes: &[u8]) -> anyhow::Result<usize> {
+    // CIDv0 is a bare sha2-256 multihash
+    if bytes.starts_with(&[0x12, 0x20]) {
+        return Ok(34);
+    }
+    let mut pos = 0;
+    let _version = read_varint(bytes, &mut pos)?;
+    let _codec = read_varint(bytes, &mut pos)?;
+    let _hash_code = read_varint(bytes, &mut pos)?;
+    let digest_len = read_varint(bytes, &mut pos)?;
+    pos.checked_add(usize::try_from(digest_len)?)
+        .context("CAR block CID is too long")
+}
+
+/// Splits a CARv1 file into its root CIDs and a map of block CID to block data.
+fn read_car(bytes: &[u8]) -> anyhow::Result<(Vec<Vec<u8>>, Blocks)> {
+    let mut pos = 0;
+    let header_len = usize::try_from(read_varint(bytes, &mut pos)?)?;
+    let header_bytes = pos
+        .checked_add(header_len)
+        .and_then(|end| bytes.get(pos..end))
+        .context("CAR header is truncated")?;
+    let header: Value =
+        ciborium::from_reader(header_bytes).context("Failed to decode CAR header")?;
+    pos += header_len;
+
+    let roots = match get(&header, "roots") {
+        Some(Value::Array(roots)) => roots.iter().filter_map(as_cid).collect(),
+        _ => bail!("CAR header has no roots"),
+    };
+
+    let mut blocks = HashMap::new();
+    while pos < bytes.len() {
+        let section_len = usize::try_from(read_varint(bytes, &mut pos)?)?;
+        let section = pos
+            .checked_add(section_len)
+            .and_then(|end| bytes.get(pos..end))
+            .context("CAR block is truncated")?;
+        let cid = section
+            .get(..cid_len(section)?)
+            .context("CAR block CID is truncated")?;
+        blocks.insert(cid.to_vec(), section[cid.len()..].to_vec());
+        pos += section_len;
+    }
+
+    Ok((roots, blocks))
+}
+
+fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Map(entries) => entries
+            .iter()
+            .find(|(k, _)| k.as_text() == Some(key))
+            .map(|(_, v)| v),
+        _ => None,
+    }
+}
+
+fn get_text<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    get(value, key).and_then(Value::as_text)
+}
+
+fn as_cid(value: &Value) -> Option<Vec<u8>> {
+    match value {
+        Value::Tag(CID_TAG, inner) => {
+            inner
+                .as_bytes()
+                .and_then(|bytes| match bytes.split_first() {
+                    Some((0, cid)) => Some(cid.to_vec()),
+                    _ => None,
+                })
+        }
+        _ => None,
+    }
+}
+
+fn decode_block(blocks: &Blocks, cid: &[u8]) -> anyhow::Result<Value> {
+    let data = blocks
+        .get(cid)
+        .context("CAR file is missing a referenced block")?;
+    ciborium::from_reader(data.as_slice()).context("Failed to decode CAR block")
+}
+
+/// Walks the repo's Merkle Search Tree in key order, collecting record keys and CIDs.
+fn walk_mst(
+    blocks: &Blocks,
+    cid: &[u8],
+    records: &mut Vec<(String, Vec<u8>)>,
+) -> anyhow::Result<()> {
+    let node = decode_block(blocks, cid)?;
+
+    if let Some(left) = get(&node, "l").and_then(as_cid) {
+        walk_mst(blocks, &left, records)?;
+    }
+
+    let mut key: Vec<u8> = Vec::new();
+    for entry in get(&node, "e")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let prefix_len = get(entry, "p")
+            .and_then(Value::as_integer)
+            .and_then(|p| usize::try_from(p).ok())
+            .unwrap_or(0);
+        key.truncate(prefix_len);
+        key.extend(
+            get(entry, "k")
+                .and_then(Value::as_bytes)
+                .context("MST entry has no key")?,
+        );
+
+        let value = get(entry, "v")
+            .and_then(as_cid)
+            .context("MST entry has no value")?;
+        records.push((String::from_utf8(key.clone())?, value));
+
+        if let Some(tree) = get(entry, "t").and_then(as_cid) {
+            walk_mst(blocks, &tree, records)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn post_permalink(did: &str, rkey: &str) -> String {
+    format!("https://bsky.app/profile/{did}/post/{rkey}")
+}
+
+/// Converts an at://did/app.bsky.feed.post/rkey URI into its bsky.app permalink.
+fn at_uri_to_permalink(uri: &str) -> Option<String> {
+    let (did, rkey) = uri
+        .strip_prefix("at://")?
+        .split_once(&format!("/{POST_COLLECTION}/"))?;
+    Some(post_permalink(did, rkey))
+}
+
+fn parse_post(record: &Value, permalink: String) -> SocialPost {
+    let mut urls = Vec::new();
+    let mut tags = Vec::new();
+
+    for facet in get(record, "facets")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        for feature in get(facet, "features")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            match get_text(feature, "$type") {
+                Some("app.bsky.richtext.facet#link") => {
+                    if let Some(uri) = get_text(feature, "uri") {
+                        urls.push(uri.to_string());
+                    }
+                }
+                Some("app.bsky.richtext.facet#tag") => {
+                    if let Some(tag) = get_text(feature, "tag") {
+                        tags.push(tag.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(uri) = get(record, "embed")
+        .and_then(|embed| get(embed, "external"))
+        .and_then(|external| get_text(external, "uri"))
+    {
+        urls.push(uri.to_string());
+    }
+    let mut seen = HashSet::new();
+    urls.retain(|url| seen.insert(url.clone()));
+
+    SocialPost {
+        permalink,
+        text: get_text(record, "text").unwrap_or_default().to_string(),
+        urls,
+        tags,
+        saved: false,
+    }
+}
+
+fn parse_repo(bytes: &[u8]) -> anyhow::Result<Vec<SocialPost>> {
+    let (roots, blocks) = read_car(bytes)?;
+    let commit = decode_block(&blocks, roots.first().context("CAR file has no root")?)?;
+    let did = get_text(&commit, "did").context("Repo commit has no DID")?;
+    let data = get(&commit, "data")
+        .and_then(as_cid)
+        .context("Repo commit has no data")?;
+
+    let mut records = Vec::new();
+    walk_mst(&blocks, &data, &mut records)?;
+
+    let mut posts = Vec::new();
+    for (key, cid) in records {
+        let Some((collection, rkey)) = key.split_once('/') else {
+            continue;
+        };
+        match collection {
+            POST_COLLECTION => {
+                let record = decode_block(&blocks, &cid)?;
+                posts.push(parse_post(&record, post_permalink(did, rkey)));
+            }
+            LIKE_COLLECTION => {
+                let record = decode_block(&blocks, &cid)?;
+                if let Some(permalink) = get(&record, "subject")
+                    .and_then(|subject| get_text(subject, "uri"))
+                    .and_then(at_uri_to_permalink)
+                {
+                    posts.push(SocialPost {
+                        permalink,
+                        text: String::new(),
+                        urls: Vec::new(),
+                        tags: Vec::new(),
+                        saved: true,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(posts)
+}
+
+pub fn import_bluesky(car_path: &str) -> anyhow::Result<()> {
+    let bytes = std::fs::read(car_path).with_context(|| format!("Failed to read {car_path}"))?;
+    let posts = parse_repo(&bytes).context("Failed to parse Bluesky repo export")?;
+
+    let bluesky_links: Vec<SerializedLink> = posts
+        .into_iter()
+        .flat_map(|post| post.into_links(LinkSource::Bluesky))
+        .collect();
+
+    progress!("Found {} Bluesky links", bluesky_links.len());
+
+    let found = bluesky_links.len();
+    let (links, stats) = merge_source(
+        read_links()?,
+        LinkSource::Bluesky,
+        resolve_aliases(bluesky_links)?,
+    );
+    write_links(&links)?;
+
+    emit(&ImportSummary::new(LinkSource::Bluesky, found, &stats))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cid(n: u8) -> Vec<u8> {
+        let mut cid = vec![0x01, 0x71, 0x12, 0x20];
+        cid.extend([n; 32]);
+        cid
+    }
+
+    fn link(cid: &[u8]) -> Value {
+        let mut bytes = vec![0];
+        bytes.extend(cid);
+        Value::Tag(CID_TAG, Box::new(Value::Bytes(bytes)))
+    }
+
+    fn map(entries: Vec<(&str, Value)>) -> Value {
+        Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Value::Text(k.to_string()), v))
+                .collect(),
+        )
+    }
+
+    fn text(s: &str) -> Value {
+        Value::Text(s.to_string())
+    }
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn push_varint(out: &mut Vec<u8>, mut value: usize) {
+        while value >= 0x80 {
+            out.push((value as u8 & 0x7f) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn car(root: &[u8], blocks: Vec<(Vec<u8>, Value)>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let header = encode(&map(vec![
+            ("version", Value::Integer(1.into())),
+            ("roots", Value::Array(vec![link(root)])),
+        ]));
+        push_varint(&mut out, header.len());
+        out.extend(header);
+        for (cid, value) in blocks {
+            let data = encode(&value);
+            push_varint(&mut out, cid.len() + data.len());
+            out.extend(cid);
+            out.extend(data);
+        }
+        out
+    }
+
+    #[test]
+    fn test_parse_repo_posts_and_likes() -> anyhow::Result<()> {
+        let post = map(vec![
+            ("$type", text("app.bsky.feed.post")),
+            ("text", text("Worth reading #rust")),
+            (
+                "facets",
+                Value::Array(vec![map(vec![(
+                    "features",
+                    Value::Array(vec![
+                        map(vec![
+                            ("$type", text("app.bsky.richtext.facet#link")),
+                            ("uri", text("https://example.com/article")),
+                        ]),
+                        map(vec![
+                            ("$type", text("app.bsky.richtext.facet#tag")),
+                            ("tag", text("rust")),
+                        ]),
+                    ]),
+                )])]),
+            ),
+        ]);
+        let like = map(vec![
+            ("$type", text("app.bsky.feed.like")),
+            (
+                "subject",
+                map(vec![(
+                    "uri",
+                    text("at://did:plc:other/app.bsky.feed.post/3kother"),
+                )]),
+            ),
+        ]);
+        let subtree = map(vec![
+            ("l", Value::Null),
+            (
+                "e",
+                Value::Array(vec![map(vec![
+                    ("p", Value::Integer(0.into())),
+                    ("k", Value::Bytes(b"app.bsky.feed.post/3kpost".to_vec())),
+                    ("v", link(&cid(4))),
+                    ("t", Value::Null),
+                ])]),
+            ),
+        ]);
+        let root_node = map(vec![
+            ("l", link(&cid(3))),
+            (
+                "e",
+                Value::Array(vec![map(vec![
+                    ("p", Value::Integer(0.into())),
+                    ("k", Value::Bytes(b"app.bsky.feed.like/3klike".to_vec())),
+                    ("v", link(&cid(5))),
+                    ("t", Value::Null),
+                ])]),
+            ),
+        ]);
+        let commit = map(vec![
+            ("did", text("did:plc:me")),
+            ("version", Value::Integer(3.into())),
+            ("data", link(&cid(2))),
+        ]);
+
+        let bytes = car(
+            &cid(1),
+            vec![
+                (cid(1), commit),
+                (cid(2), root_node),
+                (cid(3), subtree),
+                (cid(4), post),
+                (cid(5), like),
+            ],
+        );
+
+        let posts = parse_repo(&bytes)?;
+
+        assert_eq!(
+            posts,
+            vec![
+                SocialPost {
+                    permalink: "https://bsky.app/profile/did:plc:me/post/3kpost".to_string(),
+                    text: "Worth reading #rust".to_string(),
+                    urls: vec!["https://example.com/article".to_string()],
+                    tags: vec!["rust".to_string()],
+                    saved: false,
+                },
+                SocialPost {
+                    permalink: "https://bsky.app/profile/did:plc:other/post/3kother".to_string(),
+                    text: String::new(),
+                    urls: Vec::new(),
+                    tags: Vec::new(),
+                    saved: true,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_car_rejects_corrupt_files() {
+        let root = vec![0x01, 0x71, 0x12, 0x02, 0xaa, 0xbb];
+        let valid = car(&root, vec![(root.clone(), map(Vec::new()))]);
+        assert!(read_car(&valid).is_ok());
+
+        // Cut off partway through the block
+        assert!(read_car(&valid[..valid.len() - 3]).is_err());
+
+        // A CIDv0 prefix longer than the block it starts
+        let mut short_cid = car(&root, Vec::new());
+        push_varint(&mut short_cid, 4);
+        short_cid.extend([0x12, 0x20, 0x01, 0x02]);
+        assert!(read_car(&short_cid).is_err());
+
+        // A block length that overflows the position
+        let mut huge = car(&root, Vec::new());
+        push_varint(&mut huge, usize::MAX);
+        assert!(read_car(&huge).is_err());
+    }
+
+    #[test]
+    fn test_at_uri_to_permalink_rejects_other_collections() {
+        assert_eq!(
+            at_uri_to_permalink("at://did:plc:me/app.bsky.feed.generator/abc"),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::Context;
+
+use crate::links::{merge_source, read_links, resolve_aliases, write_links, ImportSummary};
+use crate::models::{GitHubRepo, GitHubStar, LinkSource, SerializedLink};
+use crate::output::{emit, progress};
+
+const PER_PAGE: usize = 100;
+
+fn parse_starred(json: &str) -> anyhow::Result<Vec<GitHubRepo>> {
+    let stars: Vec<GitHubStar> =
+        serde_json::from_str(json).context("Failed to parse starred repositories")?;
+    Ok(stars.into_iter().map(GitHubRepo::from).collect())
+}
+
+fn fetch_starred(api_base: &str, user: &str, verbose: bool) -> anyhow::Result<Vec<GitHubRepo>> {
+    let token = std::env::var("GITHUB_TOKEN").ok();
+
+    let mut repos = Vec::new();
+    for page in 1.. {
+        let url = format!("{api_base}/users/{user}/starred?per_page={PER_PAGE}&page={page}");
+        if verbose {
+            progress!("Fetching {url}");
+        }
+
+        let mut request = ureq::get(&url)
+            .header("Accept", "application/vnd.github.star+json")
+            .header("User-Agent", "sync_bookmarks");
+        if let Some(token) = &token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+
+        let body = request
+            .call()
+            .context("Failed to call GitHub API")?
+            .body_mut()
+            .read_to_string()
+            .context("Failed to read GitHub API response")?;
+
+        let page_repos = parse_starred(&body)?;
+        let is_last_page = page_repos.len() < PER_PAGE;
+        repos.extend(page_repos);
+
+        if is_last_page {
+            break;
+        }
+    }
+
+    Ok(repos)
+}
+
+/// Imports starred repositories from `stars_json` if given, otherwise from the API for `user`.
+pub fn import_github(
+    user: Option<&str>,
+    stars_json: Option<&str>,
+    api_base: &str,
+    verbose: bool,
+) -> anyhow::Result<()> {
+    let repos = match (stars_json, user) {
+        (Some(path), _) => parse_starred(
+            &std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?,
+        )?,
+        (None, Some(user)) => fetch_starred(api_base, user, verbose)?,
+        (None, None) => return Ok(()),
+    };
+
+    progress!("Found {} starred GitHub repositories", repos.len());
+
+    let github_links: Vec<SerializedLink> = repos.into_iter().map(SerializedLink::from).collect();
+
+    let found = github_links.len();
+    let (links, stats) = merge_source(
+        read_links()?,
+        LinkSource::GitHub,
+        resolve_aliases(github_links)?,
+    );
+    write_links(&links)?;
+
+    emit(&ImportSummary::new(LinkSource::GitHub, found, &stats))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_starred_accepts_both_shapes() -> anyhow::Result<()> {
+        let json = r#"[
+            {
+                "starred_at": "2024-01-01T00:00:00Z",
+                "repo": {
+                    "full_name": "rust-lang/rust",
+                    "html_url": "https://github.com/rust-lang/rust",
+                    "description": "Empowering everyone to build reliable and efficient software.",
+                    "language": "Rust",
+                    "topics": ["compiler", "rust"]
+                }
+            },
+            {
+                "full_name": "someone/dotfiles",
+                "html_url": "https://github.com/someone/dotfiles",
+                "description": null,
+                "language": null
+            }
+        ]"#;
+
+        let repos = parse_starred(json)?;
+
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0].full_name, "rust-lang/rust");
+        assert_eq!(repos[1].topics, Vec::<String>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repo_to_link_maps_topics_language_and_description() {
+        let repo = GitHubRepo {
+            full_name: "rust-lang/rust".to_string(),
+            html_url: "https://github.com/rust-lang/rust".to_string(),
+            description: Some("A language".to_string()),
+            language: Some("Rust".to_string()),
+            topics: vec!["compiler".to_string(), "rust".to_string()],
+        };
+
+        let link = SerializedLink::from(repo);
+
+        assert_eq!(link.url, "https://github.com/rust-lang/rust");
+        assert_eq!(link.title, "rust-lang/rust: A language");
+        assert_eq!(link.note.as_deref(), Some("A language"));
+        assert_eq!(link.tags, vec!["compiler", "rust"]);
+        assert_eq!(link.source, LinkSource::GitHub);
+    }
+
+    #[test]
+    fn test_repo_without_description_uses_name() {
+        let repo = GitHubRepo {
+            full_name: "someone/dotfiles".to_string(),
+            html_url: "https://github.com/someone/dotfiles".to_string(),
+            description: Some(" ".to_string()),
+            language: Some("Shell".to_string()),
+            topics: Vec::new(),
+        };
+
+        let link = SerializedLink::from(repo);
+
+        assert_eq!(link.title, "someone/dotfiles");
+        assert_eq!(link.note, None);
+        assert_eq!(link.tags, vec!["shell"]);
+    }
+}
@@ -1,9 +1,11 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use anyhow::Context;
 
 use crate::cache::{Cache, CacheType};
+use crate::links::{read_aliases, ImportSummary};
 use crate::models::{GoodLinksApiResponse, LinkSource, SerializedLink};
+use crate::output::{emit, progress};
 
 const GOODLINKS_OP_BASE_URL: &str = "op://Private/GoodLinks/base_url";
 const GOODLINKS_OP_TOKEN: &str = "op://Private/GoodLinks/token";
@@ -35,7 +37,7 @@ fn filter_removed_goodlinks(
 pub fn import_goodlinks(verbose: bool) -> anyhow::Result<()> {
     let base_url = read_op_secret(GOODLINKS_OP_BASE_URL)?;
     if verbose {
-        println!("GoodLinks base URL: {base_url}");
+        progress!("GoodLinks base URL: {base_url}");
     }
     let token = read_op_secret(GOODLINKS_OP_TOKEN)?;
 
@@ -64,7 +66,14 @@ pub fn import_goodlinks(verbose: bool) -> anyhow::Result<()> {
         offset += LIMIT;
     }
 
-    println!("Found {} read GoodLinks links", api_links.len());
+    progress!("Found {} read GoodLinks links", api_links.len());
+
+    let aliases = read_aliases()?;
+    for link in &mut api_links {
+        if let Some(canonical) = aliases.get(&link.url) {
+            link.url = canonical.clone();
+        }
+    }
 
     // Load cached URLs to skip already-fetched links
     let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
@@ -73,18 +82,32 @@ pub fn import_goodlinks(verbose: bool) -> anyhow::Result<()> {
     let api_urls: HashSet<_> = api_links.iter().map(|link| link.url.clone()).collect();
 
     // Load existing links.json, removing GoodLinks entries no longer in the API response
-    let mut serialized_links: Vec<SerializedLink> = match std::fs::read_to_string("links.json") {
-        Ok(contents) => {
-            filter_removed_goodlinks(serde_json::from_str(&contents)?, &api_urls)
-        }
+    let existing: Vec<SerializedLink> = match std::fs::read_to_string("links.json") {
+        Ok(contents) => serde_json::from_str(&contents)?,
         Err(_) => Vec::new(),
     };
+    let existing_count = existing.len();
+    let mut serialized_links = filter_removed_goodlinks(existing, &api_urls);
+    let removed = existing_count - serialized_links.len();
+
+    // Fill in read dates on entries imported before they were recorded
+    let read_dates: HashMap<_, _> = api_links
+        .iter()
+        .filter_map(|link| Some((link.url.clone(), link.read_at.clone()?)))
+        .collect();
+    for link in serialized_links
+        .iter_mut()
+        .filter(|link| link.source == LinkSource::GoodLinks && link.read_at.is_none())
+    {
+        link.read_at = read_dates.get(&link.url).cloned();
+    }
 
     let serialized_link_urls: HashSet<_> = serialized_links
         .iter()
         .map(|link| link.url.clone())
         .collect();
 
+    let found = api_links.len();
     let mut already_cached_skipped = 0;
     let mut already_serialized_skipped = 0;
     let mut serialized = 0;
@@ -110,9 +133,13 @@ pub fn import_goodlinks(verbose: bool) -> anyhow::Result<()> {
     serde_json::to_writer_pretty(serialized_links_file, &serialized_links)
         .context("Failed to write to links.json")?;
 
-    println!(
-        "Serialized {serialized} GoodLinks links; skipped {already_cached_skipped} already cached and {already_serialized_skipped} already in links.json"
-    );
+    emit(&ImportSummary {
+        source: LinkSource::GoodLinks,
+        found,
+        added: serialized,
+        skipped: already_cached_skipped + already_serialized_skipped,
+        removed,
+    })?;
 
     Ok(())
 }
@@ -122,21 +149,11 @@ mod tests {
     use super::*;
 
     fn goodlinks_link(url: &str) -> SerializedLink {
-        SerializedLink {
-            url: url.to_string(),
-            title: url.to_string(),
-            tags: Vec::new(),
-            source: LinkSource::GoodLinks,
-        }
+        SerializedLink::new(url.to_string(), url.to_string(), Vec::new(), LinkSource::GoodLinks)
     }
 
     fn obsidian_link(url: &str) -> SerializedLink {
-        SerializedLink {
-            url: url.to_string(),
-            title: url.to_string(),
-            tags: Vec::new(),
-            source: LinkSource::Obsidian,
-        }
+        SerializedLink::new(url.to_string(), url.to_string(), Vec::new(), LinkSource::Obsidian)
     }
 
     #[test]
@@ -166,6 +183,21 @@ mod tests {
         assert_eq!(result.len(), 2);
     }
 
+    #[test]
+    fn test_preserves_manual_entries_not_in_api() {
+        let existing = vec![SerializedLink::new(
+            "https://manual.example.com".to_string(),
+            "Manual".to_string(),
+            Vec::new(),
+            LinkSource::Manual,
+        )];
+
+        let result = filter_removed_goodlinks(existing, &HashSet::new());
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, LinkSource::Manual);
+    }
+
     #[test]
     fn test_empty_api_removes_all_goodlinks_entries() {
         let existing = vec![
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cache::{Cache, CacheType};
+use crate::models::Annotation;
+use crate::output::{emit, progress, Report};
+
+#[derive(Serialize)]
+struct AnnotationSummary {
+    found: usize,
+    attached: usize,
+    /// Annotations on pages that are not cached
+    unmatched: usize,
+}
+
+impl Report for AnnotationSummary {
+    const KIND: &'static str = "annotations";
+
+    fn text(&self) -> String {
+        format!(
+            "Attached {} annotations to cached links; {} had no cached link",
+            self.attached, self.unmatched
+        )
+    }
+}
+
+/// The first target of an annotation; Hypothesis uses an array, W3C allows a bare object.
+fn first_target(annotation: &Value) -> Option<&Value> {
+    match annotation.get("target")? {
+        Value::Array(targets) => targets.first(),
+        target => Some(target),
+    }
+}
+
+fn selectors(target: Option<&Value>) -> Vec<&Value> {
+    match target.and_then(|target| target.get("selector")) {
+        Some(Value::Array(selectors)) => selectors.iter().collect(),
+        Some(selector) => vec![selector],
+        None => Vec::new(),
+    }
+}
+
+fn find_selector<'a>(selectors: &[&'a Value], kind: &str) -> Option<&'a Value> {
+    selectors
+        .iter()
+        .find(|selector| selector.get("type").and_then(Value::as_str) == Some(kind))
+        .copied()
+}
+
+/// W3C bodies carry the note as a commenting TextualBody and tags as tagging bodies.
+fn bodies(annotation: &Value) -> Vec<&Value> {
+    match annotation.get("body") {
+        Some(Value::Array(bodies)) => bodies.iter().collect(),
+        Some(body) => vec![body],
+        None => Vec::new(),
+    }
+}
+
+fn parse_annotation(annotation: &Value) -> Option<Annotation> {
+    let id = annotation.get("id")?.as_str()?.to_string();
+    let target = first_target(annotation);
+    let url = annotation
+        .get("uri")
+        .or_else(|| target.and_then(|target| target.get("source")))
+        .or_else(|| target.filter(|target| target.is_string()))?
+        .as_str()?
+        .to_string();
+
+    let selectors = selectors(target);
+    let quote = find_selector(&selectors, "TextQuoteSelector")
+        .and_then(|selector| selector.get("exact"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    let position = find_selector(&selectors, "TextPositionSelector")
+        .and_then(|selector| selector.get("start"))
+        .and_then(Value::as_i64);
+
+    let bodies = bodies(annotation);
+    let note = annotation
+        .get("text")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            bodies
+                .iter()
+                .find(|body| body.get("purpose").and_then(Value::as_str) != Some("tagging"))
+                .and_then(|body| body.get("value"))
+                .and_then(Value::as_str)
+        })
+        .filter(|note| !note.trim().is_empty())
+        .map(str::to_string);
+
+    let tags = match annotation.get("tags").and_then(Value::as_array) {
+        Some(tags) => tags
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        None => bodies
+            .iter()
+            .filter(|body| body.get("purpose").and_then(Value::as_str) == Some("tagging"))
+            .filter_map(|body| body.get("value").and_then(Value::as_str))
+            .map(str::to_string)
+            .collect(),
+    };
+
+    if quote.is_none() && note.is_none() {
+        return None;
+    }
+
+    Some(Annotation {
+        id,
+        url,
+        quote,
+        note,
+        tags,
+        selector: Value::Array(selectors.into_iter().cloned().collect()),
+        position,
+        created_at: annotation
+            .get("created")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// Accepts a bare array, a Hypothesis API page (`rows`), a client export
+/// (`annotations`) or a W3C AnnotationCollection/Page (`items`, `first.items`).
+fn parse_annotations(json: &str) -> anyhow::Result<Vec<Annotation>> {
+    let export: Value = serde_json::from_str(json).context("Failed to parse annotation export")?;
+    let items = match &export {
+        Value::Array(items) => items,
+        _ => ["rows", "annotations", "items"]
+            .iter()
+            .find_map(|key| export.get(key))
+            .or_else(|| export.get("first").and_then(|page| page.get("items")))
+            .and_then(Value::as_array)
+            .context("Annotation export has no list of annotations")?,
+    };
+
+    Ok(items.iter().filter_map(parse_annotation).collect())
+}
+
+/// `url` without its fragment, which annotations on the same page may differ in.
+fn without_fragment(url: &str) -> String {
+    url.split_once('#').map_or(url, |(url, _)| url).to_string()
+}
+
+pub fn import_hypothesis(export_path: &str) -> anyhow::Result<()> {
+    let annotations = parse_annotations(
+        &std::fs::read_to_string(export_path)
+            .with_context(|| format!("Failed to read {export_path}"))?,
+    )?;
+
+    progress!("Found {} Hypothesis annotations", annotations.len());
+    let found = annotations.len();
+
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let cached_urls: HashMap<String, String> = cache
+        .query_url_owners()?
+        .into_iter()
+        .map(|(url, owner)| (without_fragment(&url), owner))
+        .collect();
+
+    let mut attached = 0;
+    let mut unmatched = 0;
+    for mut annotation in annotations {
+        let Some(cached_url) = cached_urls.get(&without_fragment(&annotation.url)) else {
+            unmatched += 1;
+            continue;
+        };
+        annotation.url = cached_url.clone();
+        cache.insert_annotation(&annotation)?;
+        attached += 1;
+    }
+
+    emit(&AnnotationSummary {
+        found,
+        attached,
+        unmatched,
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hypothesis_api_rows() -> anyhow::Result<()> {
+        let json = r#"{
+            "total": 2,
+            "rows": [
+                {
+                    "id": "abc",
+                    "created": "2024-05-01T10:00:00Z",
+                    "uri": "https://example.com/article?utm_source=feed",
+                    "text": "Interesting",
+                    "tags": ["history"],
+                    "target": [{
+                        "source": "https://example.com/article?utm_source=feed",
+                        "selector": [
+                            { "type": "TextPositionSelector", "start": 120, "end": 140 },
+                            { "type": "TextQuoteSelector", "exact": "a quoted passage", "prefix": "before ", "suffix": " after" }
+                        ]
+                    }]
+                },
+                {
+                    "id": "empty",
+                    "uri": "https://example.com/other",
+                    "text": "",
+                    "target": [{ "source": "https://example.com/other" }]
+                }
+            ]
+        }"#;
+
+        let annotations = parse_annotations(json)?;
+
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].id, "abc");
+        assert_eq!(
+            annotations[0].url,
+            "https://example.com/article?utm_source=feed"
+        );
+        assert_eq!(annotations[0].quote.as_deref(), Some("a quoted passage"));
+        assert_eq!(annotations[0].note.as_deref(), Some("Interesting"));
+        assert_eq!(annotations[0].tags, vec!["history"]);
+        assert_eq!(annotations[0].position, Some(120));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_w3c_annotation_collection() -> anyhow::Result<()> {
+        let json = r#"{
+            "@context": "http://www.w3.org/ns/anno.jsonld",
+            "type": "AnnotationCollection",
+            "first": {
+                "type": "AnnotationPage",
+                "items": [{
+                    "id": "urn:uuid:1",
+                    "type": "Annotation",
+                    "body": [
+                        { "type": "TextualBody", "value": "A comment", "purpose": "commenting" },
+                        { "type": "TextualBody", "value": "reading", "purpose": "tagging" }
+                    ],
+                    "target": {
+                        "source": "https://example.com/page",
+                        "selector": { "type": "TextQuoteSelector", "exact": "quoted" }
+                    }
+                }]
+            }
+        }"#;
+
+        let annotations = parse_annotations(json)?;
+
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0].url, "https://example.com/page");
+        assert_eq!(annotations[0].quote.as_deref(), Some("quoted"));
+        assert_eq!(annotations[0].note.as_deref(), Some("A comment"));
+        assert_eq!(annotations[0].tags, vec!["reading"]);
+        assert_eq!(annotations[0].position, None);
+
+        Ok(())
+    }
+}
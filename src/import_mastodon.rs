@@ -0,0 +1,270 @@
+use std::path::Path;
+
+use anyhow::Context;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::links::{merge_source, read_links, resolve_aliases, write_links, ImportSummary};
+use crate::models::{LinkSource, SerializedLink, SocialPost};
+use crate::output::{emit, progress};
+
+#[derive(Deserialize)]
+struct OrderedCollection<T> {
+    #[serde(rename = "orderedItems", default = "Vec::new")]
+    ordered_items: Vec<T>,
+}
+
+#[derive(Deserialize)]
+struct Activity {
+    #[serde(rename = "type")]
+    kind: String,
+    object: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Note {
+    id: String,
+    url: Option<String>,
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tag: Vec<NoteTag>,
+}
+
+#[derive(Deserialize)]
+struct NoteTag {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Regexes for reading post HTML, compiled once per archive.
+struct PostHtml {
+    break_regex: Regex,
+    tag_regex: Regex,
+    anchor_regex: Regex,
+    href_regex: Regex,
+    class_regex: Regex,
+}
+
+impl PostHtml {
+    fn new() -> anyhow::Result<Self> {
+        Ok(PostHtml {
+            break_regex: Regex::new(r"(?i)<br\s*/?>|</p>")?,
+            tag_regex: Regex::new(r"<[^>]*>")?,
+            anchor_regex: Regex::new(r#"<a\s[^>]*>"#)?,
+            href_regex: Regex::new(r#"href="([^"]+)""#)?,
+            class_regex: Regex::new(r#"class="[^"]*\b(mention|hashtag)\b[^"]*""#)?,
+        })
+    }
+
+    fn to_text(&self, html: &str) -> String {
+        let text = self.break_regex.replace_all(html, "\n");
+        let text = self.tag_regex.replace_all(&text, "");
+        decode_entities(text.trim())
+    }
+
+    /// Links in the post body, skipping the anchors Mastodon generates for mentions and hashtags.
+    fn links(&self, html: &str) -> Vec<String> {
+        let mut urls = Vec::new();
+        for anchor in self.anchor_regex.find_iter(html) {
+            if self.class_regex.is_match(anchor.as_str()) {
+                continue;
+            }
+            if let Some(href) = self.href_regex.captures(anchor.as_str()) {
+                let url = decode_entities(&href[1]);
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+        urls
+    }
+}
+
+fn parse_outbox(json: &str) -> anyhow::Result<Vec<SocialPost>> {
+    let outbox: OrderedCollection<Activity> =
+        serde_json::from_str(json).context("Failed to parse outbox.json")?;
+
+    let html = PostHtml::new()?;
+    let mut posts = Vec::new();
+    for activity in outbox.ordered_items {
+        // Boosts (Announce) only reference another post by URI
+        if activity.kind != "Create" {
+            continue;
+        }
+        let Ok(note) = serde_json::from_value::<Note>(activity.object) else {
+            continue;
+        };
+        posts.push(SocialPost {
+            permalink: note.url.unwrap_or(note.id),
+            text: html.to_text(&note.content),
+            urls: html.links(&note.content),
+            tags: note
+                .tag
+                .into_iter()
+                .filter(|tag| tag.kind == "Hashtag")
+                .map(|tag| tag.name.trim_start_matches('#').to_string())
+                .collect(),
+            saved: false,
+        });
+    }
+    Ok(posts)
+}
+
+/// bookmarks.json and likes.json only list the URIs of the saved posts.
+fn parse_uri_collection(json: &str) -> anyhow::Result<Vec<SocialPost>> {
+    let collection: OrderedCollection<String> =
+        serde_json::from_str(json).context("Failed to parse post URI collection")?;
+
+    Ok(collection
+        .ordered_items
+        .into_iter()
+        .map(|permalink| SocialPost {
+            permalink,
+            text: String::new(),
+            urls: Vec::new(),
+            tags: Vec::new(),
+            saved: true,
+        })
+        .collect())
+}
+
+pub fn import_mastodon(archive_dir: &str) -> anyhow::Result<()> {
+    let archive_dir = Path::new(archive_dir);
+
+    let mut posts = Vec::new();
+    for (file_name, parse) in [
+        (
+            "outbox.json",
+            parse_outbox as fn(&str) -> anyhow::Result<Vec<SocialPost>>,
+        ),
+        ("bookmarks.json", parse_uri_collection),
+        ("likes.json", parse_uri_collection),
+    ] {
+        let path = archive_dir.join(file_name);
+        if !path.exists() {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        posts.extend(parse(&contents).with_context(|| format!("Failed to parse {file_name}"))?);
+    }
+
+    let mastodon_links: Vec<SerializedLink> = posts
+        .into_iter()
+        .flat_map(|post| post.into_links(LinkSource::Mastodon))
+        .collect();
+
+    progress!("Found {} Mastodon links", mastodon_links.len());
+
+    let found = mastodon_links.len();
+    let (links, stats) = merge_source(
+        read_links()?,
+        LinkSource::Mastodon,
+        resolve_aliases(mastodon_links)?,
+    );
+    write_links(&links)?;
+
+    emit(&ImportSummary::new(LinkSource::Mastodon, found, &stats))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_outbox_extracts_links_text_and_hashtags() -> anyhow::Result<()> {
+        let json = r##"{
+            "orderedItems": [
+                {
+                    "type": "Create",
+                    "object": {
+                        "id": "https://mastodon.example/users/me/statuses/1",
+                        "url": "https://mastodon.example/@me/1",
+                        "content": "<p>Great read &amp; more <a href=\"https://example.com/post?a=1&amp;b=2\" rel=\"nofollow\"><span>example.com/post</span></a></p><p>cc <span class=\"h-card\"><a href=\"https://mastodon.example/@friend\" class=\"u-url mention\">@friend</a></span> <a href=\"https://mastodon.example/tags/rust\" class=\"mention hashtag\" rel=\"tag\">#rust</a></p>",
+                        "tag": [
+                            { "type": "Mention", "name": "@friend" },
+                            { "type": "Hashtag", "name": "#rust" }
+                        ]
+                    }
+                },
+                {
+                    "type": "Announce",
+                    "object": "https://other.example/statuses/2"
+                }
+            ]
+        }"##;
+
+        let posts = parse_outbox(json)?;
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].permalink, "https://mastodon.example/@me/1");
+        assert_eq!(posts[0].urls, vec!["https://example.com/post?a=1&b=2"]);
+        assert_eq!(posts[0].tags, vec!["rust"]);
+        assert_eq!(
+            posts[0].text,
+            "Great read & more example.com/post\ncc @friend #rust"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_own_posts_without_links_are_not_bookmarks() -> anyhow::Result<()> {
+        let json = r#"{
+            "orderedItems": [
+                {
+                    "type": "Create",
+                    "object": {
+                        "id": "https://mastodon.example/users/me/statuses/3",
+                        "content": "<p>Good morning</p>"
+                    }
+                }
+            ]
+        }"#;
+
+        let posts = parse_outbox(json)?;
+
+        assert_eq!(posts.len(), 1);
+        assert!(posts
+            .into_iter()
+            .flat_map(|post| post.into_links(LinkSource::Mastodon))
+            .next()
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bookmarks_link_to_the_post_itself() -> anyhow::Result<()> {
+        let json = r#"{ "orderedItems": ["https://other.example/@them/42"] }"#;
+
+        let links: Vec<SerializedLink> = parse_uri_collection(json)?
+            .into_iter()
+            .flat_map(|post| post.into_links(LinkSource::Mastodon))
+            .collect();
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url, "https://other.example/@them/42");
+        assert_eq!(links[0].title, "https://other.example/@them/42");
+        assert_eq!(links[0].note, None);
+        assert_eq!(
+            links[0].permalink.as_deref(),
+            Some("https://other.example/@them/42")
+        );
+
+        Ok(())
+    }
+}
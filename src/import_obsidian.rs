@@ -4,7 +4,9 @@ use regex::Regex;
 use std::collections::HashSet;
 use walkdir::{DirEntry, WalkDir};
 
+use crate::links::{read_aliases, ImportSummary};
 use crate::models::{LinkSource, ObsidianLink, SerializedLink};
+use crate::output::{emit, progress};
 
 fn parse_markdown_links(file_contents: &str) -> anyhow::Result<Vec<ObsidianLink>> {
     let mut obsidian_links = Vec::new();
@@ -31,18 +33,19 @@ fn parse_markdown_links(file_contents: &str) -> anyhow::Result<Vec<ObsidianLink>
             }) => {
                 current_link = Some(dest_url.to_string());
             }
-            Event::Text(text) | Event::Code(text) => {
-                if current_link.is_some() {
-                    current_link_title =
-                        Some(current_link_title.unwrap_or_default() + text.as_ref());
-                }
+            Event::Text(text) | Event::Code(text) if current_link.is_some() => {
+                current_link_title = Some(current_link_title.unwrap_or_default() + text.as_ref());
             }
             Event::End(TagEnd::Link) => {
                 if let Some(url) = current_link.clone() {
                     if let Some(title) = current_link_title.clone() {
                         current_link = None;
                         current_link_title = None;
-                        obsidian_links.push(ObsidianLink { title, url });
+                        obsidian_links.push(ObsidianLink {
+                            title,
+                            url,
+                            note_path: None,
+                        });
                     }
                 }
             }
@@ -63,6 +66,7 @@ fn parse_markdown_links(file_contents: &str) -> anyhow::Result<Vec<ObsidianLink>
         obsidian_links.push(ObsidianLink {
             title: url.clone(),
             url: url.clone(),
+            note_path: None,
         });
         parsed_urls.insert(url);
     }
@@ -84,8 +88,17 @@ fn process_markdown_files(directory: &str) -> anyhow::Result<Vec<ObsidianLink>>
         .filter(is_markdown_file)
         .try_fold(Vec::new(), |mut acc, entry| {
             let file_contents = std::fs::read_to_string(entry.path())?;
+            let note_path = entry
+                .path()
+                .strip_prefix(directory)
+                .unwrap_or(entry.path())
+                .to_string_lossy()
+                .into_owned();
             let links = parse_markdown_links(file_contents.as_ref())?;
-            acc.extend(links);
+            acc.extend(links.into_iter().map(|link| ObsidianLink {
+                note_path: Some(note_path.clone()),
+                ..link
+            }));
             Ok(acc)
         })
 }
@@ -98,21 +111,30 @@ pub fn import_obsidian() -> anyhow::Result<()> {
         "{home}/Documents/Obsidian Vaults/mochi"
     ))?);
 
-    println!("Found {} Obsidian links", obsidian_links.len());
+    progress!("Found {} Obsidian links", obsidian_links.len());
+    let found = obsidian_links.len();
+
+    let aliases = read_aliases()?;
+    for link in &mut obsidian_links {
+        if let Some(canonical) = aliases.get(&link.url) {
+            link.url = canonical.clone();
+        }
+    }
 
     let obsidian_urls: HashSet<_> = obsidian_links.iter().map(|link| link.url.clone()).collect();
 
-    let mut serialized_links: Vec<SerializedLink> = match std::fs::read_to_string("links.json") {
+    let existing: Vec<SerializedLink> = match std::fs::read_to_string("links.json") {
         Ok(serialized_links_file_contents) => {
-            serde_json::from_str::<Vec<SerializedLink>>(&serialized_links_file_contents)?
-                .into_iter()
-                .filter(|link| {
-                    obsidian_urls.contains(&link.url) || link.source != LinkSource::Obsidian
-                })
-                .collect()
+            serde_json::from_str(&serialized_links_file_contents)?
         }
         Err(_) => Vec::new(),
     };
+    let existing_count = existing.len();
+    let mut serialized_links: Vec<SerializedLink> = existing
+        .into_iter()
+        .filter(|link| obsidian_urls.contains(&link.url) || link.source != LinkSource::Obsidian)
+        .collect();
+    let removed = existing_count - serialized_links.len();
 
     let serialized_link_urls: HashSet<_> = serialized_links
         .iter()
@@ -138,7 +160,13 @@ pub fn import_obsidian() -> anyhow::Result<()> {
     serde_json::to_writer_pretty(serialized_links_file, &serialized_links)
         .context("Failed to write to links.json")?;
 
-    println!("Serialized {serialized} Obsidian links; {already_serialized_skipped} links already serialized");
+    emit(&ImportSummary {
+        source: LinkSource::Obsidian,
+        found,
+        added: serialized,
+        skipped: already_serialized_skipped,
+        removed,
+    })?;
 
     Ok(())
 }
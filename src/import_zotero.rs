@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use rusqlite::{Connection, OpenFlags};
+
+use crate::links::{merge_source, read_links, resolve_aliases, write_links, ImportSummary};
+use crate::models::{LinkSource, SerializedLink, ZoteroItem};
+use crate::output::{emit, progress};
+
+const EXCLUDED_ITEM_TYPES: &str = "('attachment', 'note', 'annotation')";
+
+/// Opens zotero.sqlite without taking a lock, so the import works while Zotero is running.
+fn open_zotero_db(path: &Path) -> anyhow::Result<Connection> {
+    let absolute = std::path::absolute(path)
+        .with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let mut uri = url::Url::from_file_path(&absolute)
+        .map_err(|_| anyhow::anyhow!("{} is not a valid file path", path.display()))?;
+    uri.set_query(Some("immutable=1"));
+    Connection::open_with_flags(
+        uri.as_str(),
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+    )
+    .with_context(|| format!("Failed to open Zotero database {}", path.display()))
+}
+
+fn query_zotero_items(conn: &Connection, storage_dir: &Path) -> anyhow::Result<Vec<ZoteroItem>> {
+    let mut items: HashMap<i64, ZoteroItem> = HashMap::new();
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT items.itemID, fields.fieldName, itemDataValues.value
+            FROM items
+            JOIN itemTypes USING (itemTypeID)
+            JOIN itemData USING (itemID)
+            JOIN fields USING (fieldID)
+            JOIN itemDataValues USING (valueID)
+            WHERE itemTypes.typeName NOT IN {EXCLUDED_ITEM_TYPES}
+                AND items.itemID NOT IN (SELECT itemID FROM deletedItems)
+                AND fields.fieldName IN ('title', 'url', 'DOI')"
+        ))
+        .context("Failed to prepare Zotero item query")?;
+    let mut rows = stmt.query([]).context("Failed to query Zotero items")?;
+    while let Some(row) = rows.next()? {
+        let item = items.entry(row.get(0)?).or_insert_with(|| ZoteroItem {
+            title: String::new(),
+            url: None,
+            doi: None,
+            authors: Vec::new(),
+            collections: Vec::new(),
+            attachments: Vec::new(),
+        });
+        let value: String = row.get(2)?;
+        match row.get_ref(1)?.as_str()? {
+            "title" => item.title = value,
+            "url" => item.url = Some(value),
+            "DOI" => item.doi = Some(value),
+            _ => {}
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT itemCreators.itemID, creators.firstName, creators.lastName
+            FROM itemCreators
+            JOIN creators USING (creatorID)
+            ORDER BY itemCreators.itemID, itemCreators.orderIndex",
+        )
+        .context("Failed to prepare Zotero creator query")?;
+    let mut rows = stmt.query([]).context("Failed to query Zotero creators")?;
+    while let Some(row) = rows.next()? {
+        if let Some(item) = items.get_mut(&row.get(0)?) {
+            let first_name: Option<String> = row.get(1)?;
+            let last_name: Option<String> = row.get(2)?;
+            let name = [first_name, last_name]
+                .into_iter()
+                .flatten()
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            item.authors.push(name);
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT collectionItems.itemID, collections.collectionName
+            FROM collectionItems
+            JOIN collections USING (collectionID)
+            ORDER BY collections.collectionName",
+        )
+        .context("Failed to prepare Zotero collection query")?;
+    let mut rows = stmt
+        .query([])
+        .context("Failed to query Zotero collections")?;
+    while let Some(row) = rows.next()? {
+        if let Some(item) = items.get_mut(&row.get(0)?) {
+            item.collections.push(row.get(1)?);
+        }
+    }
+
+    // Stored attachments live at storage/<attachment key>/<file name>
+    let mut stmt = conn
+        .prepare(
+            "SELECT itemAttachments.parentItemID, items.key, itemAttachments.path
+            FROM itemAttachments
+            JOIN items USING (itemID)
+            WHERE itemAttachments.parentItemID IS NOT NULL
+                AND itemAttachments.path LIKE 'storage:%'
+                AND items.itemID NOT IN (SELECT itemID FROM deletedItems)",
+        )
+        .context("Failed to prepare Zotero attachment query")?;
+    let mut rows = stmt
+        .query([])
+        .context("Failed to query Zotero attachments")?;
+    while let Some(row) = rows.next()? {
+        if let Some(item) = items.get_mut(&row.get(0)?) {
+            let key: String = row.get(1)?;
+            let path: String = row.get(2)?;
+            let file_name = path.trim_start_matches("storage:");
+            item.attachments.push(
+                storage_dir
+                    .join(key)
+                    .join(file_name)
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+    }
+
+    let mut items: Vec<(i64, ZoteroItem)> = items.into_iter().collect();
+    items.sort_by_key(|(id, _)| *id);
+    Ok(items.into_iter().map(|(_, item)| item).collect())
+}
+
+pub fn import_zotero(zotero_dir: Option<String>) -> anyhow::Result<()> {
+    let zotero_dir = match zotero_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            PathBuf::from(std::env::var("HOME").context("HOME env var not set")?).join("Zotero")
+        }
+    };
+    let db_path = zotero_dir.join("zotero.sqlite");
+    if !db_path.exists() {
+        progress!("No Zotero database at {}; skipping", db_path.display());
+        return Ok(());
+    }
+
+    let conn = open_zotero_db(&db_path)?;
+    let items = query_zotero_items(&conn, &zotero_dir.join("storage"))?;
+
+    let zotero_links: Vec<SerializedLink> = items
+        .into_iter()
+        .filter_map(|item| item.try_into().ok())
+        .collect();
+
+    progress!(
+        "Found {} Zotero items with a URL or DOI",
+        zotero_links.len()
+    );
+
+    let found = zotero_links.len();
+    let (links, stats) = merge_source(
+        read_links()?,
+        LinkSource::Zotero,
+        resolve_aliases(zotero_links)?,
+    );
+    write_links(&links)?;
+
+    emit(&ImportSummary::new(LinkSource::Zotero, found, &stats))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zotero_fixture() -> anyhow::Result<Connection> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE itemTypes (itemTypeID INTEGER PRIMARY KEY, typeName TEXT);
+            CREATE TABLE items (itemID INTEGER PRIMARY KEY, itemTypeID INT, key TEXT);
+            CREATE TABLE fields (fieldID INTEGER PRIMARY KEY, fieldName TEXT);
+            CREATE TABLE itemDataValues (valueID INTEGER PRIMARY KEY, value);
+            CREATE TABLE itemData (itemID INT, fieldID INT, valueID INT);
+            CREATE TABLE creators (creatorID INTEGER PRIMARY KEY, firstName TEXT, lastName TEXT);
+            CREATE TABLE itemCreators (itemID INT, creatorID INT, orderIndex INT);
+            CREATE TABLE collections (collectionID INTEGER PRIMARY KEY, collectionName TEXT);
+            CREATE TABLE collectionItems (collectionID INT, itemID INT);
+            CREATE TABLE itemAttachments (itemID INTEGER PRIMARY KEY, parentItemID INT, path TEXT);
+            CREATE TABLE deletedItems (itemID INTEGER PRIMARY KEY);
+
+            INSERT INTO itemTypes VALUES (1, 'journalArticle'), (2, 'attachment'), (3, 'webpage');
+            INSERT INTO fields VALUES (1, 'title'), (2, 'url'), (3, 'DOI'), (4, 'abstractNote');
+            INSERT INTO items VALUES (1, 1, 'PAPERKEY'), (2, 2, 'SNAPKEY'), (3, 3, 'WEBKEY'), (4, 3, 'GONEKEY');
+            INSERT INTO itemDataValues VALUES
+                (1, 'A Paper'), (2, '10.1000/xyz'), (3, 'A Page'),
+                (4, 'https://example.com/page'), (5, 'Deleted'), (6, 'https://example.com/deleted');
+            INSERT INTO itemData VALUES (1, 1, 1), (1, 3, 2), (3, 1, 3), (3, 2, 4), (4, 1, 5), (4, 2, 6);
+            INSERT INTO creators VALUES (1, 'Ada', 'Lovelace'), (2, 'Alan', 'Turing');
+            INSERT INTO itemCreators VALUES (1, 2, 1), (1, 1, 0);
+            INSERT INTO collections VALUES (1, 'Computing'), (2, 'History');
+            INSERT INTO collectionItems VALUES (2, 1), (1, 1);
+            INSERT INTO itemAttachments VALUES (2, 3, 'storage:page.html');
+            INSERT INTO deletedItems VALUES (4);",
+        )?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn test_query_zotero_items() -> anyhow::Result<()> {
+        let conn = zotero_fixture()?;
+
+        let items = query_zotero_items(&conn, Path::new("/zotero/storage"))?;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "A Paper");
+        assert_eq!(items[0].doi.as_deref(), Some("10.1000/xyz"));
+        assert_eq!(items[0].url, None);
+        assert_eq!(items[0].authors, vec!["Ada Lovelace", "Alan Turing"]);
+        assert_eq!(items[0].collections, vec!["Computing", "History"]);
+        assert_eq!(items[1].url.as_deref(), Some("https://example.com/page"));
+        assert_eq!(
+            items[1].attachments,
+            vec!["/zotero/storage/SNAPKEY/page.html"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_doi_only_item_links_to_resolver() {
+        let item = ZoteroItem {
+            title: "A Paper".to_string(),
+            url: None,
+            doi: Some("10.1000/xyz".to_string()),
+            authors: vec!["Ada Lovelace".to_string()],
+            collections: vec!["Computing".to_string()],
+            attachments: Vec::new(),
+        };
+
+        let link: SerializedLink = item.try_into().unwrap();
+
+        assert_eq!(link.url, "https://doi.org/10.1000/xyz");
+        assert_eq!(link.tags, vec!["Computing"]);
+        assert_eq!(link.source, LinkSource::Zotero);
+    }
+
+    #[test]
+    fn test_open_zotero_db_with_uri_characters_in_path() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("zotero #1?%-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("zotero.sqlite");
+        Connection::open(&path)?.execute_batch("CREATE TABLE items (itemID INTEGER)")?;
+
+        let count: i64 =
+            open_zotero_db(&path)?.query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))?;
+        assert_eq!(count, 0);
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
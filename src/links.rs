@@ -0,0 +1,301 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+
+use crate::cache::{Cache, CacheType};
+use crate::models::{LinkSource, SerializedLink};
+use crate::output::Report;
+
+pub const LINKS_FILE: &str = "links.json";
+
+pub struct MergeStats {
+    pub serialized: usize,
+    pub already_serialized: usize,
+    pub removed: usize,
+}
+
+/// What importing one source changed in links.json.
+#[derive(serde::Serialize)]
+pub struct ImportSummary {
+    pub source: LinkSource,
+    /// Links found in the source
+    pub found: usize,
+    /// Links added to links.json
+    pub added: usize,
+    /// Links already in links.json or the cache
+    pub skipped: usize,
+    /// Links of this source dropped because the source no longer has them
+    pub removed: usize,
+}
+
+impl ImportSummary {
+    pub fn new(source: LinkSource, found: usize, stats: &MergeStats) -> Self {
+        ImportSummary {
+            source,
+            found,
+            added: stats.serialized,
+            skipped: stats.already_serialized,
+            removed: stats.removed,
+        }
+    }
+}
+
+impl Report for ImportSummary {
+    const KIND: &'static str = "import";
+
+    fn text(&self) -> String {
+        format!(
+            "Serialized {} {} links; {} links already serialized; removed {} no longer in {}",
+            self.added,
+            self.source.as_str(),
+            self.skipped,
+            self.removed,
+            self.source.as_str()
+        )
+    }
+}
+
+/// Canonical form of a URL for matching: no query, fragment or trailing slash.
+pub fn normalize_url(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.set_query(None);
+            parsed.set_fragment(None);
+            let path = parsed.path().trim_end_matches('/').to_string();
+            parsed.set_path(if path.is_empty() { "/" } else { &path });
+            parsed.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Form of a URL for telling links apart: no fragment or trailing slash.
+/// Unlike `normalize_url` the query is kept, since it often names the page,
+/// as in `youtube.com/watch?v=…`.
+pub fn link_key(url: &str) -> String {
+    match url::Url::parse(url) {
+        Ok(mut parsed) => {
+            parsed.set_fragment(None);
+            let path = parsed.path().trim_end_matches('/').to_string();
+            parsed.set_path(if path.is_empty() { "/" } else { &path });
+            parsed.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Reads links.json, treating a missing file as an empty list.
+pub fn read_links() -> anyhow::Result<Vec<SerializedLink>> {
+    match std::fs::read_to_string(LINKS_FILE) {
+        Ok(contents) => serde_json::from_str(&contents).context("Failed to parse links.json"),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).context("Failed to read links.json"),
+    }
+}
+
+pub fn write_links(links: &[SerializedLink]) -> anyhow::Result<()> {
+    let links_file = std::fs::File::create(LINKS_FILE).context("Failed to create links.json")?;
+    let links_file = std::io::BufWriter::new(links_file);
+    serde_json::to_writer_pretty(links_file, links).context("Failed to write to links.json")
+}
+
+/// Each URL merged into another link, by `import --resolve-redirects` or
+/// `duplicates --merge`, mapped to the URL it was merged into.
+pub fn read_aliases() -> anyhow::Result<HashMap<String, String>> {
+    Cache::new(CacheType::Disk("cache.db".to_string()))?.query_aliases()
+}
+
+/// Rewrites incoming links whose URL was merged into another link, so that
+/// importing them again keeps the merged entry instead of adding the old URL back.
+pub fn resolve_aliases(mut links: Vec<SerializedLink>) -> anyhow::Result<Vec<SerializedLink>> {
+    let aliases = read_aliases()?;
+    for link in &mut links {
+        if let Some(canonical) = aliases.get(&link.url) {
+            link.url = canonical.clone();
+        }
+    }
+    Ok(links)
+}
+
+/// Replaces the entries from `source` with `incoming`: entries of that source
+/// that are no longer present are dropped, and incoming links whose URL is
+/// already in the list (from any source) are skipped.
+pub fn merge_source(
+    existing: Vec<SerializedLink>,
+    source: LinkSource,
+    incoming: Vec<SerializedLink>,
+) -> (Vec<SerializedLink>, MergeStats) {
+    let incoming_urls: HashSet<_> = incoming.iter().map(|link| link.url.clone()).collect();
+
+    let before = existing.len();
+    let mut links: Vec<SerializedLink> = existing
+        .into_iter()
+        .filter(|link| link.source != source || incoming_urls.contains(&link.url))
+        .collect();
+    let removed = before - links.len();
+
+    let mut link_urls: HashSet<_> = links.iter().map(|link| link.url.clone()).collect();
+
+    let mut serialized = 0;
+    let mut already_serialized = 0;
+    for link in incoming {
+        if !link_urls.insert(link.url.clone()) {
+            already_serialized += 1;
+            continue;
+        }
+        serialized += 1;
+        links.push(link);
+    }
+
+    (
+        links,
+        MergeStats {
+            serialized,
+            already_serialized,
+            removed,
+        },
+    )
+}
+
+pub struct ResolveStats {
+    pub rewritten: usize,
+    pub merged: usize,
+}
+
+/// Rewrites links to the final URL they redirect to. A rewritten link whose
+/// final URL is already in the list is dropped in favour of the existing entry.
+pub fn resolve_redirects(
+    links: Vec<SerializedLink>,
+    final_urls: &HashMap<String, String>,
+) -> (Vec<SerializedLink>, ResolveStats) {
+    let mut seen: HashSet<_> = links
+        .iter()
+        .filter(|link| !final_urls.contains_key(&link.url))
+        .map(|link| link_key(&link.url))
+        .collect();
+
+    let mut rewritten = 0;
+    let mut merged = 0;
+    let mut resolved = Vec::with_capacity(links.len());
+    for mut link in links {
+        if let Some(final_url) = final_urls.get(&link.url) {
+            if !seen.insert(link_key(final_url)) {
+                merged += 1;
+                continue;
+            }
+            link.url = final_url.clone();
+            rewritten += 1;
+        }
+        resolved.push(link);
+    }
+
+    (resolved, ResolveStats { rewritten, merged })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, source: LinkSource) -> SerializedLink {
+        SerializedLink::new(url.to_string(), url.to_string(), Vec::new(), source)
+    }
+
+    #[test]
+    fn test_merge_removes_stale_entries_of_same_source_only() {
+        let existing = vec![
+            link("https://stale.example.com", LinkSource::Zotero),
+            link("https://keep.example.com", LinkSource::Zotero),
+            link("https://obsidian.example.com", LinkSource::Obsidian),
+        ];
+        let incoming = vec![link("https://keep.example.com", LinkSource::Zotero)];
+
+        let (links, stats) = merge_source(existing, LinkSource::Zotero, incoming);
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(stats.removed, 1);
+        assert_eq!(stats.serialized, 0);
+        assert_eq!(stats.already_serialized, 1);
+    }
+
+    #[test]
+    fn test_merge_skips_urls_from_other_sources() {
+        let existing = vec![link("https://example.org", LinkSource::GoodLinks)];
+        let incoming = vec![
+            link("https://example.org", LinkSource::Zotero),
+            link("https://new.example.org", LinkSource::Zotero),
+            link("https://new.example.org", LinkSource::Zotero),
+        ];
+
+        let (links, stats) = merge_source(existing, LinkSource::Zotero, incoming);
+
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].source, LinkSource::GoodLinks);
+        assert_eq!(links[1].url, "https://new.example.org");
+        assert_eq!(stats.serialized, 1);
+        assert_eq!(stats.already_serialized, 2);
+    }
+
+    #[test]
+    fn test_resolve_redirects_rewrites_and_merges() {
+        let links = vec![
+            link("https://short.example/a", LinkSource::GoodLinks),
+            link("https://blog.example.org/post/", LinkSource::Obsidian),
+            link("https://short.example/b", LinkSource::Zotero),
+        ];
+        let final_urls = HashMap::from([
+            (
+                "https://short.example/a".to_string(),
+                "https://blog.example.org/post".to_string(),
+            ),
+            (
+                "https://short.example/b".to_string(),
+                "https://news.example.org/story".to_string(),
+            ),
+        ]);
+
+        let (links, stats) = resolve_redirects(links, &final_urls);
+
+        assert_eq!(stats.rewritten, 1);
+        assert_eq!(stats.merged, 1);
+        let urls: Vec<_> = links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://blog.example.org/post/",
+                "https://news.example.org/story"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_redirects_keeps_query_distinguished_targets() {
+        let links = vec![
+            link("https://www.youtube.com/watch?v=abc", LinkSource::GoodLinks),
+            link("https://youtu.be/xyz", LinkSource::GoodLinks),
+            link("https://youtu.be/abc", LinkSource::Mastodon),
+        ];
+        let final_urls = HashMap::from([
+            (
+                "https://youtu.be/xyz".to_string(),
+                "https://www.youtube.com/watch?v=xyz".to_string(),
+            ),
+            (
+                "https://youtu.be/abc".to_string(),
+                "https://www.youtube.com/watch?v=abc".to_string(),
+            ),
+        ]);
+
+        let (links, stats) = resolve_redirects(links, &final_urls);
+
+        assert_eq!(stats.rewritten, 1);
+        assert_eq!(stats.merged, 1);
+        let urls: Vec<_> = links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://www.youtube.com/watch?v=abc",
+                "https://www.youtube.com/watch?v=xyz"
+            ]
+        );
+    }
+}
@@ -1,28 +1,247 @@
+mod autotag;
 mod cache;
+mod check;
 mod cli;
+mod duplicates;
+mod extract;
+mod feed;
 mod fetch;
+mod highlights;
+mod import_bluesky;
+mod import_github;
 mod import_goodlinks;
+mod import_hypothesis;
+mod import_mastodon;
 mod import_obsidian;
+mod import_zotero;
+mod links;
+mod manual;
+mod metadata;
 mod models;
+mod output;
+mod refresh;
+mod related;
+mod rules;
+mod scheduler;
+mod search;
+mod serve;
+mod site;
+mod snapshot;
+mod stats;
+mod summary;
 mod sync_raindrop;
+mod template;
+mod tui;
 
+use autotag::{autotag, AutotagOptions};
+use check::{check_links, CheckOptions};
 use clap::Parser;
-use cli::{Cli, Commands};
-use fetch::fetch_to_cache;
+use cli::{Cli, Commands, ExportCommand, OutputFormat, RulesCommand, SnapshotCommand};
+use duplicates::{apply_aliases, report_duplicates};
+use feed::{export_feed, FeedOptions};
+use fetch::{ban_host, fetch_to_cache, report_failures, rewrite_redirects, FetchOptions};
+use highlights::export_highlights;
+use import_bluesky::import_bluesky;
+use import_github::import_github;
 use import_goodlinks::import_goodlinks;
+use import_hypothesis::import_hypothesis;
+use import_mastodon::import_mastodon;
 use import_obsidian::import_obsidian;
+use import_zotero::import_zotero;
+use manual::{add_link, remove_link, tag_link};
+use refresh::{refresh_cache, RefreshOptions};
+use related::{related, RelatedOptions};
+use rules::{apply_keyword_rules, apply_rules, test_rules};
+use search::{search_cache, SearchOptions};
+use serve::serve;
+use site::{export_site, SiteOptions};
+use snapshot::{open_snapshot, SnapshotOptions};
+use stats::{report_stats, StatsOptions};
 use sync_raindrop::sync_raindrop;
+use tui::run_tui;
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    // `--json` on search and stats predates the global flag and means the same
+    let json = matches!(
+        cli.command,
+        Commands::Stats { json: true, .. } | Commands::Search { json: true, .. }
+    );
+    output::set_format(if json { OutputFormat::Json } else { cli.format });
 
     match cli.command {
         Commands::Raindrop { dry_run } => sync_raindrop(dry_run),
-        Commands::Import { verbose } => {
+        Commands::Import {
+            verbose,
+            zotero_dir,
+            mastodon_archive,
+            bluesky_car,
+            github_user,
+            github_stars_json,
+            github_api_base,
+            hypothesis_export,
+            politeness,
+            retry_failed,
+            resolve_redirects,
+            snapshot,
+            snapshot_dir,
+            rules,
+        } => {
             import_goodlinks(verbose)?;
             import_obsidian()?;
-            fetch_to_cache(verbose)?;
+            import_zotero(zotero_dir)?;
+            if let Some(archive_dir) = mastodon_archive {
+                import_mastodon(&archive_dir)?;
+            }
+            if let Some(car_path) = bluesky_car {
+                import_bluesky(&car_path)?;
+            }
+            import_github(
+                github_user.as_deref(),
+                github_stars_json.as_deref(),
+                &github_api_base,
+                verbose,
+            )?;
+            apply_rules(&rules, verbose)?;
+            apply_aliases()?;
+            fetch_to_cache(&FetchOptions {
+                verbose,
+                politeness: (&politeness).into(),
+                retry_failed,
+                snapshot: snapshot.map(|format| SnapshotOptions {
+                    format,
+                    dir: snapshot_dir,
+                }),
+            })?;
+            apply_keyword_rules(&rules, verbose)?;
+            if let Some(export_path) = hypothesis_export {
+                import_hypothesis(&export_path)?;
+            }
+            if resolve_redirects {
+                rewrite_redirects(verbose)?;
+            }
             Ok(())
         }
+        Commands::Refresh {
+            verbose,
+            politeness,
+            max_age_days,
+            min_change_percent,
+        } => refresh_cache(&RefreshOptions {
+            verbose,
+            politeness: (&politeness).into(),
+            max_age_days,
+            min_change: min_change_percent / 100.0,
+        }),
+        Commands::Check {
+            politeness,
+            head_only,
+            no_wayback,
+            report_only,
+            history,
+            all,
+            output,
+        } => check_links(&CheckOptions {
+            politeness: (&politeness).into(),
+            head_only,
+            wayback: !no_wayback,
+            report_only,
+            history,
+            all,
+            output,
+        }),
+        Commands::Failures { all } => report_failures(all),
+        Commands::Add { url, title, tags } => add_link(&url, title, tags),
+        Commands::Remove { url } => remove_link(&url),
+        Commands::Tag { url, add, remove } => tag_link(&url, &add, &remove),
+        Commands::Autotag {
+            vocabulary,
+            dry_run,
+            all,
+            max_tags,
+            keywords,
+        } => autotag(&AutotagOptions {
+            vocabulary,
+            dry_run,
+            all,
+            max_tags,
+            keywords,
+        }),
+        Commands::Rules {
+            command: RulesCommand::Test { url, rules },
+        } => test_rules(&rules, &url),
+        Commands::Ban { host } => ban_host(&host),
+        Commands::Tui => run_tui(),
+        Commands::Serve { bind } => serve(&bind),
+        Commands::Related {
+            url,
+            limit,
+            rebuild,
+        } => related(&RelatedOptions {
+            url,
+            limit,
+            rebuild,
+        }),
+        Commands::Duplicates { merge } => report_duplicates(merge),
+        Commands::Stats { json: _, top } => report_stats(&StatsOptions { top }),
+        Commands::Search {
+            query,
+            source,
+            tags,
+            since,
+            until,
+            limit,
+            json: _,
+        } => search_cache(&SearchOptions {
+            query: query.join(" "),
+            source,
+            tags,
+            since,
+            until,
+            limit,
+        }),
+        Commands::Snapshot {
+            command: SnapshotCommand::Open { url, print },
+        } => open_snapshot(&url, print),
+        Commands::Export {
+            command:
+                ExportCommand::Site {
+                    out_dir,
+                    templates,
+                    exclude_tags,
+                    title,
+                },
+        } => export_site(&SiteOptions {
+            out_dir,
+            templates_dir: templates,
+            exclude_tags,
+            title,
+        }),
+        Commands::Export {
+            command:
+                ExportCommand::Feed {
+                    format,
+                    source,
+                    tags,
+                    limit,
+                    title,
+                    url,
+                    output,
+                },
+        } => export_feed(&FeedOptions {
+            format,
+            source,
+            tags,
+            limit,
+            title,
+            url,
+            output,
+        }),
+        Commands::Highlights {
+            url,
+            template,
+            out_dir,
+            related,
+        } => export_highlights(url.as_deref(), template.as_deref(), &out_dir, related),
     }
 }
@@ -0,0 +1,210 @@
+use anyhow::{bail, Context};
+use readability::extractor;
+use serde::Serialize;
+use url::Url;
+
+use crate::cache::{Cache, CacheType};
+use crate::links::{link_key, read_links, write_links};
+use crate::models::{LinkSource, SerializedLink};
+use crate::output::{emit, Report};
+
+fn find_link<'a>(links: &'a [SerializedLink], url: &str) -> Option<&'a SerializedLink> {
+    let url = link_key(url);
+    links.iter().find(|link| link_key(&link.url) == url)
+}
+
+/// Removes every entry matching `url`, returning the removed links.
+fn remove_from(links: &mut Vec<SerializedLink>, url: &str) -> Vec<SerializedLink> {
+    let url = link_key(url);
+    let (removed, kept) = std::mem::take(links)
+        .into_iter()
+        .partition(|link| link_key(&link.url) == url);
+    *links = kept;
+    removed
+}
+
+/// Adds a manual link to links.json, fetching its title when none is given.
+pub fn add_to_links(
+    url: &str,
+    title: Option<String>,
+    tags: Vec<String>,
+) -> anyhow::Result<SerializedLink> {
+    Url::parse(url).with_context(|| format!("{url} is not a valid URL"))?;
+
+    let mut links = read_links()?;
+    if let Some(existing) = find_link(&links, url) {
+        bail!(
+            "{url} is already in links.json (from {})",
+            existing.source.as_str()
+        );
+    }
+
+    let title = match title {
+        Some(title) => title,
+        None => extractor::scrape(url)
+            .map(|product| product.title)
+            .with_context(|| format!("Failed to fetch title for {url}; pass --title instead"))?,
+    };
+
+    let link = SerializedLink::new(url.to_string(), title, tags, LinkSource::Manual);
+    links.push(link.clone());
+    write_links(&links)?;
+    Ok(link)
+}
+
+#[derive(Serialize)]
+struct LinkAdded {
+    #[serde(flatten)]
+    link: SerializedLink,
+}
+
+impl Report for LinkAdded {
+    const KIND: &'static str = "added";
+
+    fn text(&self) -> String {
+        let link = &self.link;
+        format!(
+            "Added [{}] {} ({})",
+            link.tags.join(", "),
+            link.title,
+            link.url
+        )
+    }
+}
+
+pub fn add_link(url: &str, title: Option<String>, tags: Vec<String>) -> anyhow::Result<()> {
+    let link = add_to_links(url, title, tags)?;
+    emit(&LinkAdded { link })
+}
+
+/// Removes `url` from links.json, returning the entries that were removed.
+pub fn remove_links(url: &str) -> anyhow::Result<Vec<SerializedLink>> {
+    let mut links = read_links()?;
+    let removed = remove_from(&mut links, url);
+    if removed.is_empty() {
+        bail!("{url} is not in links.json");
+    }
+    write_links(&links)?;
+    Ok(removed)
+}
+
+#[derive(Serialize)]
+struct LinkRemoved {
+    #[serde(flatten)]
+    link: SerializedLink,
+}
+
+impl Report for LinkRemoved {
+    const KIND: &'static str = "removed";
+
+    fn text(&self) -> String {
+        let link = &self.link;
+        let mut out = format!("Removed {} ({})", link.title, link.url);
+        if link.source != LinkSource::Manual {
+            out.push_str(&format!(
+                "\n  Imported from {}; it will come back on the next import unless removed there",
+                link.source.as_str()
+            ));
+        }
+        out
+    }
+}
+
+pub fn remove_link(url: &str) -> anyhow::Result<()> {
+    for link in remove_links(url)? {
+        emit(&LinkRemoved { link })?;
+    }
+
+    Ok(())
+}
+
+/// Adds and removes tags on every entry matching `url`, keeping the cached
+/// copy in step so searches see the new tags. Returns the resulting tags.
+pub fn retag_link(url: &str, add: &[String], remove: &[String]) -> anyhow::Result<Vec<String>> {
+    let mut links = read_links()?;
+    let key = link_key(url);
+    let mut matched = Vec::new();
+    for link in links.iter_mut().filter(|link| link_key(&link.url) == key) {
+        link.tags.retain(|tag| !remove.contains(tag));
+        for tag in add {
+            if !link.tags.contains(tag) {
+                link.tags.push(tag.clone());
+            }
+        }
+        matched.push((link.url.clone(), link.tags.clone()));
+    }
+    let Some((_, tags)) = matched.first().cloned() else {
+        bail!("{url} is not in links.json");
+    };
+    write_links(&links)?;
+
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    for (url, tags) in &matched {
+        cache.update_tags(url, tags)?;
+    }
+    Ok(tags)
+}
+
+#[derive(Serialize)]
+struct LinkTagged {
+    url: String,
+    tags: Vec<String>,
+}
+
+impl Report for LinkTagged {
+    const KIND: &'static str = "tagged";
+
+    fn text(&self) -> String {
+        format!("Tagged {} [{}]", self.url, self.tags.join(", "))
+    }
+}
+
+pub fn tag_link(url: &str, add: &[String], remove: &[String]) -> anyhow::Result<()> {
+    let tags = retag_link(url, add, remove)?;
+    emit(&LinkTagged {
+        url: url.to_string(),
+        tags,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, source: LinkSource) -> SerializedLink {
+        SerializedLink::new(url.to_string(), url.to_string(), Vec::new(), source)
+    }
+
+    #[test]
+    fn test_find_link_keeps_query() {
+        let links = vec![
+            link("https://www.youtube.com/watch?v=abc", LinkSource::Manual),
+            link("https://example.com/post/", LinkSource::Manual),
+        ];
+
+        assert!(find_link(&links, "https://www.youtube.com/watch?v=abc").is_some());
+        assert!(find_link(&links, "https://www.youtube.com/watch?v=xyz").is_none());
+        assert!(find_link(&links, "https://example.com/post#comments").is_some());
+        assert!(find_link(&links, "https://example.com/other").is_none());
+
+        let mut links = links;
+        let removed = remove_from(&mut links, "https://www.youtube.com/watch?v=xyz");
+        assert!(removed.is_empty());
+        assert_eq!(links.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_from_keeps_other_links() {
+        let mut links = vec![
+            link("https://example.com/a", LinkSource::Manual),
+            link("https://example.com/b", LinkSource::GoodLinks),
+        ];
+
+        let removed = remove_from(&mut links, "https://example.com/a/");
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].url, "https://example.com/a");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].url, "https://example.com/b");
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde_json::Value;
+use url::Url;
+
+use crate::models::PageMetadata;
+
+const WORDS_PER_MINUTE: usize = 230;
+/// JSON-LD types describing the page's main content, preferred over site-wide entries
+const ARTICLE_TYPES: &[&str] = &[
+    "Article",
+    "NewsArticle",
+    "BlogPosting",
+    "TechArticle",
+    "ScholarlyArticle",
+    "Report",
+];
+
+static META_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<meta\s[^>]*>").unwrap());
+static ATTR_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?is)([a-z:_-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+static JSON_LD_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<script[^>]*type\s*=\s*["']application/ld\+json["'][^>]*>(.*?)</script>"#)
+        .unwrap()
+});
+static HTML_LANG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?is)<html[^>]*\slang\s*=\s*["']?([a-z]{2,3}(?:[-_][a-z0-9]+)*)"#).unwrap()
+});
+
+pub fn reading_time_minutes(word_count: usize) -> usize {
+    word_count.div_ceil(WORDS_PER_MINUTE).max(1)
+}
+
+/// One-line summary such as "Ada Lovelace · Example Blog · 2024-03-01 · 5 min read".
+pub fn byline(metadata: &PageMetadata) -> Option<String> {
+    let date = metadata
+        .published_at
+        .as_deref()
+        .map(|date| date.split('T').next().unwrap_or(date).to_string());
+    let reading_time = metadata
+        .word_count
+        .filter(|&count| count > 0)
+        .map(|count| format!("{} min read", reading_time_minutes(count)));
+
+    let parts: Vec<String> = [
+        metadata.author.clone(),
+        metadata.site_name.clone(),
+        date,
+        reading_time,
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!parts.is_empty()).then(|| parts.join(" · "))
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Collects `<meta>` contents keyed by their lowercased `property`, `name`,
+/// `itemprop` or `http-equiv`. The first occurrence of a key wins.
+fn meta_tags(html: &str) -> HashMap<String, String> {
+    let mut tags = HashMap::new();
+    for tag in META_REGEX.find_iter(html) {
+        let attrs: HashMap<String, &str> = ATTR_REGEX
+            .captures_iter(tag.as_str())
+            .filter_map(|c| {
+                let value = c.get(2).or_else(|| c.get(3))?.as_str();
+                Some((c[1].to_lowercase(), value))
+            })
+            .collect();
+
+        let Some(content) = attrs.get("content").filter(|c| !c.trim().is_empty()) else {
+            continue;
+        };
+        for key_attr in ["property", "name", "itemprop", "http-equiv"] {
+            if let Some(key) = attrs.get(key_attr) {
+                tags.entry(key.to_lowercase())
+                    .or_insert_with(|| decode_entities(content));
+            }
+        }
+    }
+    tags
+}
+
+fn json_ld_objects(html: &str) -> Vec<Value> {
+    let mut objects = Vec::new();
+    let mut pending: Vec<Value> = JSON_LD_REGEX
+        .captures_iter(html)
+        .filter_map(|c| serde_json::from_str(c[1].trim()).ok())
+        .collect();
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::Array(items) => pending.extend(items),
+            Value::Object(mut object) => {
+                if let Some(graph) = object.remove("@graph") {
+                    pending.push(graph);
+                }
+                objects.push(Value::Object(object));
+            }
+            _ => {}
+        }
+    }
+    objects.reverse();
+    objects
+}
+
+fn is_article(object: &Value) -> bool {
+    match &object["@type"] {
+        Value::String(t) => ARTICLE_TYPES.contains(&t.as_str()),
+        Value::Array(types) => types
+            .iter()
+            .any(|t| t.as_str().is_some_and(|t| ARTICLE_TYPES.contains(&t))),
+        _ => false,
+    }
+}
+
+/// Reads a name out of a JSON-LD person/organisation, which may be a plain
+/// string, an object with a `name`, or a list of either.
+fn json_ld_name(value: &Value) -> Option<String> {
+    match value {
+        Value::String(name) => Some(name.clone()),
+        Value::Object(_) => value["name"].as_str().map(str::to_string),
+        Value::Array(items) => {
+            let names: Vec<_> = items.iter().filter_map(json_ld_name).collect();
+            (!names.is_empty()).then(|| names.join(", "))
+        }
+        _ => None,
+    }
+}
+
+fn json_ld_image(value: &Value) -> Option<String> {
+    match value {
+        Value::String(url) => Some(url.clone()),
+        Value::Object(_) => value["url"].as_str().map(str::to_string),
+        Value::Array(items) => items.iter().find_map(json_ld_image),
+        _ => None,
+    }
+}
+
+fn html_lang(html: &str) -> Option<String> {
+    HTML_LANG_REGEX.captures(html).map(|c| c[1].to_string())
+}
+
+/// Extracts page metadata from OpenGraph, JSON-LD and plain `<meta>` tags,
+/// taking whichever source is usually most precise for each field.
+pub fn extract_metadata(html: &str, base: &Url) -> PageMetadata {
+    let meta = meta_tags(html);
+    let get = |keys: &[&str]| keys.iter().find_map(|key| meta.get(*key).cloned());
+
+    let json_ld = json_ld_objects(html);
+    let article = json_ld.iter().find(|o| is_article(o)).or(json_ld.first());
+    let from_ld = |field: &str, read: fn(&Value) -> Option<String>| {
+        article
+            .and_then(|a| read(&a[field]))
+            .filter(|s| !s.is_empty())
+    };
+
+    // article:author is often a profile URL rather than a name
+    let meta_author = get(&["author", "article:author", "twitter:creator"])
+        .filter(|author| !author.starts_with("http"));
+
+    let lead_image = get(&[
+        "og:image",
+        "og:image:url",
+        "twitter:image",
+        "twitter:image:src",
+    ])
+    .or_else(|| from_ld("image", json_ld_image))
+    .and_then(|image| base.join(&image).ok())
+    .map(|image| image.to_string());
+
+    PageMetadata {
+        author: from_ld("author", json_ld_name).or(meta_author),
+        published_at: get(&["article:published_time", "og:published_time"])
+            .or_else(|| from_ld("datePublished", |v| v.as_str().map(str::to_string)))
+            .or_else(|| {
+                get(&[
+                    "date",
+                    "pubdate",
+                    "dc.date",
+                    "dcterms.date",
+                    "citation_publication_date",
+                ])
+            }),
+        site_name: get(&["og:site_name"])
+            .or_else(|| from_ld("publisher", json_ld_name))
+            .or_else(|| get(&["application-name"])),
+        language: html_lang(html)
+            .or_else(|| get(&["og:locale", "content-language", "language"]))
+            .map(|lang| lang.replace('_', "-")),
+        lead_image,
+        description: get(&["og:description", "description", "twitter:description"]),
+        word_count: None,
+        content_type: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> Url {
+        Url::parse("https://blog.example.org/posts/hello").unwrap()
+    }
+
+    #[test]
+    fn test_extract_opengraph_and_meta() {
+        let html = r#"<!DOCTYPE html>
+            <html lang="en-GB"><head>
+            <meta property="og:site_name" content="Example Blog">
+            <meta property="og:image" content="/images/lead.png" />
+            <meta name="author" content="Ada Lovelace">
+            <meta property="article:published_time" content="2024-03-01T09:00:00Z">
+            <meta name="description" content="Notes &amp; thoughts">
+            </head><body></body></html>"#;
+
+        let metadata = extract_metadata(html, &base());
+
+        assert_eq!(metadata.site_name.as_deref(), Some("Example Blog"));
+        assert_eq!(
+            metadata.lead_image.as_deref(),
+            Some("https://blog.example.org/images/lead.png")
+        );
+        assert_eq!(metadata.author.as_deref(), Some("Ada Lovelace"));
+        assert_eq!(
+            metadata.published_at.as_deref(),
+            Some("2024-03-01T09:00:00Z")
+        );
+        assert_eq!(metadata.language.as_deref(), Some("en-GB"));
+        assert_eq!(metadata.description.as_deref(), Some("Notes & thoughts"));
+    }
+
+    #[test]
+    fn test_extract_json_ld_graph() {
+        let html = r#"<html><head>
+            <script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebSite", "name": "Example"},
+                {"@type": "BlogPosting",
+                 "author": [{"@type": "Person", "name": "Grace Hopper"}, {"name": "Alan Turing"}],
+                 "datePublished": "2023-11-05",
+                 "publisher": {"@type": "Organization", "name": "Example Press"},
+                 "image": {"@type": "ImageObject", "url": "https://cdn.example.org/a.jpg"}}
+            ]}
+            </script>
+            <meta property="og:locale" content="fr_FR">
+            </head></html>"#;
+
+        let metadata = extract_metadata(html, &base());
+
+        assert_eq!(
+            metadata.author.as_deref(),
+            Some("Grace Hopper, Alan Turing")
+        );
+        assert_eq!(metadata.published_at.as_deref(), Some("2023-11-05"));
+        assert_eq!(metadata.site_name.as_deref(), Some("Example Press"));
+        assert_eq!(
+            metadata.lead_image.as_deref(),
+            Some("https://cdn.example.org/a.jpg")
+        );
+        assert_eq!(metadata.language.as_deref(), Some("fr-FR"));
+    }
+
+    #[test]
+    fn test_reading_time() {
+        assert_eq!(reading_time_minutes(0), 1);
+        assert_eq!(reading_time_minutes(230), 1);
+        assert_eq!(reading_time_minutes(1000), 5);
+    }
+
+    #[test]
+    fn test_byline() {
+        let metadata = PageMetadata {
+            author: Some("Ada Lovelace".to_string()),
+            published_at: Some("2024-03-01T09:00:00Z".to_string()),
+            word_count: Some(1000),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            byline(&metadata).as_deref(),
+            Some("Ada Lovelace · 2024-03-01 · 5 min read")
+        );
+        assert_eq!(byline(&PageMetadata::default()), None);
+    }
+}
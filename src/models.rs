@@ -2,30 +2,56 @@ use rusqlite::{
     types::{FromSql, FromSqlError, ToSqlOutput, Value, ValueRef},
     ToSql,
 };
-use serde::Serialize;
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum LinkSource {
     GoodLinks,
     Obsidian,
+    Zotero,
+    Mastodon,
+    Bluesky,
+    GitHub,
+    Manual,
+}
+
+impl LinkSource {
+    pub const ALL: &[LinkSource] = &[
+        LinkSource::GoodLinks,
+        LinkSource::Obsidian,
+        LinkSource::Zotero,
+        LinkSource::Mastodon,
+        LinkSource::Bluesky,
+        LinkSource::GitHub,
+        LinkSource::Manual,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkSource::GoodLinks => "GoodLinks",
+            LinkSource::Obsidian => "Obsidian",
+            LinkSource::Zotero => "Zotero",
+            LinkSource::Mastodon => "Mastodon",
+            LinkSource::Bluesky => "Bluesky",
+            LinkSource::GitHub => "GitHub",
+            LinkSource::Manual => "Manual",
+        }
+    }
 }
 
 impl FromSql for LinkSource {
     fn column_result(value: ValueRef) -> std::result::Result<LinkSource, FromSqlError> {
-        match value.as_str()? {
-            "GoodLinks" => Ok(LinkSource::GoodLinks),
-            "Obsidian" => Ok(LinkSource::Obsidian),
-            _ => Err(FromSqlError::InvalidType),
-        }
+        let value = value.as_str()?;
+        LinkSource::ALL
+            .iter()
+            .find(|source| source.as_str() == value)
+            .copied()
+            .ok_or(FromSqlError::InvalidType)
     }
 }
 
 impl ToSql for LinkSource {
     fn to_sql(&self) -> std::result::Result<ToSqlOutput<'_>, rusqlite::Error> {
-        match self {
-            LinkSource::GoodLinks => Ok(ToSqlOutput::Owned(Value::Text("GoodLinks".into()))),
-            LinkSource::Obsidian => Ok(ToSqlOutput::Owned(Value::Text("Obsidian".into()))),
-        }
+        Ok(ToSqlOutput::Owned(Value::Text(self.as_str().into())))
     }
 }
 
@@ -56,21 +82,62 @@ impl CachedLink {
     }
 }
 
-#[derive(serde::Serialize, serde::Deserialize, PartialEq, Eq, Hash)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct SerializedLink {
     pub url: String,
     pub title: String,
     pub tags: Vec<String>,
     pub source: LinkSource,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub authors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doi: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permalink: Option<String>,
+    /// When the link was marked as read, as reported by the source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_at: Option<String>,
+    /// Note the link was found in, relative to its Obsidian vault
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note_path: Option<String>,
+    /// Raindrop collection to file the link in instead of the one for its source
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub collection: Option<String>,
+}
+
+impl SerializedLink {
+    pub fn new(url: String, title: String, tags: Vec<String>, source: LinkSource) -> Self {
+        SerializedLink {
+            url,
+            title,
+            tags,
+            source,
+            authors: Vec::new(),
+            doi: None,
+            attachments: Vec::new(),
+            note: None,
+            permalink: None,
+            read_at: None,
+            note_path: None,
+            collection: None,
+        }
+    }
 }
 
 impl From<GoodLinksLink> for SerializedLink {
     fn from(val: GoodLinksLink) -> Self {
         SerializedLink {
-            url: val.url,
-            title: val.title.unwrap_or_default(),
-            tags: val.tags,
-            source: LinkSource::GoodLinks,
+            read_at: val.read_at,
+            ..SerializedLink::new(
+                val.url,
+                val.title.unwrap_or_default(),
+                val.tags,
+                LinkSource::GoodLinks,
+            )
         }
     }
 }
@@ -78,10 +145,8 @@ impl From<GoodLinksLink> for SerializedLink {
 impl From<ObsidianLink> for SerializedLink {
     fn from(val: ObsidianLink) -> Self {
         SerializedLink {
-            url: val.url,
-            title: val.title,
-            tags: Vec::new(),
-            source: LinkSource::Obsidian,
+            note_path: val.note_path,
+            ..SerializedLink::new(val.url, val.title, Vec::new(), LinkSource::Obsidian)
         }
     }
 }
@@ -103,12 +168,416 @@ pub struct GoodLinksApiResponse {
     pub has_more: bool,
 }
 
+#[derive(serde::Deserialize)]
+pub struct GitHubRepo {
+    pub full_name: String,
+    pub html_url: String,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
+}
+
+/// The starred-repos API returns bare repos, or `{ starred_at, repo }` with the star+json media type.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum GitHubStar {
+    Timestamped { repo: GitHubRepo },
+    Repo(GitHubRepo),
+}
+
+impl From<GitHubStar> for GitHubRepo {
+    fn from(val: GitHubStar) -> Self {
+        match val {
+            GitHubStar::Timestamped { repo } | GitHubStar::Repo(repo) => repo,
+        }
+    }
+}
+
+impl From<GitHubRepo> for SerializedLink {
+    fn from(val: GitHubRepo) -> Self {
+        let description = val.description.filter(|d| !d.trim().is_empty());
+        let title = match &description {
+            Some(description) => format!("{}: {description}", val.full_name),
+            None => val.full_name,
+        };
+        let mut tags = val.topics;
+        if let Some(language) = val.language {
+            let language = language.to_lowercase();
+            if !tags.contains(&language) {
+                tags.push(language);
+            }
+        }
+        SerializedLink {
+            note: description,
+            ..SerializedLink::new(val.html_url, title, tags, LinkSource::GitHub)
+        }
+    }
+}
+
+/// A highlight or note on a cached article, as exported from Hypothesis.
+#[derive(PartialEq, Debug)]
+pub struct Annotation {
+    pub id: String,
+    pub url: String,
+    pub quote: Option<String>,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    pub selector: serde_json::Value,
+    pub position: Option<i64>,
+    pub created_at: Option<String>,
+}
+
+/// Why fetching a link failed, coarse enough to decide whether retrying could help.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorClass {
+    Http,
+    Timeout,
+    Dns,
+    Connection,
+    Tls,
+    Parse,
+    /// Redirected to a banned host
+    Banned,
+    /// A content type we can't extract text from, such as images or video
+    Unsupported,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorClass::Http => "http",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Dns => "dns",
+            ErrorClass::Connection => "connection",
+            ErrorClass::Tls => "tls",
+            ErrorClass::Parse => "parse",
+            ErrorClass::Banned => "banned",
+            ErrorClass::Unsupported => "unsupported",
+            ErrorClass::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchError {
+    pub status_code: Option<u16>,
+    pub class: ErrorClass,
+    pub message: String,
+}
+
+impl From<ureq::Error> for FetchError {
+    fn from(val: ureq::Error) -> Self {
+        let (status_code, class) = match &val {
+            ureq::Error::StatusCode(code) => (Some(*code), ErrorClass::Http),
+            ureq::Error::Timeout(_) => (None, ErrorClass::Timeout),
+            ureq::Error::HostNotFound => (None, ErrorClass::Dns),
+            ureq::Error::ConnectionFailed | ureq::Error::Io(_) => (None, ErrorClass::Connection),
+            ureq::Error::Tls(_) | ureq::Error::Rustls(_) => (None, ErrorClass::Tls),
+            _ => (None, ErrorClass::Other),
+        };
+        FetchError {
+            status_code,
+            class,
+            message: val.to_string(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct FetchFailure {
+    pub url: String,
+    pub status_code: Option<u16>,
+    pub error_class: String,
+    pub message: String,
+    pub attempts: u32,
+    pub last_attempt_at: String,
+    pub next_retry_at: String,
+}
+
+#[derive(serde::Serialize, Clone, Copy, PartialEq, Eq, Hash, Debug, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkStatus {
+    Ok,
+    Redirect,
+    #[serde(rename = "soft_404")]
+    SoftNotFound,
+    Gone,
+    ClientError,
+    ServerError,
+    Dns,
+    Timeout,
+    ConnectionError,
+    Other,
+}
+
+impl LinkStatus {
+    pub const ALL: &[LinkStatus] = &[
+        LinkStatus::Ok,
+        LinkStatus::Redirect,
+        LinkStatus::SoftNotFound,
+        LinkStatus::Gone,
+        LinkStatus::ClientError,
+        LinkStatus::ServerError,
+        LinkStatus::Dns,
+        LinkStatus::Timeout,
+        LinkStatus::ConnectionError,
+        LinkStatus::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkStatus::Ok => "ok",
+            LinkStatus::Redirect => "redirect",
+            LinkStatus::SoftNotFound => "soft_404",
+            LinkStatus::Gone => "gone",
+            LinkStatus::ClientError => "client_error",
+            LinkStatus::ServerError => "server_error",
+            LinkStatus::Dns => "dns",
+            LinkStatus::Timeout => "timeout",
+            LinkStatus::ConnectionError => "connection_error",
+            LinkStatus::Other => "other",
+        }
+    }
+
+    /// Whether the link no longer leads to its content.
+    pub fn is_dead(&self) -> bool {
+        !matches!(self, LinkStatus::Ok | LinkStatus::Redirect)
+    }
+}
+
+impl FromSql for LinkStatus {
+    fn column_result(value: ValueRef) -> std::result::Result<LinkStatus, FromSqlError> {
+        let value = value.as_str()?;
+        LinkStatus::ALL
+            .iter()
+            .find(|status| status.as_str() == value)
+            .copied()
+            .ok_or(FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for LinkStatus {
+    fn to_sql(&self) -> std::result::Result<ToSqlOutput<'_>, rusqlite::Error> {
+        Ok(ToSqlOutput::Owned(Value::Text(self.as_str().into())))
+    }
+}
+
+/// The outcome of checking whether a link is still alive.
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct LinkCheck {
+    pub url: String,
+    pub status: LinkStatus,
+    pub status_code: Option<u16>,
+    pub final_url: Option<String>,
+    pub message: Option<String>,
+    pub wayback_url: Option<String>,
+    pub checked_at: Option<String>,
+}
+
 pub struct ObsidianLink {
     pub title: String,
     pub url: String,
+    pub note_path: Option<String>,
 }
 
 pub struct Article {
     pub title: String,
     pub text_content: String,
+    /// Readable HTML as cleaned up by readability
+    pub content_html: String,
+    pub metadata: PageMetadata,
+    /// Every URL requested, from the original to the final one; empty if not redirected
+    pub redirects: Vec<String>,
+    /// The raw response, kept only when a snapshot is wanted
+    pub captured: Option<CapturedResponse>,
+    /// Validators for conditional requests when the page is refreshed
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// Restricts a full-text search of the cache.
+#[derive(Default)]
+pub struct SearchFilters {
+    pub source: Option<LinkSource>,
+    /// Every tag must be present
+    pub tags: Vec<String>,
+    /// Inclusive bounds on the publication date (or fetch date when unknown), as YYYY-MM-DD
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: usize,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct SearchResult {
+    /// Row id in the cache, as used by `/api/links/:id`
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    pub source: LinkSource,
+    pub tags: Vec<String>,
+    pub date: Option<String>,
+    pub snippet: String,
+    /// BM25 score; lower is a better match
+    pub rank: f64,
+}
+
+#[derive(serde::Serialize, Debug, PartialEq)]
+pub struct RelatedLink {
+    pub url: String,
+    pub title: String,
+    /// Cosine similarity of the two articles, from 0 to 1
+    pub score: f64,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct LinkSummary {
+    pub id: i64,
+    pub url: String,
+    pub title: String,
+    pub source: LinkSource,
+    pub tags: Vec<String>,
+    pub date: Option<String>,
+}
+
+/// A cached link due to be fetched again, with what's needed to tell if it changed.
+pub struct StaleLink {
+    pub url: String,
+    pub text_content: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+pub struct CapturedResponse {
+    /// URL the response was finally served from
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// A local copy of a page: a standalone file, or a record within a WARC file.
+#[derive(serde::Serialize, PartialEq, Eq, Debug)]
+pub struct Snapshot {
+    pub url: String,
+    pub format: String,
+    pub path: String,
+    pub warc_offset: Option<u64>,
+    pub captured_at: Option<String>,
+}
+
+/// Details about a page beyond its text, read from its `<meta>` tags and JSON-LD.
+#[derive(serde::Serialize, Default, Clone, PartialEq, Eq, Debug)]
+pub struct PageMetadata {
+    pub author: Option<String>,
+    pub published_at: Option<String>,
+    pub site_name: Option<String>,
+    pub language: Option<String>,
+    pub lead_image: Option<String>,
+    pub description: Option<String>,
+    pub word_count: Option<usize>,
+    /// MIME type the page was served as
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ZoteroItem {
+    pub title: String,
+    pub url: Option<String>,
+    pub doi: Option<String>,
+    pub authors: Vec<String>,
+    pub collections: Vec<String>,
+    pub attachments: Vec<String>,
+}
+
+impl TryFrom<ZoteroItem> for SerializedLink {
+    type Error = ZoteroItem;
+
+    /// Fails for items with neither a URL nor a DOI to link to.
+    fn try_from(val: ZoteroItem) -> Result<Self, Self::Error> {
+        let url = match (&val.url, &val.doi) {
+            (Some(url), _) => url.clone(),
+            (None, Some(doi)) => format!("https://doi.org/{doi}"),
+            (None, None) => return Err(val),
+        };
+        let title = if val.title.is_empty() {
+            url.clone()
+        } else {
+            val.title
+        };
+        Ok(SerializedLink {
+            url,
+            title,
+            tags: val.collections,
+            source: LinkSource::Zotero,
+            authors: val.authors,
+            doi: val.doi,
+            attachments: val.attachments,
+            note: None,
+            permalink: None,
+            read_at: None,
+            note_path: None,
+            collection: None,
+        })
+    }
+}
+
+const SOCIAL_TITLE_MAX_CHARS: usize = 100;
+
+/// A post from a social platform export, either written or saved by the user.
+#[derive(Debug, PartialEq)]
+pub struct SocialPost {
+    pub permalink: String,
+    pub text: String,
+    pub urls: Vec<String>,
+    pub tags: Vec<String>,
+    /// Bookmarked or liked rather than written by the user
+    pub saved: bool,
+}
+
+impl SocialPost {
+    /// The first line of the post text, shortened to fit a bookmark title.
+    fn title(&self) -> Option<String> {
+        let first_line = self
+            .text
+            .lines()
+            .find(|line| !line.trim().is_empty())?
+            .trim();
+        if first_line.chars().count() <= SOCIAL_TITLE_MAX_CHARS {
+            return Some(first_line.to_string());
+        }
+        let truncated: String = first_line.chars().take(SOCIAL_TITLE_MAX_CHARS).collect();
+        Some(format!("{}…", truncated.trim_end()))
+    }
+
+    /// One link per URL mentioned in the post. A saved post that links
+    /// nowhere is kept as a link to the post itself; the user's own posts
+    /// without links are not bookmarks.
+    pub fn into_links(self, source: LinkSource) -> Vec<SerializedLink> {
+        let title = self.title();
+        let urls = if self.urls.is_empty() && self.saved {
+            vec![self.permalink.clone()]
+        } else {
+            self.urls
+        };
+        let note = (!self.text.is_empty()).then_some(self.text);
+        urls.into_iter()
+            .map(|url| SerializedLink {
+                title: title.clone().unwrap_or_else(|| url.clone()),
+                url,
+                tags: self.tags.clone(),
+                source,
+                authors: Vec::new(),
+                doi: None,
+                attachments: Vec::new(),
+                note: note.clone(),
+                permalink: Some(self.permalink.clone()),
+                read_at: None,
+                note_path: None,
+                collection: None,
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,83 @@
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Picks text or JSON output for the rest of the run.
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// The result of a command or step, printed as text or as a JSON line.
+pub trait Report: Serialize {
+    /// Written as the `type` of each JSON line, e.g. "import"
+    const KIND: &'static str;
+
+    fn text(&self) -> String;
+}
+
+/// `report` as one line of JSON, with its kind in a `type` field.
+fn json_line<R: Report>(report: &R) -> anyhow::Result<String> {
+    let mut value = serde_json::to_value(report)?;
+    if let Some(object) = value.as_object_mut() {
+        object.insert("type".to_string(), R::KIND.into());
+    }
+    Ok(value.to_string())
+}
+
+/// Prints `report` as text, or with `--format json` as a JSON line.
+pub fn emit<R: Report>(report: &R) -> anyhow::Result<()> {
+    if is_json() {
+        println!("{}", json_line(report)?);
+    } else {
+        println!("{}", report.text());
+    }
+    Ok(())
+}
+
+/// Prints a progress message: to stdout as text, or to stderr with
+/// `--format json` so stdout only carries JSON lines.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::output::is_json() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+pub(crate) use progress;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Done {
+        count: usize,
+    }
+
+    impl Report for Done {
+        const KIND: &'static str = "done";
+
+        fn text(&self) -> String {
+            format!("Did {} things", self.count)
+        }
+    }
+
+    #[test]
+    fn test_report_json_has_type() -> anyhow::Result<()> {
+        let line = json_line(&Done { count: 2 })?;
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line)?;
+        assert_eq!(value, serde_json::json!({ "type": "done", "count": 2 }));
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::cache::{Cache, CacheType};
+use crate::fetch::{banned_hosts, content_hash, http_agent, refetch_article};
+use crate::output::{emit, progress, Report};
+use crate::scheduler::{host_of, run_politely, Politeness};
+
+pub struct RefreshOptions {
+    pub verbose: bool,
+    pub politeness: Politeness,
+    /// Refetch links last fetched more than this many days ago
+    pub max_age_days: u32,
+    /// Changes touching less than this fraction of the words are stored but not reported
+    pub min_change: f64,
+}
+
+/// Fraction of words that differ between two versions of a text, ignoring
+/// order: 0.0 for the same words, 1.0 for nothing in common.
+fn change_ratio(old: &str, new: &str) -> f64 {
+    let mut counts: HashMap<&str, i64> = HashMap::new();
+    for word in old.split_whitespace() {
+        *counts.entry(word).or_default() += 1;
+    }
+    for word in new.split_whitespace() {
+        *counts.entry(word).or_default() -= 1;
+    }
+
+    let total = old.split_whitespace().count() + new.split_whitespace().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let differing: i64 = counts.values().map(|count| count.abs()).sum();
+    differing as f64 / total as f64
+}
+
+#[derive(serde::Serialize)]
+struct ChangedLink {
+    url: String,
+    title: String,
+    /// Share of words added or removed, from 0 to 1
+    ratio: f64,
+}
+
+impl Report for ChangedLink {
+    const KIND: &'static str = "changed_link";
+
+    fn text(&self) -> String {
+        format!("{:>5.1}% {} ({})", self.ratio * 100.0, self.title, self.url)
+    }
+}
+
+#[derive(serde::Serialize)]
+struct RefreshReport {
+    changed: usize,
+    minor: usize,
+    unchanged: usize,
+    not_modified: usize,
+    failed: usize,
+}
+
+impl Report for RefreshReport {
+    const KIND: &'static str = "refresh";
+
+    fn text(&self) -> String {
+        format!(
+            "\n{} changed, {} with minor edits, {} unchanged, {} not modified, {} failed",
+            self.changed, self.minor, self.unchanged, self.not_modified, self.failed
+        )
+    }
+}
+
+pub fn refresh_cache(options: &RefreshOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_owned()))?;
+    let banned = banned_hosts(&cache)?;
+    let stale: Vec<_> = cache
+        .query_stale_links(options.max_age_days)?
+        .into_iter()
+        .filter(|link| !banned.contains(&host_of(&link.url)))
+        .collect();
+
+    progress!(
+        "Refreshing {} links last fetched over {} days ago",
+        stale.len(),
+        options.max_age_days
+    );
+
+    let pb = ProgressBar::new(stale.len().try_into()?);
+    pb.set_style(ProgressStyle::with_template(
+        "{bar:40} {pos}/{len} [{elapsed_precise}, {per_sec}, eta {eta}] {msg}",
+    )?);
+
+    let agent = http_agent();
+    let mut not_modified = 0;
+    let mut unchanged = 0;
+    let mut minor = 0;
+    let mut failed = 0;
+    let mut changed = Vec::new();
+
+    run_politely(
+        stale,
+        |link| link.url.as_str(),
+        &options.politeness,
+        |link| {
+            refetch_article(
+                &agent,
+                &link.url,
+                &banned,
+                link.etag.as_deref(),
+                link.last_modified.as_deref(),
+            )
+        },
+        |link, result| {
+            let old_hash = link
+                .content_hash
+                .clone()
+                .unwrap_or_else(|| content_hash(&link.text_content));
+            match result {
+                Ok(None) => {
+                    cache.record_validators(
+                        &link.url,
+                        link.etag.as_deref(),
+                        link.last_modified.as_deref(),
+                        &old_hash,
+                    )?;
+                    not_modified += 1;
+                }
+                Ok(Some(article)) => {
+                    let new_hash = content_hash(&article.text_content);
+                    if new_hash == old_hash {
+                        unchanged += 1;
+                    } else {
+                        cache.replace_content(&link.url, &article.title, &article.text_content)?;
+                        cache.record_page_details(
+                            &link.url,
+                            &article.content_html,
+                            &article.metadata,
+                        )?;
+                        let ratio = change_ratio(&link.text_content, &article.text_content);
+                        if ratio >= options.min_change {
+                            changed.push((link.url.clone(), article.title.clone(), ratio));
+                        } else {
+                            minor += 1;
+                        }
+                    }
+                    cache.record_validators(
+                        &link.url,
+                        article.etag.as_deref(),
+                        article.last_modified.as_deref(),
+                        &new_hash,
+                    )?;
+                }
+                Err(e) => {
+                    if options.verbose {
+                        pb.println(format!("Failed to refresh {}: {}", link.url, e.message));
+                    }
+                    failed += 1;
+                }
+            }
+            pb.set_message(format!("{} changed", changed.len()));
+            pb.inc(1);
+            Ok(())
+        },
+    )?;
+    pb.finish();
+
+    changed.sort_by(|a, b| b.2.total_cmp(&a.2));
+    for (url, title, ratio) in changed.iter().cloned() {
+        emit(&ChangedLink { url, title, ratio })?;
+    }
+    emit(&RefreshReport {
+        changed: changed.len(),
+        minor,
+        unchanged,
+        not_modified,
+        failed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_ratio() {
+        assert_eq!(change_ratio("a b c", "a  b\nc"), 0.0);
+        assert_eq!(change_ratio("a b", "c d"), 1.0);
+        assert_eq!(change_ratio("a b c d", "a b c e"), 0.25);
+        assert_eq!(change_ratio("", ""), 0.0);
+    }
+
+    #[test]
+    fn test_content_hash_ignores_whitespace() {
+        assert_eq!(
+            content_hash("one  two\nthree"),
+            content_hash("one two three")
+        );
+        assert_ne!(content_hash("one two"), content_hash("two one"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::autotag::Corpus;
+use crate::cache::{Cache, CacheType};
+use crate::models::RelatedLink;
+use crate::output::{emit, progress, Report};
+
+/// Highest-weighted terms kept per article; the long tail adds noise and time
+const TERMS_PER_ARTICLE: usize = 100;
+/// Related links stored per article in the index
+const INDEX_NEIGHBOURS: usize = 20;
+/// Articles less similar than this are not worth suggesting
+const MIN_SCORE: f64 = 0.05;
+
+pub struct RelatedOptions {
+    pub url: Option<String>,
+    pub limit: usize,
+    /// Recompute the index from the cached articles first
+    pub rebuild: bool,
+}
+
+/// A unit-length TF-IDF vector of each text's top terms.
+fn vectors(texts: &[&str]) -> Vec<Vec<(String, f64)>> {
+    let corpus = Corpus::new(texts.iter().copied());
+    texts
+        .iter()
+        .map(|text| {
+            let mut vector = corpus.keywords(text, TERMS_PER_ARTICLE);
+            let norm = vector.iter().map(|(_, w)| w * w).sum::<f64>().sqrt();
+            if norm > 0.0 {
+                for (_, weight) in &mut vector {
+                    *weight /= norm;
+                }
+            }
+            vector
+        })
+        .collect()
+}
+
+/// For each vector, the indices and cosine similarities of its `neighbours`
+/// most similar vectors, best first.
+fn nearest(vectors: &[Vec<(String, f64)>], neighbours: usize) -> Vec<Vec<(usize, f64)>> {
+    let mut postings: HashMap<&str, Vec<(usize, f64)>> = HashMap::new();
+    for (doc, vector) in vectors.iter().enumerate() {
+        for (term, weight) in vector {
+            postings.entry(term).or_default().push((doc, *weight));
+        }
+    }
+
+    vectors
+        .iter()
+        .enumerate()
+        .map(|(doc, vector)| {
+            let mut scores: HashMap<usize, f64> = HashMap::new();
+            for (term, weight) in vector {
+                for (other, other_weight) in &postings[term.as_str()] {
+                    if *other != doc {
+                        *scores.entry(*other).or_default() += weight * other_weight;
+                    }
+                }
+            }
+            let mut scores: Vec<(usize, f64)> = scores
+                .into_iter()
+                .filter(|(_, score)| *score >= MIN_SCORE)
+                .collect();
+            scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            scores.truncate(neighbours);
+            scores
+        })
+        .collect()
+}
+
+/// Recomputes the related-links index over every cached article, returning
+/// how many articles were indexed.
+pub fn build_index(cache: &Cache) -> anyhow::Result<usize> {
+    let cached = cache.query_all()?;
+    let texts: Vec<String> = cached
+        .iter()
+        .map(|link| format!("{}\n{}", link.title, link.text_content))
+        .collect();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+
+    let mut pairs = Vec::new();
+    for (doc, neighbours) in nearest(&vectors(&texts), INDEX_NEIGHBOURS)
+        .into_iter()
+        .enumerate()
+    {
+        for (other, score) in neighbours {
+            pairs.push((cached[doc].url.clone(), cached[other].url.clone(), score));
+        }
+    }
+    cache.replace_related(&pairs)?;
+    Ok(cached.len())
+}
+
+impl Report for RelatedLink {
+    const KIND: &'static str = "related_link";
+
+    fn text(&self) -> String {
+        format!("{:.2}  {} ({})", self.score, self.title, self.url)
+    }
+}
+
+/// Rebuilds the index if articles were cached or changed since it was built.
+pub fn ensure_index(cache: &Cache) -> anyhow::Result<()> {
+    if cache.count_unindexed_related()? > 0 {
+        let indexed = build_index(cache)?;
+        progress!("Indexed {indexed} cached articles for related links");
+    }
+    Ok(())
+}
+
+pub fn related(options: &RelatedOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    if options.rebuild {
+        let indexed = build_index(&cache)?;
+        progress!("Indexed {indexed} cached articles for related links");
+    } else {
+        ensure_index(&cache)?;
+    }
+
+    let Some(url) = &options.url else {
+        return Ok(());
+    };
+    let link = cache
+        .query(url)?
+        .with_context(|| format!("{url} is not in the cache"))?;
+    let related = cache.query_related(&link.url, options.limit)?;
+    if related.is_empty() {
+        progress!(
+            "No related links for {}; try `related --rebuild` after importing",
+            link.title
+        );
+    }
+    for related in &related {
+        emit(related)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CachedLink, LinkSource};
+
+    #[test]
+    fn test_nearest_prefers_shared_distinctive_terms() {
+        let texts = [
+            "rust borrow checker ownership lifetimes",
+            "sourdough bread starter flour hydration",
+            "rust ownership and lifetimes explained",
+            "baking sourdough with a starter",
+        ];
+        let nearest = nearest(&vectors(&texts), 1);
+
+        assert_eq!(nearest[0][0].0, 2);
+        assert_eq!(nearest[1][0].0, 3);
+        assert_eq!(nearest[2][0].0, 0);
+        assert!(nearest[0][0].1 <= 1.0);
+    }
+
+    #[test]
+    fn test_build_index_and_query() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        for (url, title, text) in [
+            (
+                "https://a.example/",
+                "Borrowing",
+                "rust borrow checker ownership",
+            ),
+            (
+                "https://b.example/",
+                "Lifetimes",
+                "rust ownership lifetimes",
+            ),
+            ("https://c.example/", "Bread", "sourdough starter hydration"),
+        ] {
+            cache.insert(&CachedLink::new(
+                url.to_string(),
+                title.to_string(),
+                LinkSource::Manual,
+                Vec::new(),
+                text.to_string(),
+            ))?;
+        }
+
+        assert_eq!(cache.count_unindexed_related()?, 3);
+        assert_eq!(build_index(&cache)?, 3);
+        assert_eq!(cache.count_unindexed_related()?, 0);
+        let related = cache.query_related("https://a.example/", 5)?;
+        assert_eq!(related.len(), 1);
+        assert_eq!(related[0].title, "Lifetimes");
+        assert!(cache.query_related("https://c.example/", 5)?.is_empty());
+
+        Ok(())
+    }
+}
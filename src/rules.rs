@@ -0,0 +1,536 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Context};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{Cache, CacheType};
+use crate::links::{link_key, read_links, write_links};
+use crate::models::{LinkSource, SerializedLink};
+use crate::output::{emit, progress, Report};
+use crate::scheduler::host_of;
+use crate::search::parse_source;
+
+/// A rule as written in the rules file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    #[serde(rename = "match")]
+    matcher: MatcherSpec,
+    #[serde(default)]
+    add_tags: Vec<String>,
+    #[serde(default)]
+    remove_tags: Vec<String>,
+    collection: Option<String>,
+    /// Bans the whole host of a matched link, not only the link itself
+    #[serde(default)]
+    ban: bool,
+    #[serde(default)]
+    skip: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MatcherSpec {
+    host: Option<String>,
+    url: Option<String>,
+    title: Option<String>,
+    source: Option<String>,
+    note_path: Option<String>,
+    #[serde(default)]
+    keywords: Vec<String>,
+}
+
+/// Everything a rule's matchers check must hold for it to fire.
+struct Matcher {
+    /// The host or any of its subdomains
+    host: Option<String>,
+    url: Option<Regex>,
+    title: Option<Regex>,
+    source: Option<LinkSource>,
+    /// Prefix of the Obsidian note the link was found in
+    note_path: Option<String>,
+    /// Words or phrases that must all appear in the cached article text
+    keywords: Vec<String>,
+}
+
+struct Rule {
+    name: String,
+    matcher: Matcher,
+    add_tags: Vec<String>,
+    remove_tags: Vec<String>,
+    collection: Option<String>,
+    ban: bool,
+    skip: bool,
+}
+
+/// Lower-cased words separated by single spaces and padded with one at each
+/// end, so phrases can be found with `contains(" phrase ")`.
+fn words(text: &str) -> String {
+    let mut out = String::from(" ");
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        out.push_str(&word.to_lowercase());
+        out.push(' ');
+    }
+    out
+}
+
+impl Matcher {
+    fn parse(spec: MatcherSpec) -> anyhow::Result<Self> {
+        let regex = |pattern: Option<String>| {
+            pattern
+                .map(|pattern| {
+                    Regex::new(&pattern).with_context(|| format!("Invalid regex {pattern}"))
+                })
+                .transpose()
+        };
+        Ok(Matcher {
+            host: spec
+                .host
+                .map(|host| host.trim_start_matches("www.").to_lowercase()),
+            url: regex(spec.url)?,
+            title: regex(spec.title)?,
+            source: spec.source.as_deref().map(parse_source).transpose()?,
+            note_path: spec.note_path,
+            keywords: spec
+                .keywords
+                .iter()
+                .map(|keyword| words(keyword))
+                .filter(|keyword| keyword.trim() != "")
+                .collect(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.host.is_none()
+            && self.url.is_none()
+            && self.title.is_none()
+            && self.source.is_none()
+            && self.note_path.is_none()
+            && self.keywords.is_empty()
+    }
+
+    /// Each matcher the rule sets, with whether `link` satisfies it.
+    fn check(&self, link: &SerializedLink, text: Option<&str>) -> Vec<(&'static str, bool)> {
+        let mut checks = Vec::new();
+        if let Some(host) = &self.host {
+            let link_host = host_of(&link.url);
+            let link_host = link_host.trim_start_matches("www.");
+            let matched = link_host == host || link_host.ends_with(&format!(".{host}"));
+            checks.push(("host", matched));
+        }
+        if let Some(url) = &self.url {
+            checks.push(("url", url.is_match(&link.url)));
+        }
+        if let Some(title) = &self.title {
+            checks.push(("title", title.is_match(&link.title)));
+        }
+        if let Some(source) = self.source {
+            checks.push(("source", link.source == source));
+        }
+        if let Some(prefix) = &self.note_path {
+            let matched = link
+                .note_path
+                .as_deref()
+                .is_some_and(|path| path.starts_with(prefix.as_str()));
+            checks.push(("note_path", matched));
+        }
+        if !self.keywords.is_empty() {
+            let text = words(text.unwrap_or_default());
+            let matched = self.keywords.iter().all(|keyword| text.contains(keyword));
+            checks.push(("keywords", matched));
+        }
+        checks
+    }
+}
+
+/// Rules in the order they are applied.
+pub struct Rules(Vec<Rule>);
+
+/// What applying the rules to a link did.
+#[derive(Default)]
+pub struct Outcome {
+    /// Names of the rules that fired, in order
+    pub fired: Vec<String>,
+    pub ban: bool,
+    pub skip: bool,
+}
+
+impl Rules {
+    pub fn parse(json: &str) -> anyhow::Result<Self> {
+        let specs: Vec<RuleSpec> = serde_json::from_str(json)?;
+        let mut names = HashSet::new();
+        let mut rules = Vec::new();
+        for spec in specs {
+            if !names.insert(spec.name.clone()) {
+                bail!("More than one rule is named {}", spec.name);
+            }
+            let name = spec.name;
+            let matcher =
+                Matcher::parse(spec.matcher).with_context(|| format!("In rule {name}"))?;
+            if matcher.is_empty() {
+                bail!("Rule {name} has no matchers; it would apply to every link");
+            }
+            rules.push(Rule {
+                name,
+                matcher,
+                add_tags: spec.add_tags,
+                remove_tags: spec.remove_tags,
+                collection: spec.collection,
+                ban: spec.ban,
+                skip: spec.skip,
+            });
+        }
+        Ok(Rules(rules))
+    }
+
+    /// Reads the rules file, treating a missing file as having no rules.
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => Rules::parse(&json).with_context(|| format!("Failed to parse {path}")),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Rules(Vec::new())),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {path}")),
+        }
+    }
+
+    /// Applies every matching rule to `link` in file order: tags are added and
+    /// removed as each rule fires, and the last collection set wins.
+    pub fn apply(&self, link: &mut SerializedLink, text: Option<&str>) -> Outcome {
+        let mut outcome = Outcome::default();
+        for rule in &self.0 {
+            if !rule
+                .matcher
+                .check(link, text)
+                .iter()
+                .all(|(_, matched)| *matched)
+            {
+                continue;
+            }
+            link.tags.retain(|tag| !rule.remove_tags.contains(tag));
+            for tag in &rule.add_tags {
+                if !link.tags.contains(tag) {
+                    link.tags.push(tag.clone());
+                }
+            }
+            if let Some(collection) = &rule.collection {
+                link.collection = Some(collection.clone());
+            }
+            outcome.ban |= rule.ban;
+            outcome.skip |= rule.skip;
+            outcome.fired.push(rule.name.clone());
+        }
+        outcome
+    }
+}
+
+#[derive(Serialize)]
+struct RulesReport {
+    /// Links whose tags or collection changed
+    updated: usize,
+    /// Links skipped or on banned hosts, removed from links.json
+    dropped: usize,
+}
+
+impl Report for RulesReport {
+    const KIND: &'static str = "rules";
+
+    fn text(&self) -> String {
+        format!(
+            "Rules updated {} links and dropped {}",
+            self.updated, self.dropped
+        )
+    }
+}
+
+/// Applies the rules to every link in links.json before fetching. Content
+/// keywords are matched against text cached so far; `apply_keyword_rules`
+/// runs the rules that have them again once new links are fetched. Skipped
+/// links and links on banned hosts are dropped from links.json.
+pub fn apply_rules(path: &str, verbose: bool) -> anyhow::Result<()> {
+    apply_to_links(&Rules::load(path)?, verbose)
+}
+
+/// Applies the rules with content keywords again after fetching, so links
+/// cached by this import are matched against their text too.
+pub fn apply_keyword_rules(path: &str, verbose: bool) -> anyhow::Result<()> {
+    let Rules(rules) = Rules::load(path)?;
+    let keyword_rules = rules
+        .into_iter()
+        .filter(|rule| !rule.matcher.keywords.is_empty())
+        .collect();
+    apply_to_links(&Rules(keyword_rules), verbose)
+}
+
+fn apply_to_links(rules: &Rules, verbose: bool) -> anyhow::Result<()> {
+    if rules.0.is_empty() {
+        return Ok(());
+    }
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+
+    let mut links = read_links()?;
+    let mut changed = 0;
+    let mut dropped = 0;
+    let mut kept = Vec::with_capacity(links.len());
+    for mut link in links.drain(..) {
+        let text = cache.query(&link.url)?.map(|cached| cached.text_content);
+        let before = (link.tags.clone(), link.collection.clone());
+        let outcome = rules.apply(&mut link, text.as_deref());
+
+        if outcome.ban {
+            cache.ban_host(&host_of(&link.url))?;
+        }
+        if outcome.ban || outcome.skip {
+            if verbose {
+                progress!(
+                    "Dropped {} ({}): {}",
+                    link.title,
+                    link.url,
+                    outcome.fired.join(", ")
+                );
+            }
+            dropped += 1;
+            continue;
+        }
+        if (&link.tags, &link.collection) != (&before.0, &before.1) {
+            if verbose {
+                progress!(
+                    "Updated {} ({}): {}",
+                    link.title,
+                    link.url,
+                    outcome.fired.join(", ")
+                );
+            }
+            if link.tags != before.0 {
+                cache.update_tags(&link.url, &link.tags)?;
+            }
+            changed += 1;
+        }
+        kept.push(link);
+    }
+
+    write_links(&kept)?;
+    emit(&RulesReport {
+        updated: changed,
+        dropped,
+    })
+}
+
+#[derive(Serialize)]
+struct RuleCheck {
+    rule: String,
+    fires: bool,
+    /// Matchers of the rule that did not match the link
+    unmatched: Vec<String>,
+}
+
+impl Report for RuleCheck {
+    const KIND: &'static str = "rule_check";
+
+    fn text(&self) -> String {
+        if self.fires {
+            format!("  fires  {}", self.rule)
+        } else {
+            format!(
+                "  skips  {} ({} did not match)",
+                self.rule,
+                self.unmatched.join(", ")
+            )
+        }
+    }
+}
+
+/// What the rules would do to a link, as reported by `rules test`.
+#[derive(Serialize)]
+struct RuleTestReport {
+    url: String,
+    fired: Vec<String>,
+    /// The host that would be banned
+    ban: Option<String>,
+    /// Whether the link would be dropped from links.json
+    drop: bool,
+    added_tags: Vec<String>,
+    removed_tags: Vec<String>,
+    collection: Option<String>,
+}
+
+impl Report for RuleTestReport {
+    const KIND: &'static str = "rule_test";
+
+    fn text(&self) -> String {
+        let mut lines = vec![String::new()];
+        if let Some(host) = &self.ban {
+            lines.push(format!(
+                "Would ban {host}, so no link on it is fetched again"
+            ));
+        }
+        if self.drop {
+            lines.push("Would drop the link from links.json".to_string());
+            return lines.join("\n");
+        }
+        if !self.added_tags.is_empty() || !self.removed_tags.is_empty() {
+            let changes: Vec<String> = self
+                .added_tags
+                .iter()
+                .map(|tag| format!("+{tag}"))
+                .chain(self.removed_tags.iter().map(|tag| format!("-{tag}")))
+                .collect();
+            lines.push(format!("Tags: {}", changes.join(" ")));
+        }
+        if let Some(collection) = &self.collection {
+            lines.push(format!("Collection: {collection}"));
+        }
+        if self.fired.is_empty() {
+            lines.push("No rules fired".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+/// Explains which rules fire for `url` and what they would do, without changing anything.
+pub fn test_rules(path: &str, url: &str) -> anyhow::Result<()> {
+    let rules = Rules::load(path)?;
+    if rules.0.is_empty() {
+        progress!("No rules in {path}");
+        return Ok(());
+    }
+    // Testing changes nothing, so don't create cache.db if it isn't there yet
+    let cache = if std::path::Path::new("cache.db").exists() {
+        Cache::new(CacheType::Disk("cache.db".to_string()))?
+    } else {
+        Cache::new(CacheType::Memory)?
+    };
+    let cached = cache.query(url)?;
+
+    let key = link_key(url);
+    let mut link = match read_links()?
+        .into_iter()
+        .find(|link| link_key(&link.url) == key)
+    {
+        Some(link) => link,
+        None => {
+            progress!("{url} is not in links.json; testing it as a new manual link");
+            let title = cached.as_ref().map_or(url, |cached| cached.title.as_str());
+            SerializedLink::new(
+                url.to_string(),
+                title.to_string(),
+                Vec::new(),
+                LinkSource::Manual,
+            )
+        }
+    };
+    let text = cached.map(|cached| cached.text_content);
+    if text.is_none() {
+        progress!("{url} is not cached; keyword matchers will not match");
+    }
+
+    for rule in &rules.0 {
+        let checks = rule.matcher.check(&link, text.as_deref());
+        let unmatched: Vec<String> = checks
+            .iter()
+            .filter(|(_, matched)| !matched)
+            .map(|(name, _)| name.to_string())
+            .collect();
+        emit(&RuleCheck {
+            rule: rule.name.clone(),
+            fires: unmatched.is_empty(),
+            unmatched,
+        })?;
+    }
+
+    let before = link.tags.clone();
+    let outcome = rules.apply(&mut link, text.as_deref());
+    let added_tags = link
+        .tags
+        .iter()
+        .filter(|tag| !before.contains(tag))
+        .cloned()
+        .collect();
+    let removed_tags = before
+        .iter()
+        .filter(|tag| !link.tags.contains(tag))
+        .cloned()
+        .collect();
+    emit(&RuleTestReport {
+        url: link.url.clone(),
+        ban: outcome.ban.then(|| host_of(&link.url)),
+        drop: outcome.ban || outcome.skip,
+        added_tags,
+        removed_tags,
+        collection: link.collection.clone(),
+        fired: outcome.fired,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"[
+        {
+            "name": "arxiv papers",
+            "match": { "host": "arxiv.org" },
+            "add_tags": ["paper"],
+            "collection": "Papers"
+        },
+        {
+            "name": "work notes",
+            "match": { "source": "obsidian", "note_path": "Work/" },
+            "add_tags": ["work"],
+            "remove_tags": ["inbox"]
+        },
+        {
+            "name": "transformer papers",
+            "match": { "title": "(?i)attention", "keywords": ["self-attention"] },
+            "add_tags": ["transformers"]
+        },
+        {
+            "name": "no trackers",
+            "match": { "url": "[?&]utm_source=" },
+            "skip": true
+        }
+    ]"#;
+
+    #[test]
+    fn test_rules_fire_in_order() -> anyhow::Result<()> {
+        let rules = Rules::parse(RULES)?;
+
+        let mut paper = SerializedLink::new(
+            "https://export.arxiv.org/abs/1706.03762".to_string(),
+            "Attention Is All You Need".to_string(),
+            Vec::new(),
+            LinkSource::Zotero,
+        );
+        let outcome = rules.apply(&mut paper, Some("We rely on Self-Attention only."));
+        assert_eq!(outcome.fired, ["arxiv papers", "transformer papers"]);
+        assert_eq!(paper.tags, ["paper", "transformers"]);
+        assert_eq!(paper.collection.as_deref(), Some("Papers"));
+        assert!(!outcome.skip);
+
+        let mut note = SerializedLink {
+            note_path: Some("Work/standup.md".to_string()),
+            ..SerializedLink::new(
+                "https://example.org/?utm_source=feed".to_string(),
+                "Example".to_string(),
+                vec!["inbox".to_string()],
+                LinkSource::Obsidian,
+            )
+        };
+        let outcome = rules.apply(&mut note, None);
+        assert_eq!(outcome.fired, ["work notes", "no trackers"]);
+        assert_eq!(note.tags, ["work"]);
+        assert!(outcome.skip);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_reject_matchless_and_unknown_fields() {
+        assert!(Rules::parse(r#"[{ "name": "all", "match": {}, "skip": true }]"#).is_err());
+        assert!(Rules::parse(r#"[{ "name": "typo", "match": { "hots": "a.org" } }]"#).is_err());
+        assert!(Rules::parse(r#"[{ "name": "bad", "match": { "url": "(" } }]"#).is_err());
+    }
+}
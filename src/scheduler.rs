@@ -0,0 +1,294 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use url::Url;
+
+use crate::cli::PolitenessArgs;
+
+pub struct Politeness {
+    /// Number of worker threads making requests concurrently
+    pub jobs: usize,
+    /// Maximum concurrent requests to a single host
+    pub per_host: usize,
+    /// Minimum time between starting two requests to the same host
+    pub host_delay: Duration,
+}
+
+impl From<&PolitenessArgs> for Politeness {
+    fn from(val: &PolitenessArgs) -> Self {
+        Politeness {
+            jobs: val.jobs,
+            per_host: val.per_host,
+            host_delay: Duration::from_millis(val.host_delay_ms),
+        }
+    }
+}
+
+struct HostQueue<T> {
+    items: VecDeque<T>,
+    active: usize,
+    next_start: Instant,
+}
+
+/// Hands out work items to workers while keeping each host within its
+/// concurrency limit and request spacing.
+struct Scheduler<T> {
+    hosts: Mutex<HashMap<String, HostQueue<T>>>,
+    changed: Condvar,
+    per_host: usize,
+    host_delay: Duration,
+}
+
+impl<T> Scheduler<T> {
+    fn new(items: Vec<(String, T)>, per_host: usize, host_delay: Duration) -> Self {
+        let now = Instant::now();
+        let mut hosts: HashMap<String, HostQueue<T>> = HashMap::new();
+        for (host, item) in items {
+            hosts
+                .entry(host)
+                .or_insert_with(|| HostQueue {
+                    items: VecDeque::new(),
+                    active: 0,
+                    next_start: now,
+                })
+                .items
+                .push_back(item);
+        }
+        Scheduler {
+            hosts: Mutex::new(hosts),
+            changed: Condvar::new(),
+            per_host: per_host.max(1),
+            host_delay,
+        }
+    }
+
+    /// Blocks until some host may be requested, returning `None` once every item has been handed out.
+    fn next(&self) -> Option<(String, T)> {
+        let mut hosts = self.hosts.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            let mut earliest: Option<Instant> = None;
+            let mut any_pending = false;
+
+            for (host, queue) in hosts.iter_mut() {
+                if queue.items.is_empty() {
+                    continue;
+                }
+                any_pending = true;
+                if queue.active >= self.per_host {
+                    continue;
+                }
+                if queue.next_start <= now {
+                    queue.active += 1;
+                    queue.next_start = now + self.host_delay;
+                    let item = queue.items.pop_front()?;
+                    return Some((host.clone(), item));
+                }
+                earliest = Some(earliest.map_or(queue.next_start, |e| e.min(queue.next_start)));
+            }
+
+            if !any_pending {
+                return None;
+            }
+
+            // Either wait for the next host to come off its delay, or for a request to finish
+            hosts = match earliest {
+                Some(at) => {
+                    self.changed
+                        .wait_timeout(hosts, at.saturating_duration_since(now))
+                        .unwrap()
+                        .0
+                }
+                None => self.changed.wait(hosts).unwrap(),
+            };
+        }
+    }
+
+    fn finish(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(queue) = hosts.get_mut(host) {
+            queue.active -= 1;
+        }
+        self.changed.notify_all();
+    }
+}
+
+/// Spaces out requests made from within `run_politely` work, such as a
+/// page's images and stylesheets, so each host still sees at most one
+/// request per `host_delay` from them.
+pub struct HostPacer {
+    next_start: Mutex<HashMap<String, Instant>>,
+    host_delay: Duration,
+}
+
+impl HostPacer {
+    pub fn new(host_delay: Duration) -> Self {
+        HostPacer {
+            next_start: Mutex::new(HashMap::new()),
+            host_delay,
+        }
+    }
+
+    /// Blocks until `host` may be requested, reserving that slot.
+    pub fn wait(&self, host: &str) {
+        let start = {
+            let mut next_start = self.next_start.lock().unwrap();
+            let now = Instant::now();
+            let start = next_start.get(host).map_or(now, |&next| next.max(now));
+            next_start.insert(host.to_string(), start + self.host_delay);
+            start
+        };
+        std::thread::sleep(start.saturating_duration_since(Instant::now()));
+    }
+}
+
+pub fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Runs `work` on every item from a pool of worker threads, spacing out
+/// requests per host. Results are handed to `on_result` on the calling
+/// thread, so it can own non-thread-safe state such as the cache connection.
+pub fn run_politely<T, R>(
+    items: Vec<T>,
+    url_of: impl Fn(&T) -> &str,
+    politeness: &Politeness,
+    work: impl Fn(&T) -> R + Sync,
+    mut on_result: impl FnMut(T, R) -> anyhow::Result<()>,
+) -> anyhow::Result<()>
+where
+    T: Send,
+    R: Send,
+{
+    let items = items
+        .into_iter()
+        .map(|item| (host_of(url_of(&item)), item))
+        .collect();
+    let scheduler = Scheduler::new(items, politeness.per_host, politeness.host_delay);
+    let (tx, rx) = mpsc::channel::<(T, R)>();
+
+    std::thread::scope(|scope| {
+        for _ in 0..politeness.jobs.max(1) {
+            let tx = tx.clone();
+            let scheduler = &scheduler;
+            let work = &work;
+            scope.spawn(move || {
+                while let Some((host, item)) = scheduler.next() {
+                    let result = work(&item);
+                    scheduler.finish(&host);
+                    if tx.send((item, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        for (item, result) in rx {
+            on_result(item, result)?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(url: &str) -> (String, String) {
+        (host_of(url), url.to_string())
+    }
+
+    #[test]
+    fn test_scheduler_limits_concurrency_per_host() {
+        let scheduler = Scheduler::new(
+            vec![
+                item("https://a.example.org/1"),
+                item("https://a.example.org/2"),
+                item("https://b.example.org/1"),
+            ],
+            1,
+            Duration::ZERO,
+        );
+
+        let (first_host, _) = scheduler.next().unwrap();
+        let (second_host, _) = scheduler.next().unwrap();
+        assert_ne!(first_host, second_host);
+
+        // The remaining item's host is busy until one of the requests finishes
+        scheduler.finish("a.example.org");
+        let (third_host, third) = scheduler.next().unwrap();
+        assert_eq!(third_host, "a.example.org");
+        assert_eq!(third, "https://a.example.org/2");
+
+        scheduler.finish("a.example.org");
+        scheduler.finish("b.example.org");
+        assert!(scheduler.next().is_none());
+    }
+
+    #[test]
+    fn test_scheduler_spaces_requests_to_the_same_host() {
+        let delay = Duration::from_millis(50);
+        let scheduler = Scheduler::new(
+            vec![
+                item("https://a.example.org/1"),
+                item("https://a.example.org/2"),
+            ],
+            2,
+            delay,
+        );
+
+        let start = Instant::now();
+        scheduler.next().unwrap();
+        scheduler.next().unwrap();
+
+        assert!(start.elapsed() >= delay);
+    }
+
+    #[test]
+    fn test_run_politely_delivers_every_result() -> anyhow::Result<()> {
+        let urls: Vec<String> = (0..20)
+            .map(|i| format!("https://host{}.example.org/{i}", i % 3))
+            .collect();
+        let politeness = Politeness {
+            jobs: 4,
+            per_host: 2,
+            host_delay: Duration::ZERO,
+        };
+
+        let mut results = Vec::new();
+        run_politely(
+            urls.clone(),
+            |url| url.as_str(),
+            &politeness,
+            |url| url.len(),
+            |url, len| {
+                assert_eq!(url.len(), len);
+                results.push(url);
+                Ok(())
+            },
+        )?;
+
+        results.sort();
+        let mut expected = urls;
+        expected.sort();
+        assert_eq!(results, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_pacer_spaces_requests_per_host() {
+        let pacer = HostPacer::new(Duration::from_millis(50));
+        let start = Instant::now();
+        pacer.wait("a.example.org");
+        pacer.wait("b.example.org");
+        assert!(start.elapsed() < Duration::from_millis(50));
+        pacer.wait("a.example.org");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,146 @@
+use std::io::IsTerminal;
+
+use anyhow::bail;
+
+use crate::cache::{Cache, CacheType};
+use crate::models::{LinkSource, SearchFilters, SearchResult};
+use crate::output::{emit, is_json, progress, Report};
+
+const FTS_OPERATORS: &[&str] = &["AND", "OR", "NOT"];
+
+/// Turns a user's query into FTS5 syntax. Quoted phrases, trailing `*`
+/// prefixes and upper-case AND/OR/NOT pass through; every other word is
+/// quoted so punctuation such as `c++` or `don't` can't break the query.
+pub fn to_fts_query(input: &str) -> String {
+    let mut terms = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        if let Some(after_quote) = rest.strip_prefix('"') {
+            let (phrase, remainder) = after_quote.split_once('"').unwrap_or((after_quote, ""));
+            let (prefix, remainder) = match remainder.strip_prefix('*') {
+                Some(remainder) => ("*", remainder),
+                None => ("", remainder),
+            };
+            if !phrase.trim().is_empty() {
+                terms.push(format!("\"{}\"{prefix}", phrase.trim()));
+            }
+            rest = remainder.trim_start();
+            continue;
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '"')
+            .unwrap_or(rest.len());
+        let (word, remainder) = rest.split_at(end);
+        rest = remainder.trim_start();
+
+        if FTS_OPERATORS.contains(&word) {
+            terms.push(word.to_string());
+            continue;
+        }
+        let (word, prefix) = match word.strip_suffix('*') {
+            Some(word) => (word, "*"),
+            None => (word, ""),
+        };
+        if !word.is_empty() {
+            terms.push(format!("\"{}\"{prefix}", word.replace('"', "")));
+        }
+    }
+    terms.join(" ")
+}
+
+pub fn parse_source(name: &str) -> anyhow::Result<LinkSource> {
+    match LinkSource::ALL
+        .iter()
+        .find(|source| source.as_str().eq_ignore_ascii_case(name))
+    {
+        Some(source) => Ok(*source),
+        None => {
+            let names: Vec<_> = LinkSource::ALL.iter().map(|s| s.as_str()).collect();
+            bail!(
+                "Unknown source {name}; expected one of {}",
+                names.join(", ")
+            )
+        }
+    }
+}
+
+pub struct SearchOptions {
+    pub query: String,
+    pub source: Option<String>,
+    pub tags: Vec<String>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: usize,
+}
+
+impl Report for SearchResult {
+    const KIND: &'static str = "search_result";
+
+    fn text(&self) -> String {
+        let date = self.date.as_deref().unwrap_or("");
+        let snippet = self
+            .snippet
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "{} [{}] {date}\n  {}\n  {snippet}\n",
+            self.title,
+            self.source.as_str(),
+            self.url
+        )
+    }
+}
+
+pub fn search_cache(options: &SearchOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let filters = SearchFilters {
+        source: options.source.as_deref().map(parse_source).transpose()?,
+        tags: options.tags.clone(),
+        since: options.since.clone(),
+        until: options.until.clone(),
+        limit: options.limit,
+    };
+
+    let highlight = if is_json() {
+        ("<mark>", "</mark>")
+    } else if std::io::stdout().is_terminal() {
+        ("\x1b[1m", "\x1b[0m")
+    } else {
+        ("**", "**")
+    };
+    let results = cache.search(&to_fts_query(&options.query), &filters, highlight)?;
+
+    if results.is_empty() {
+        progress!("No matches for {}", options.query);
+    }
+    for result in &results {
+        emit(result)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_fts_query() {
+        assert_eq!(to_fts_query("rust async"), r#""rust" "async""#);
+        assert_eq!(
+            to_fts_query(r#"  "error handling" async* OR c++ "#),
+            r#""error handling" "async"* OR "c++""#
+        );
+        assert_eq!(to_fts_query(r#"don't "unclosed"#), r#""don't" "unclosed""#);
+        assert_eq!(to_fts_query(r#""#), "");
+    }
+
+    #[test]
+    fn test_parse_source() -> anyhow::Result<()> {
+        assert_eq!(parse_source("zotero")?, LinkSource::Zotero);
+        assert!(parse_source("delicious").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,579 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use anyhow::anyhow;
+use serde_json::json;
+use tiny_http::{Header, Response, Server};
+use url::Url;
+
+use crate::cache::{Cache, CacheType};
+use crate::extract::{escape_html, paragraphs_to_html};
+use crate::manual::add_to_links;
+use crate::metadata::byline;
+use crate::models::{SearchFilters, SearchResult};
+use crate::output::progress;
+use crate::search::{parse_source, to_fts_query};
+use crate::template::Template;
+
+const PAGE_SIZE: usize = 50;
+const MAX_API_LIMIT: usize = 500;
+/// Private-use characters marking matches in search snippets, swapped for
+/// `<mark>` once the snippet has been escaped
+const MATCH_OPEN: &str = "\u{E000}";
+const MATCH_CLOSE: &str = "\u{E001}";
+
+const LAYOUT: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title | html}}</title>
+<style>
+body { font: 16px/1.5 system-ui, sans-serif; max-width: 48rem; margin: 0 auto; padding: 1rem; }
+nav { display: flex; gap: 1rem; align-items: center; margin-bottom: 1rem; }
+nav form { margin-left: auto; }
+li { margin-bottom: 0.5rem; }
+small, .meta { color: #666; }
+mark { background: #ffe680; }
+article p { white-space: pre-line; }
+</style>
+</head>
+<body>
+<nav><a href="/">Links</a><a href="/tags">Tags</a>
+<form action="/search"><input name="q" value="{{query | html}}" placeholder="Search"></form></nav>
+{{body}}
+</body>
+</html>
+"#;
+
+const LINKS_PAGE: &str = r#"<h1>{{heading | html}}</h1>
+<ul>
+{{#links}}
+<li><a href="/links/{{id}}">{{title | html}}</a><br>
+<small>{{source}} {{date | html}} {{#tags}}<a href="/?tag={{. | url | html}}">#{{. | html}}</a> {{/tags}}</small>
+{{#snippet}}<br>{{snippet}}{{/snippet}}</li>
+{{/links}}
+</ul>
+{{^links}}
+<p>No links found.</p>
+{{/links}}
+<p>{{#previous}}<a href="{{previous | html}}">Previous</a> {{/previous}}{{#next}}<a href="{{next | html}}">Next</a>{{/next}}</p>
+{{#bookmarklet}}
+<p class="meta">Drag to your bookmarks bar to save pages here: <a href="{{bookmarklet | html}}">Add to links</a></p>
+{{/bookmarklet}}
+"#;
+
+const TAGS_PAGE: &str = r#"<h1>Tags</h1>
+<ul>
+{{#tags}}
+<li><a href="/?tag={{name | url | html}}">{{name | html}}</a> <small>{{count}}</small></li>
+{{/tags}}
+</ul>
+"#;
+
+const ARTICLE_PAGE: &str = r#"<article>
+<h1>{{title | html}}</h1>
+<p class="meta"><a href="{{url | html}}">{{url | html}}</a>{{#byline}}<br>{{byline | html}}{{/byline}}<br>
+{{source}} {{#tags}}<a href="/?tag={{. | url | html}}">#{{. | html}}</a> {{/tags}}</p>
+{{text}}
+</article>
+"#;
+
+const MESSAGE_PAGE: &str = r#"<h1>{{heading | html}}</h1>
+<p>{{message | html}}</p>
+{{#url}}<p><a href="{{url | html}}">Back to {{url | html}}</a></p>{{/url}}
+"#;
+
+struct Templates {
+    layout: Template,
+    links: Template,
+    tags: Template,
+    article: Template,
+    message: Template,
+}
+
+impl Templates {
+    fn new() -> anyhow::Result<Self> {
+        Ok(Templates {
+            layout: Template::parse(LAYOUT)?,
+            links: Template::parse(LINKS_PAGE)?,
+            tags: Template::parse(TAGS_PAGE)?,
+            article: Template::parse(ARTICLE_PAGE)?,
+            message: Template::parse(MESSAGE_PAGE)?,
+        })
+    }
+}
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(value: &impl serde::Serialize) -> anyhow::Result<Self> {
+        Ok(Reply {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_string_pretty(value)?,
+        })
+    }
+
+    fn api_error(status: u16, message: &str) -> Self {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message }).to_string(),
+        }
+    }
+}
+
+/// Query string parameters, ignoring empty values.
+struct Params(Vec<(String, String)>);
+
+impl Params {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, v)| k == key && !v.is_empty())
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn all(&self, key: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(k, v)| k == key && !v.is_empty())
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    fn number(&self, key: &str, default: usize) -> Result<usize, String> {
+        match self.get(key) {
+            Some(value) => value.parse().map_err(|_| format!("{key} must be a number")),
+            None => Ok(default),
+        }
+    }
+
+    fn filters(&self, limit: usize) -> Result<SearchFilters, String> {
+        Ok(SearchFilters {
+            source: self
+                .get("source")
+                .map(parse_source)
+                .transpose()
+                .map_err(|e| e.to_string())?,
+            tags: self.all("tag"),
+            since: self.get("since").map(str::to_string),
+            until: self.get("until").map(str::to_string),
+            limit,
+        })
+    }
+}
+
+/// Escapes a search snippet for HTML, marking up the matched words.
+fn snippet_html(snippet: &str) -> String {
+    escape_html(snippet)
+        .replace(MATCH_OPEN, "<mark>")
+        .replace(MATCH_CLOSE, "</mark>")
+}
+
+struct App {
+    cache: Cache,
+    templates: Templates,
+    /// Secret the bookmarklet sends to `/add`, so other pages the browser
+    /// opens cannot add links through it
+    token: String,
+}
+
+/// A random token for this run of the server, from the process's hash seeds.
+fn new_token() -> String {
+    let (a, b) = (RandomState::new(), RandomState::new());
+    format!(
+        "{:016x}{:016x}",
+        a.hash_one(std::process::id()),
+        b.hash_one(std::time::SystemTime::now())
+    )
+}
+
+impl App {
+    fn page(&self, status: u16, title: &str, query: &str, body: String) -> Reply {
+        Reply {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body: self.templates.layout.render(&json!({
+                "title": title,
+                "query": query,
+                "body": body,
+            })),
+        }
+    }
+
+    fn message(&self, status: u16, heading: &str, message: &str, url: Option<&str>) -> Reply {
+        let body = self.templates.message.render(&json!({
+            "heading": heading,
+            "message": message,
+            "url": url,
+        }));
+        self.page(status, heading, "", body)
+    }
+
+    fn respond(&self, method: &str, target: &str, host: &str) -> anyhow::Result<Reply> {
+        let url = Url::parse(&format!("http://{host}"))
+            .and_then(|base| base.join(target))
+            .map_err(|e| anyhow!("Invalid request target {target}: {e}"))?;
+        let params = Params(url.query_pairs().into_owned().collect());
+        let segments: Vec<&str> = url
+            .path_segments()
+            .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+            .unwrap_or_default();
+
+        if method != "GET" && method != "HEAD" {
+            return Ok(Reply::api_error(405, "Only GET requests are supported"));
+        }
+
+        match segments.as_slice() {
+            [] => self.list_page(&params, host),
+            ["search"] => self.search_page(&params),
+            ["tags"] => self.tags_page(),
+            ["links", id] => self.article_page(id),
+            ["add"] => Ok(self.add(&params)),
+            ["api", "links"] => self.api_links(&params),
+            ["api", "search"] => self.api_search(&params),
+            ["api", "links", id] => self.api_link(id),
+            ["api", ..] => Ok(Reply::api_error(404, "Not found")),
+            _ => Ok(self.message(404, "Not found", "There is nothing here.", None)),
+        }
+    }
+
+    fn list_page(&self, params: &Params, host: &str) -> anyhow::Result<Reply> {
+        let (filters, page) = match params
+            .filters(PAGE_SIZE + 1)
+            .and_then(|filters| Ok((filters, params.number("page", 1)?.max(1))))
+        {
+            Ok(parsed) => parsed,
+            Err(e) => return Ok(self.message(400, "Bad request", &e, None)),
+        };
+        let mut links = self.cache.query_links(&filters, (page - 1) * PAGE_SIZE)?;
+        let has_next = links.len() > PAGE_SIZE;
+        links.truncate(PAGE_SIZE);
+
+        let page_url = |page: usize| {
+            let mut query = url::form_urlencoded::Serializer::new(String::new());
+            for (key, value) in params.0.iter().filter(|(key, _)| key != "page") {
+                query.append_pair(key, value);
+            }
+            query.append_pair("page", &page.to_string());
+            format!("/?{}", query.finish())
+        };
+        let heading = match (filters.tags.as_slice(), &filters.source) {
+            ([], None) => "Links".to_string(),
+            (tags, source) => format!(
+                "Links {}",
+                tags.iter()
+                    .map(|tag| format!("#{tag}"))
+                    .chain(source.map(|source| source.as_str().to_string()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        };
+        let bookmarklet = format!(
+            "javascript:location.href='http://{host}/add?token={}&url='+encodeURIComponent(location.href)+'&title='+encodeURIComponent(document.title)",
+            self.token
+        );
+
+        let body = self.templates.links.render(&json!({
+            "heading": heading,
+            "links": links,
+            "previous": (page > 1).then(|| page_url(page - 1)),
+            "next": has_next.then(|| page_url(page + 1)),
+            "bookmarklet": bookmarklet,
+        }));
+        Ok(self.page(200, &heading, "", body))
+    }
+
+    fn search_page(&self, params: &Params) -> anyhow::Result<Reply> {
+        let query = params.get("q").unwrap_or_default();
+        let filters = match params.filters(PAGE_SIZE) {
+            Ok(filters) => filters,
+            Err(e) => return Ok(self.message(400, "Bad request", &e, None)),
+        };
+        let results = self.search(query, &filters, (MATCH_OPEN, MATCH_CLOSE))?;
+
+        let links: Vec<_> = results
+            .iter()
+            .map(|result| {
+                json!({
+                    "id": result.id,
+                    "title": result.title,
+                    "source": result.source.as_str(),
+                    "date": result.date,
+                    "tags": result.tags,
+                    "snippet": snippet_html(&result.snippet),
+                })
+            })
+            .collect();
+        let body = self.templates.links.render(&json!({
+            "heading": format!("Search: {query}"),
+            "links": links,
+        }));
+        Ok(self.page(200, &format!("Search: {query}"), query, body))
+    }
+
+    fn search(
+        &self,
+        query: &str,
+        filters: &SearchFilters,
+        highlight: (&str, &str),
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let query = to_fts_query(query);
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.cache.search(&query, filters, highlight)
+    }
+
+    fn tags_page(&self) -> anyhow::Result<Reply> {
+        let tags: Vec<_> = self
+            .cache
+            .query_tag_counts()?
+            .into_iter()
+            .map(|(name, count)| json!({ "name": name, "count": count }))
+            .collect();
+        let body = self.templates.tags.render(&json!({ "tags": tags }));
+        Ok(self.page(200, "Tags", "", body))
+    }
+
+    fn article_page(&self, id: &str) -> anyhow::Result<Reply> {
+        let summary = match id.parse().map(|id| self.cache.query_link_by_id(id)) {
+            Ok(summary) => summary?,
+            Err(_) => None,
+        };
+        let Some(summary) = summary else {
+            return Ok(self.message(404, "Not found", "No cached link with that id.", None));
+        };
+        let text = self
+            .cache
+            .query(&summary.url)?
+            .map(|link| link.text_content)
+            .unwrap_or_default();
+        let metadata = self.cache.query_page_metadata(&summary.url)?;
+
+        let body = self.templates.article.render(&json!({
+            "title": summary.title,
+            "url": summary.url,
+            "source": summary.source.as_str(),
+            "tags": summary.tags,
+            "byline": metadata.as_ref().and_then(byline),
+            "text": paragraphs_to_html(&text),
+        }));
+        Ok(self.page(200, &summary.title, "", body))
+    }
+
+    /// Adds a link from the bookmarklet: `/add?token=…&url=…&title=…&tag=…`.
+    fn add(&self, params: &Params) -> Reply {
+        if params.get("token") != Some(self.token.as_str()) {
+            return self.message(
+                403,
+                "Forbidden",
+                "Links can only be added with the bookmarklet from this server's front page.",
+                None,
+            );
+        }
+        let Some(url) = params.get("url") else {
+            return self.message(400, "Bad request", "url is required", None);
+        };
+        match add_to_links(
+            url,
+            params.get("title").map(str::to_string),
+            params.all("tag"),
+        ) {
+            Ok(link) => self.message(
+                200,
+                "Added",
+                &format!(
+                    "Added {} to links.json; it will be cached on the next import.",
+                    link.title
+                ),
+                Some(url),
+            ),
+            Err(e) => self.message(400, "Could not add link", &format!("{e:#}"), Some(url)),
+        }
+    }
+
+    fn api_links(&self, params: &Params) -> anyhow::Result<Reply> {
+        let parsed = params.number("limit", PAGE_SIZE).and_then(|limit| {
+            let filters = params.filters(limit.min(MAX_API_LIMIT))?;
+            Ok((filters, params.number("offset", 0)?))
+        });
+        match parsed {
+            Ok((filters, offset)) => Reply::json(&self.cache.query_links(&filters, offset)?),
+            Err(e) => Ok(Reply::api_error(400, &e)),
+        }
+    }
+
+    fn api_search(&self, params: &Params) -> anyhow::Result<Reply> {
+        let Some(query) = params.get("q") else {
+            return Ok(Reply::api_error(400, "q is required"));
+        };
+        let filters = match params
+            .number("limit", PAGE_SIZE)
+            .and_then(|limit| params.filters(limit.min(MAX_API_LIMIT)))
+        {
+            Ok(filters) => filters,
+            Err(e) => return Ok(Reply::api_error(400, &e)),
+        };
+        Reply::json(&self.search(query, &filters, ("<mark>", "</mark>"))?)
+    }
+
+    fn api_link(&self, id: &str) -> anyhow::Result<Reply> {
+        let Ok(id) = id.parse() else {
+            return Ok(Reply::api_error(400, "id must be a number"));
+        };
+        let Some(summary) = self.cache.query_link_by_id(id)? else {
+            return Ok(Reply::api_error(404, "No cached link with that id"));
+        };
+        let text = self
+            .cache
+            .query(&summary.url)?
+            .map(|link| link.text_content)
+            .unwrap_or_default();
+        let metadata = self
+            .cache
+            .query_page_metadata(&summary.url)?
+            .unwrap_or_default();
+
+        let mut value = serde_json::to_value(&summary)?;
+        value["text_content"] = json!(text);
+        value["metadata"] = serde_json::to_value(&metadata)?;
+        Reply::json(&value)
+    }
+}
+
+/// Serves pages and a JSON API over cache.db until interrupted. Only adding
+/// links needs the bookmarklet's token; everything else is readable without
+/// authentication, so only bind to addresses on a trusted network.
+pub fn serve(bind: &str) -> anyhow::Result<()> {
+    let app = App {
+        cache: Cache::new(CacheType::Disk("cache.db".to_string()))?,
+        templates: Templates::new()?,
+        token: new_token(),
+    };
+    let server = Server::http(bind).map_err(|e| anyhow!("Failed to listen on {bind}: {e}"))?;
+    progress!("Serving cache.db on http://{bind}");
+
+    for request in server.incoming_requests() {
+        let host = request
+            .headers()
+            .iter()
+            .find(|header| header.field.equiv("Host"))
+            .map(|header| header.value.to_string())
+            .unwrap_or_else(|| bind.to_string());
+
+        let reply = app
+            .respond(request.method().as_str(), request.url(), &host)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to handle {}: {e:#}", request.url());
+                Reply::api_error(500, &format!("{e:#}"))
+            });
+
+        let mut response = Response::from_string(reply.body).with_status_code(reply.status);
+        if let Ok(header) = Header::from_bytes("Content-Type", reply.content_type) {
+            response.add_header(header);
+        }
+        if let Err(e) = request.respond(response) {
+            eprintln!("Failed to send response: {e}");
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CachedLink, LinkSource};
+
+    fn app() -> anyhow::Result<App> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://example.org/async".to_string(),
+            "Async <Rust>".to_string(),
+            LinkSource::Manual,
+            vec!["rust".to_string()],
+            "Futures are polled by an executor.".to_string(),
+        ))?;
+        Ok(App {
+            cache,
+            templates: Templates::new()?,
+            token: "secret".to_string(),
+        })
+    }
+
+    #[test]
+    fn test_api_routes() -> anyhow::Result<()> {
+        let app = app()?;
+
+        let reply = app.respond("GET", "/api/links?tag=rust", "localhost")?;
+        assert_eq!(reply.status, 200);
+        let links: serde_json::Value = serde_json::from_str(&reply.body)?;
+        assert_eq!(links[0]["url"], "https://example.org/async");
+        let id = links[0]["id"].as_i64().unwrap();
+
+        let reply = app.respond("GET", &format!("/api/links/{id}"), "localhost")?;
+        let link: serde_json::Value = serde_json::from_str(&reply.body)?;
+        assert_eq!(link["text_content"], "Futures are polled by an executor.");
+
+        let reply = app.respond("GET", "/api/search?q=executor", "localhost")?;
+        let results: serde_json::Value = serde_json::from_str(&reply.body)?;
+        assert!(results[0]["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("<mark>executor</mark>"));
+
+        assert_eq!(
+            app.respond("GET", "/api/links/999", "localhost")?.status,
+            404
+        );
+        assert_eq!(
+            app.respond("GET", "/api/links?source=nowhere", "localhost")?
+                .status,
+            400
+        );
+        assert_eq!(app.respond("POST", "/api/links", "localhost")?.status, 405);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pages_escape_content() -> anyhow::Result<()> {
+        let app = app()?;
+
+        let reply = app.respond("GET", "/", "localhost")?;
+        assert_eq!(reply.status, 200);
+        assert!(reply.body.contains("Async &lt;Rust&gt;"));
+        assert!(reply.body.contains(r#"href="/?tag=rust""#));
+
+        let reply = app.respond("GET", "/search?q=polled", "localhost")?;
+        assert!(reply.body.contains("<mark>polled</mark>"));
+
+        assert_eq!(app.respond("GET", "/links/abc", "localhost")?.status, 404);
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_requires_token() -> anyhow::Result<()> {
+        let app = app()?;
+
+        let reply = app.respond("GET", "/", "localhost:8080")?;
+        assert!(reply
+            .body
+            .contains("http://localhost:8080/add?token=secret&amp;url="));
+
+        for target in [
+            "/add?url=https://evil.example/",
+            "/add?token=guess&url=https://evil.example/",
+        ] {
+            assert_eq!(app.respond("GET", target, "localhost")?.status, 403);
+        }
+        Ok(())
+    }
+}
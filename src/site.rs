@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::cache::{Cache, CacheType};
+use crate::links::read_links;
+use crate::metadata::byline;
+use crate::models::{PageMetadata, SerializedLink};
+use crate::output::{emit, Report};
+use crate::summary::summary_for;
+use crate::template::Template;
+
+/// Excerpts fall back to the start of the article when a page has no description
+const EXCERPT_WORDS: usize = 60;
+/// Links with this tag are never published, whatever else is excluded
+const PRIVATE_TAG: &str = "private";
+
+pub struct SiteOptions {
+    pub out_dir: String,
+    /// Files here named like the built-in templates replace them
+    pub templates_dir: String,
+    /// Links with any of these tags, or tagged private, are left out of the site
+    pub exclude_tags: Vec<String>,
+    pub title: String,
+}
+
+struct SiteTemplates {
+    layout: Template,
+    list: Template,
+    link: Template,
+    tags: Template,
+    search: Template,
+}
+
+impl SiteTemplates {
+    fn load(dir: &Path) -> anyhow::Result<Self> {
+        let load = |name: &str, default: &str| -> anyhow::Result<Template> {
+            let path = dir.join(name);
+            let src = if path.exists() {
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?
+            } else {
+                default.to_string()
+            };
+            Template::parse(&src).with_context(|| format!("Failed to parse template {name}"))
+        };
+
+        Ok(SiteTemplates {
+            layout: load("layout.html", include_str!("../templates/site/layout.html"))?,
+            list: load("list.html", include_str!("../templates/site/list.html"))?,
+            link: load("link.html", include_str!("../templates/site/link.html"))?,
+            tags: load("tags.html", include_str!("../templates/site/tags.html"))?,
+            search: load("search.html", include_str!("../templates/site/search.html"))?,
+        })
+    }
+}
+
+/// A link with what the site shows about it.
+struct Entry {
+    link: SerializedLink,
+    date: Option<String>,
+    metadata: Option<PageMetadata>,
+    excerpt: Option<String>,
+}
+
+fn slugify(name: &str) -> String {
+    let slug = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug
+    }
+}
+
+/// Link pages are named after a hash of the URL so they keep their address
+/// as links are added and removed.
+fn link_page(url: &str) -> String {
+    let hash = Sha256::digest(url.as_bytes());
+    let hex: String = hash.iter().take(6).map(|b| format!("{b:02x}")).collect();
+    format!("links/{hex}.html")
+}
+
+fn tag_page(tag: &str) -> String {
+    format!("tags/{}.html", slugify(tag))
+}
+
+fn source_page(link: &SerializedLink) -> String {
+    format!("sources/{}.html", slugify(link.source.as_str()))
+}
+
+/// The page's own description, else its extractive summary, else the start of its text.
+pub fn excerpt(
+    metadata: Option<&PageMetadata>,
+    summary: Option<&str>,
+    text: Option<&str>,
+) -> Option<String> {
+    if let Some(description) = metadata
+        .and_then(|metadata| metadata.description.as_deref())
+        .or(summary)
+        .filter(|description| !description.trim().is_empty())
+    {
+        return Some(description.trim().to_string());
+    }
+
+    let words: Vec<&str> = text?.split_whitespace().collect();
+    match words.len() {
+        0 => None,
+        n if n <= EXCERPT_WORDS => Some(words.join(" ")),
+        _ => Some(format!("{}…", words[..EXCERPT_WORDS].join(" "))),
+    }
+}
+
+fn entry_context(entry: &Entry) -> Value {
+    let tags: Vec<Value> = entry
+        .link
+        .tags
+        .iter()
+        .map(|tag| json!({ "name": tag, "page": tag_page(tag) }))
+        .collect();
+    json!({
+        "title": entry.link.title,
+        "url": entry.link.url,
+        "page": link_page(&entry.link.url),
+        "date": entry.date,
+        "source": entry.link.source.as_str(),
+        "source_page": source_page(&entry.link),
+        "tags": tags,
+        "excerpt": entry.excerpt,
+        "byline": entry.metadata.as_ref().and_then(byline),
+    })
+}
+
+/// Groups entries, already sorted newest first, under the month they are dated.
+fn by_month(entries: &[&Entry]) -> Vec<Value> {
+    let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+    for entry in entries {
+        let month = entry
+            .date
+            .as_deref()
+            .and_then(|date| date.get(..7))
+            .unwrap_or("Undated");
+        match groups.last_mut() {
+            Some((name, links)) if name == month => links.push(entry_context(entry)),
+            _ => groups.push((month.to_string(), vec![entry_context(entry)])),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(name, links)| json!({ "name": name, "links": links }))
+        .collect()
+}
+
+struct Site<'a> {
+    templates: &'a SiteTemplates,
+    out_dir: &'a Path,
+    title: &'a str,
+}
+
+impl Site<'_> {
+    /// Renders `body` into the layout and writes it to `path`, relative to the site root.
+    fn write_page(
+        &self,
+        path: &str,
+        page_title: &str,
+        body: &Template,
+        mut context: Value,
+    ) -> anyhow::Result<()> {
+        let root = "../".repeat(path.matches('/').count());
+        context["root"] = json!(root);
+        let html = self.templates.layout.render(&json!({
+            "site_title": self.title,
+            "page_title": page_title,
+            "root": root,
+            "body": body.render(&context),
+        }));
+
+        let path: PathBuf = self.out_dir.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&path, html).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn write_list(&self, path: &str, heading: &str, entries: &[&Entry]) -> anyhow::Result<()> {
+        self.write_page(
+            path,
+            heading,
+            &self.templates.list,
+            json!({ "heading": heading, "groups": by_month(entries) }),
+        )
+    }
+}
+
+/// Writes the site for `links` into `options.out_dir`, returning how many links it includes.
+/// The site is built beside it and swapped in, so pages of links since removed
+/// or made private do not linger.
+fn build_site(
+    links: Vec<SerializedLink>,
+    cache: &Cache,
+    templates: &SiteTemplates,
+    options: &SiteOptions,
+) -> anyhow::Result<usize> {
+    let out_dir = Path::new(&options.out_dir);
+    let staging = PathBuf::from(format!("{}.partial", options.out_dir));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)
+            .with_context(|| format!("Failed to remove {}", staging.display()))?;
+    }
+
+    let count = write_site(links, cache, templates, options, &staging)?;
+
+    if out_dir.exists() {
+        std::fs::remove_dir_all(out_dir)
+            .with_context(|| format!("Failed to remove {}", out_dir.display()))?;
+    }
+    std::fs::rename(&staging, out_dir)
+        .with_context(|| format!("Failed to move the site into {}", out_dir.display()))?;
+    Ok(count)
+}
+
+fn write_site(
+    links: Vec<SerializedLink>,
+    cache: &Cache,
+    templates: &SiteTemplates,
+    options: &SiteOptions,
+    out_dir: &Path,
+) -> anyhow::Result<usize> {
+    let dates = cache.query_dates()?;
+    let mut entries = Vec::new();
+    for link in links {
+        let excluded = link.tags.iter().any(|tag| {
+            std::iter::once(PRIVATE_TAG)
+                .chain(options.exclude_tags.iter().map(String::as_str))
+                .any(|excluded| excluded.eq_ignore_ascii_case(tag))
+        });
+        if excluded {
+            continue;
+        }
+        let metadata = cache.query_page_metadata(&link.url)?;
+        let text = cache.query(&link.url)?.map(|cached| cached.text_content);
+        let summary = summary_for(cache, &link.url)?;
+        entries.push(Entry {
+            date: dates.get(&link.url).cloned(),
+            excerpt: excerpt(metadata.as_ref(), summary.as_deref(), text.as_deref()),
+            metadata,
+            link,
+        });
+    }
+    // Newest first, with undated links at the end
+    entries.sort_by(|a, b| {
+        b.date
+            .cmp(&a.date)
+            .then_with(|| a.link.title.cmp(&b.link.title))
+    });
+
+    let site = Site {
+        templates,
+        out_dir,
+        title: &options.title,
+    };
+    let all: Vec<&Entry> = entries.iter().collect();
+    site.write_list("index.html", &options.title, &all)?;
+
+    let mut by_tag: BTreeMap<String, (String, Vec<&Entry>)> = BTreeMap::new();
+    let mut by_source: BTreeMap<String, (&str, Vec<&Entry>)> = BTreeMap::new();
+    for entry in &entries {
+        site.write_page(
+            &link_page(&entry.link.url),
+            &entry.link.title,
+            &templates.link,
+            entry_context(entry),
+        )?;
+        for tag in &entry.link.tags {
+            by_tag
+                .entry(tag_page(tag))
+                .or_insert_with(|| (tag.clone(), Vec::new()))
+                .1
+                .push(entry);
+        }
+        by_source
+            .entry(source_page(&entry.link))
+            .or_insert_with(|| (entry.link.source.as_str(), Vec::new()))
+            .1
+            .push(entry);
+    }
+
+    for (page, (tag, tagged)) in &by_tag {
+        site.write_list(page, &format!("#{tag}"), tagged)?;
+    }
+    for (page, (source, from_source)) in &by_source {
+        site.write_list(page, &format!("From {source}"), from_source)?;
+    }
+
+    let mut tag_counts: Vec<Value> = by_tag
+        .iter()
+        .map(|(page, (tag, tagged))| json!({ "name": tag, "page": page, "count": tagged.len() }))
+        .collect();
+    tag_counts.sort_by_key(|tag| std::cmp::Reverse(tag["count"].as_u64()));
+    site.write_page(
+        "tags/index.html",
+        "Tags",
+        &templates.tags,
+        json!({ "tags": tag_counts }),
+    )?;
+
+    site.write_page("search.html", "Search", &templates.search, json!({}))?;
+    let index: Vec<Value> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "title": entry.link.title,
+                "url": entry.link.url,
+                "page": link_page(&entry.link.url),
+                "date": entry.date,
+                "source": entry.link.source.as_str(),
+                "tags": entry.link.tags,
+                "excerpt": entry.excerpt,
+            })
+        })
+        .collect();
+    let index_path = site.out_dir.join("search-index.json");
+    std::fs::write(&index_path, serde_json::to_string(&index)?)
+        .with_context(|| format!("Failed to write {}", index_path.display()))?;
+
+    Ok(entries.len())
+}
+
+#[derive(serde::Serialize)]
+struct SiteReport {
+    links: usize,
+    out_dir: String,
+}
+
+impl Report for SiteReport {
+    const KIND: &'static str = "site";
+
+    fn text(&self) -> String {
+        format!("Wrote {} links to {}", self.links, self.out_dir)
+    }
+}
+
+pub fn export_site(options: &SiteOptions) -> anyhow::Result<()> {
+    let templates = SiteTemplates::load(Path::new(&options.templates_dir))?;
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+
+    let links = build_site(read_links()?, &cache, &templates, options)?;
+    emit(&SiteReport {
+        links,
+        out_dir: options.out_dir.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CachedLink, LinkSource};
+
+    #[test]
+    fn test_slugify_and_excerpt() {
+        assert_eq!(slugify("Machine Learning/NLP"), "machine-learning-nlp");
+        assert_eq!(slugify("!!"), "untitled");
+
+        let text = "word ".repeat(EXCERPT_WORDS + 5);
+        assert!(excerpt(None, None, Some(&text)).unwrap().ends_with("word…"));
+        assert_eq!(excerpt(None, None, Some("  ")), None);
+        assert_eq!(
+            excerpt(None, Some("The gist."), Some(&text)).as_deref(),
+            Some("The gist.")
+        );
+        let metadata = PageMetadata {
+            description: Some("A summary".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            excerpt(Some(&metadata), Some("The gist."), Some(&text)).as_deref(),
+            Some("A summary")
+        );
+    }
+
+    #[test]
+    fn test_build_site_skips_excluded_tags() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://example.org/public".to_string(),
+            "Public <post>".to_string(),
+            LinkSource::Manual,
+            vec!["rust".to_string()],
+            "Some article text.".to_string(),
+        ))?;
+        let links = vec![
+            SerializedLink::new(
+                "https://example.org/public".to_string(),
+                "Public <post>".to_string(),
+                vec!["rust".to_string()],
+                LinkSource::Manual,
+            ),
+            SerializedLink::new(
+                "https://example.org/diary".to_string(),
+                "Diary".to_string(),
+                vec!["Private".to_string()],
+                LinkSource::Manual,
+            ),
+        ];
+        let out_dir = std::env::temp_dir().join(format!("site-test-{}", std::process::id()));
+        let options = SiteOptions {
+            out_dir: out_dir.to_string_lossy().into_owned(),
+            templates_dir: out_dir.join("no-templates").to_string_lossy().into_owned(),
+            exclude_tags: vec!["work".to_string()],
+            title: "Reading log".to_string(),
+        };
+        let templates = SiteTemplates::load(Path::new(&options.templates_dir))?;
+
+        assert_eq!(build_site(links, &cache, &templates, &options)?, 1);
+
+        let index = std::fs::read_to_string(out_dir.join("index.html"))?;
+        assert!(index.contains("Public &lt;post&gt;"));
+        assert!(!index.contains("Diary"));
+        let tag_page = std::fs::read_to_string(out_dir.join("tags/rust.html"))?;
+        assert!(tag_page.contains(r#"href="../links/"#));
+        let link_page =
+            std::fs::read_to_string(out_dir.join(link_page("https://example.org/public")))?;
+        assert!(link_page.contains("Some article text."));
+        let search_index: Value =
+            serde_json::from_str(&std::fs::read_to_string(out_dir.join("search-index.json"))?)?;
+        assert_eq!(search_index.as_array().map(Vec::len), Some(1));
+
+        std::fs::remove_dir_all(&out_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_site_removes_stale_pages() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        let link = |url: &str, tags: &[&str]| {
+            SerializedLink::new(
+                url.to_string(),
+                "Post".to_string(),
+                tags.iter().map(|tag| tag.to_string()).collect(),
+                LinkSource::Manual,
+            )
+        };
+        let out_dir = std::env::temp_dir().join(format!("site-stale-test-{}", std::process::id()));
+        let options = SiteOptions {
+            out_dir: out_dir.to_string_lossy().into_owned(),
+            templates_dir: out_dir.join("no-templates").to_string_lossy().into_owned(),
+            exclude_tags: Vec::new(),
+            title: "Reading log".to_string(),
+        };
+        let templates = SiteTemplates::load(Path::new(&options.templates_dir))?;
+
+        let links = vec![
+            link("https://example.org/a", &["rust"]),
+            link("https://example.org/b", &[]),
+        ];
+        assert_eq!(build_site(links, &cache, &templates, &options)?, 2);
+        assert!(out_dir.join(link_page("https://example.org/b")).exists());
+
+        let links = vec![
+            link("https://example.org/a", &["rust"]),
+            link("https://example.org/b", &["private"]),
+        ];
+        assert_eq!(build_site(links, &cache, &templates, &options)?, 1);
+        assert!(out_dir.join(link_page("https://example.org/a")).exists());
+        assert!(!out_dir.join(link_page("https://example.org/b")).exists());
+        assert!(!Path::new(&format!("{}.partial", options.out_dir)).exists());
+
+        std::fs::remove_dir_all(&out_dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,633 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::OpenOptions;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use regex::{Captures, Regex};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use url::Url;
+
+use crate::cache::{Cache, CacheType};
+use crate::cli::SnapshotFormat;
+use crate::extract::{content_kind, ContentKind};
+use crate::models::{CapturedResponse, Snapshot};
+use crate::output::{emit, Report};
+use crate::scheduler::{host_of, HostPacer};
+
+/// Stylesheets, images and fonts larger than this are left as links
+const MAX_RESOURCE_BYTES: u64 = 5 * 1024 * 1024;
+/// Resources inlined into one page at most; the rest are left as links
+const MAX_PAGE_RESOURCES: usize = 100;
+/// Total size of the resources inlined into one page
+const MAX_PAGE_RESOURCE_BYTES: usize = 25 * 1024 * 1024;
+/// Headers describing the encoding on the wire, which no longer match the stored body
+const TRANSPORT_HEADERS: &[&str] = &["content-encoding", "transfer-encoding", "content-length"];
+const OPEN_COMMAND: &str = if cfg!(target_os = "macos") {
+    "open"
+} else {
+    "xdg-open"
+};
+
+static SCRIPT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<script\b[^>]*>.*?</script>|<script\b[^>]*/>").unwrap());
+static LINK_TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)<link\b[^>]*>").unwrap());
+static STYLE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?is)(<style\b[^>]*>)(.*?)(</style>)").unwrap());
+static IMG_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<img\b[^>]*>").unwrap());
+static HEAD_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?is)<head\b[^>]*>").unwrap());
+static CSS_URL_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)url\(\s*['"]?([^'")]+?)['"]?\s*\)"#).unwrap());
+static HREF_REGEX: LazyLock<Regex> = LazyLock::new(|| attr_regex("href"));
+static REL_REGEX: LazyLock<Regex> = LazyLock::new(|| attr_regex("rel"));
+static SRC_REGEX: LazyLock<Regex> = LazyLock::new(|| attr_regex("src"));
+static SRCSET_REGEX: LazyLock<Regex> = LazyLock::new(|| attr_regex("srcset"));
+
+/// Matches the `name` attribute of a tag, with its value quoted or not.
+fn attr_regex(name: &str) -> Regex {
+    Regex::new(&format!(
+        r#"(?is)\s{name}\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#
+    ))
+    .unwrap()
+}
+
+pub struct SnapshotOptions {
+    pub format: SnapshotFormat,
+    pub dir: String,
+}
+
+/// Saves snapshots from the fetch workers. WARC appends are serialized so
+/// records from concurrent fetches don't interleave.
+pub struct Snapshotter {
+    format: SnapshotFormat,
+    dir: String,
+    warc_lock: Mutex<()>,
+    /// Hosts that page resources are never fetched from
+    banned: HashSet<String>,
+    /// Spaces resource requests like the page fetches themselves
+    pacer: HostPacer,
+}
+
+fn url_hash(url: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn header<'a>(captured: &'a CapturedResponse, name: &str) -> Option<&'a str> {
+    captured
+        .headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+fn mime_from_extension(url: &Url) -> &'static str {
+    let extension = url
+        .path()
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "css" => "text/css",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn fetch_resource(agent: &ureq::Agent, url: &Url) -> Option<(String, Vec<u8>)> {
+    let mut response = agent.get(url.as_str()).call().ok()?;
+    let mime = response
+        .headers()
+        .get("content-type")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty() && value != "application/octet-stream")
+        .unwrap_or_else(|| mime_from_extension(url).to_string());
+    let body = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_RESOURCE_BYTES)
+        .read_to_vec()
+        .ok()?;
+    Some((mime, body))
+}
+
+/// Resources referenced by a page, each fetched at most once, up to
+/// MAX_PAGE_RESOURCES of them and MAX_PAGE_RESOURCE_BYTES in total.
+struct Resources<F> {
+    fetch: F,
+    fetched: HashMap<String, Option<(String, Vec<u8>)>>,
+    bytes: usize,
+}
+
+impl<F: FnMut(&Url) -> Option<(String, Vec<u8>)>> Resources<F> {
+    fn get(&mut self, url: &Url) -> Option<(String, Vec<u8>)> {
+        if let Some(fetched) = self.fetched.get(url.as_str()) {
+            return fetched.clone();
+        }
+        if self.fetched.len() >= MAX_PAGE_RESOURCES || self.bytes >= MAX_PAGE_RESOURCE_BYTES {
+            return None;
+        }
+        let resource = (self.fetch)(url)
+            .filter(|(_, body)| self.bytes + body.len() <= MAX_PAGE_RESOURCE_BYTES);
+        if let Some((_, body)) = &resource {
+            self.bytes += body.len();
+        }
+        self.fetched.insert(url.to_string(), resource.clone());
+        resource
+    }
+
+    fn data_uri(&mut self, url: &Url) -> String {
+        self.get(url)
+            .map(|(mime, body)| format!("data:{mime};base64,{}", STANDARD.encode(body)))
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    /// Replaces `url(...)` references in a stylesheet, resolved against `base`.
+    fn inline_css(&mut self, css: &str, base: &Url) -> String {
+        CSS_URL_REGEX
+            .replace_all(css, |c: &Captures| {
+                let reference = &c[1];
+                match base.join(reference) {
+                    Ok(url) if !reference.starts_with("data:") => {
+                        format!("url(\"{}\")", self.data_uri(&url))
+                    }
+                    _ => c[0].to_string(),
+                }
+            })
+            .into_owned()
+    }
+}
+
+/// Turns a page into a single self-contained file: scripts are dropped,
+/// stylesheets are inlined and images, fonts and CSS backgrounds become data
+/// URIs. Anything `fetch` can't retrieve is left pointing at its absolute URL.
+fn inline_page(
+    html: &str,
+    base: &Url,
+    fetch: impl FnMut(&Url) -> Option<(String, Vec<u8>)>,
+) -> String {
+    let attr_value = |regex: &Regex, tag: &str| {
+        regex.captures(tag).and_then(|c| {
+            c.get(1)
+                .or_else(|| c.get(2))
+                .or_else(|| c.get(3))
+                .map(|m| m.as_str().to_string())
+        })
+    };
+
+    let mut resources = Resources {
+        fetch,
+        fetched: HashMap::new(),
+        bytes: 0,
+    };
+
+    let html = SCRIPT_REGEX.replace_all(html, "");
+
+    let html = STYLE_REGEX.replace_all(&html, |c: &Captures| {
+        format!("{}{}{}", &c[1], resources.inline_css(&c[2], base), &c[3])
+    });
+
+    let html = LINK_TAG_REGEX.replace_all(&html, |c: &Captures| {
+        let tag = &c[0];
+        let is_stylesheet = attr_value(&REL_REGEX, tag)
+            .is_some_and(|rel| rel.to_lowercase().contains("stylesheet"));
+        let href = attr_value(&HREF_REGEX, tag).and_then(|href| base.join(&href).ok());
+        match href {
+            Some(href) if is_stylesheet => match resources.get(&href) {
+                Some((_, css)) => format!(
+                    "<style>{}</style>",
+                    resources.inline_css(&String::from_utf8_lossy(&css), &href)
+                ),
+                None => tag.replace(
+                    &attr_value(&HREF_REGEX, tag).unwrap_or_default(),
+                    href.as_str(),
+                ),
+            },
+            _ => tag.to_string(),
+        }
+    });
+
+    let html = IMG_REGEX.replace_all(&html, |c: &Captures| {
+        let tag = SRCSET_REGEX.replace_all(&c[0], "");
+        let Some(src) = attr_value(&SRC_REGEX, &tag) else {
+            return tag.into_owned();
+        };
+        match base.join(&src) {
+            Ok(url) if !src.starts_with("data:") => SRC_REGEX
+                .replace(&tag, format!(" src=\"{}\"", resources.data_uri(&url)))
+                .into_owned(),
+            _ => tag.into_owned(),
+        }
+    });
+
+    // Keep the page's own links working when opened from disk
+    let base_tag = format!("<base href=\"{base}\">");
+    match HEAD_REGEX.find(&html) {
+        Some(head) => format!("{}{base_tag}{}", &html[..head.end()], &html[head.end()..]),
+        None => format!("{base_tag}{html}"),
+    }
+}
+
+fn http_block(captured: &CapturedResponse) -> Vec<u8> {
+    let reason = ureq::http::StatusCode::from_u16(captured.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("");
+    let mut block = format!("HTTP/1.1 {} {reason}\r\n", captured.status);
+    for (name, value) in &captured.headers {
+        if !TRANSPORT_HEADERS.contains(&name.to_lowercase().as_str()) {
+            block.push_str(&format!("{name}: {value}\r\n"));
+        }
+    }
+    block.push_str(&format!("Content-Length: {}\r\n\r\n", captured.body.len()));
+
+    let mut block = block.into_bytes();
+    block.extend_from_slice(&captured.body);
+    block
+}
+
+fn warc_record(
+    warc_type: &str,
+    target_uri: Option<&str>,
+    content_type: &str,
+    date: &str,
+    content: &[u8],
+) -> Vec<u8> {
+    let id = url_hash(&format!("{date}{}", target_uri.unwrap_or_default()));
+    let id2 = url_hash(&format!("{id}{warc_type}"));
+    let record_id = format!(
+        "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        id >> 32,
+        (id >> 16) & 0xffff,
+        id & 0xffff,
+        id2 >> 48,
+        id2 & 0xffff_ffff_ffff
+    );
+
+    let mut header = format!(
+        "WARC/1.1\r\nWARC-Type: {warc_type}\r\nWARC-Record-ID: <urn:uuid:{record_id}>\r\nWARC-Date: {date}\r\n"
+    );
+    if let Some(uri) = target_uri {
+        header.push_str(&format!("WARC-Target-URI: {uri}\r\n"));
+    }
+    header.push_str(&format!(
+        "Content-Type: {content_type}\r\nContent-Length: {}\r\n\r\n",
+        content.len()
+    ));
+
+    let mut record = header.into_bytes();
+    record.extend_from_slice(content);
+    record.extend_from_slice(b"\r\n\r\n");
+    record
+}
+
+/// Each record is its own gzip member so it can be read back from its offset alone.
+fn gzip(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    Ok(encoder.finish()?)
+}
+
+/// Reads the HTTP response body and its content type out of the WARC record at `offset`.
+fn read_warc_response(path: &Path, offset: u64) -> anyhow::Result<(Option<String>, Vec<u8>)> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut record = Vec::new();
+    GzDecoder::new(file)
+        .read_to_end(&mut record)
+        .context("Failed to decompress WARC record")?;
+
+    let split = |bytes: &[u8]| {
+        bytes
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|end| (String::from_utf8_lossy(&bytes[..end]).into_owned(), end + 4))
+    };
+    let (warc_headers, block_start) = split(&record).context("Malformed WARC record")?;
+    if !warc_headers.contains("WARC-Type: response") {
+        bail!("WARC record at offset {offset} is not a response");
+    }
+    let (http_headers, body_start) =
+        split(&record[block_start..]).context("Malformed HTTP response in WARC record")?;
+
+    let content_type = http_headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("content-type")
+            .then(|| value.trim().to_string())
+    });
+    let body_start = block_start + body_start;
+    let content_length: usize = warc_headers
+        .lines()
+        .find_map(|line| line.strip_prefix("Content-Length: "))
+        .and_then(|length| length.trim().parse().ok())
+        .context("WARC record has no Content-Length")?;
+    let body = record[body_start..(block_start + content_length).min(record.len())].to_vec();
+
+    Ok((content_type, body))
+}
+
+fn extension_for(captured: &CapturedResponse) -> &'static str {
+    let url = Url::parse(&captured.url).ok();
+    match url.map(|url| content_kind(header(captured, "content-type"), &url)) {
+        Some(ContentKind::Pdf) => "pdf",
+        Some(ContentKind::Markdown) => "md",
+        Some(ContentKind::PlainText) => "txt",
+        _ => "html",
+    }
+}
+
+impl Snapshotter {
+    pub fn new(
+        format: SnapshotFormat,
+        dir: &str,
+        banned: HashSet<String>,
+        host_delay: Duration,
+    ) -> Self {
+        Snapshotter {
+            format,
+            dir: dir.to_string(),
+            warc_lock: Mutex::new(()),
+            banned,
+            pacer: HostPacer::new(host_delay),
+        }
+    }
+
+    fn fetch_resource(&self, agent: &ureq::Agent, url: &Url) -> Option<(String, Vec<u8>)> {
+        let host = host_of(url.as_str());
+        if self.banned.contains(&host) {
+            return None;
+        }
+        self.pacer.wait(&host);
+        fetch_resource(agent, url)
+    }
+
+    pub fn save(
+        &self,
+        agent: &ureq::Agent,
+        url: &str,
+        captured: &CapturedResponse,
+    ) -> anyhow::Result<Snapshot> {
+        match self.format {
+            SnapshotFormat::Html => self.save_file(agent, url, captured),
+            SnapshotFormat::Warc => self.append_warc(url, captured),
+        }
+    }
+
+    fn save_file(
+        &self,
+        agent: &ureq::Agent,
+        url: &str,
+        captured: &CapturedResponse,
+    ) -> anyhow::Result<Snapshot> {
+        let now = OffsetDateTime::now_utc();
+        let extension = extension_for(captured);
+        let dir = Path::new(&self.dir).join(host_of(&captured.url));
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let path = dir.join(format!(
+            "{:016x}-{}.{extension}",
+            url_hash(url),
+            now.unix_timestamp()
+        ));
+
+        if extension == "html" {
+            let base = Url::parse(&captured.url)?;
+            let html = inline_page(
+                &String::from_utf8_lossy(&captured.body),
+                &base,
+                |resource| self.fetch_resource(agent, resource),
+            );
+            std::fs::write(&path, html)
+        } else {
+            std::fs::write(&path, &captured.body)
+        }
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(Snapshot {
+            url: url.to_string(),
+            format: "html".to_string(),
+            path: path.to_string_lossy().into_owned(),
+            warc_offset: None,
+            captured_at: None,
+        })
+    }
+
+    fn append_warc(&self, url: &str, captured: &CapturedResponse) -> anyhow::Result<Snapshot> {
+        let now = OffsetDateTime::now_utc();
+        let date = now.format(&Rfc3339)?;
+        let path = Path::new(&self.dir).join(format!(
+            "{}-{:02}.warc.gz",
+            now.year(),
+            u8::from(now.month())
+        ));
+
+        let response = gzip(&warc_record(
+            "response",
+            Some(&captured.url),
+            "application/http;msgtype=response",
+            &date,
+            &http_block(captured),
+        ))?;
+
+        let _guard = self.warc_lock.lock().unwrap();
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        let mut offset = file.metadata()?.len();
+        if offset == 0 {
+            let info = gzip(&warc_record(
+                "warcinfo",
+                None,
+                "application/warc-fields",
+                &date,
+                b"software: sync_bookmarks\r\nformat: WARC File Format 1.1\r\n",
+            ))?;
+            file.write_all(&info)?;
+            offset = info.len() as u64;
+        }
+        file.write_all(&response)
+            .with_context(|| format!("Failed to write to {}", path.display()))?;
+
+        Ok(Snapshot {
+            url: url.to_string(),
+            format: "warc".to_string(),
+            path: path.to_string_lossy().into_owned(),
+            warc_offset: Some(offset),
+            captured_at: None,
+        })
+    }
+}
+
+impl Report for Snapshot {
+    const KIND: &'static str = "snapshot";
+
+    fn text(&self) -> String {
+        let captured_at = self.captured_at.as_deref().unwrap_or_default();
+        match self.warc_offset {
+            None => format!("{} (captured {captured_at})", self.path),
+            Some(offset) => format!("{} at offset {offset} (captured {captured_at})", self.path),
+        }
+    }
+}
+
+/// Finds the latest snapshot of `url` and opens it, unpacking WARC records to a temporary file first.
+pub fn open_snapshot(url: &str, print_only: bool) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let snapshot = cache
+        .query_latest_snapshot(url)?
+        .with_context(|| format!("No snapshot of {url}; fetch it with `import --snapshot`"))?;
+
+    emit(&snapshot)?;
+    let path = match snapshot.warc_offset {
+        None => PathBuf::from(&snapshot.path),
+        Some(offset) => {
+            if print_only {
+                return Ok(());
+            }
+            let (content_type, body) = read_warc_response(Path::new(&snapshot.path), offset)?;
+            let extension = extension_for(&CapturedResponse {
+                url: url.to_string(),
+                status: 200,
+                headers: content_type
+                    .map(|ct| vec![("Content-Type".to_string(), ct)])
+                    .unwrap_or_default(),
+                body: Vec::new(),
+            });
+            let path =
+                std::env::temp_dir().join(format!("snapshot-{:016x}.{extension}", url_hash(url)));
+            std::fs::write(&path, body)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            path
+        }
+    };
+
+    if !print_only {
+        open_in_browser(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Opens a file or URL with the desktop's default application.
+pub fn open_in_browser(target: impl AsRef<OsStr>) -> anyhow::Result<()> {
+    Command::new(OPEN_COMMAND)
+        .arg(target)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to run {OPEN_COMMAND}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline_page() {
+        let html = r#"<html><head><link rel="stylesheet" href="/style.css"><script src="app.js"></script></head>
+<body><img src="img/a.png" srcset="img/a@2x.png 2x"><img src="https://cdn.example.org/missing.png"></body></html>"#;
+        let base = Url::parse("https://example.org/posts/1").unwrap();
+
+        let inlined = inline_page(html, &base, |url| match url.as_str() {
+            "https://example.org/style.css" => Some((
+                "text/css".to_string(),
+                b"body { background: url('bg.gif') }".to_vec(),
+            )),
+            "https://example.org/bg.gif" => Some(("image/gif".to_string(), b"GIF".to_vec())),
+            "https://example.org/posts/img/a.png" => {
+                Some(("image/png".to_string(), b"PNG".to_vec()))
+            }
+            _ => None,
+        });
+
+        assert!(!inlined.contains("<script"));
+        assert!(!inlined.contains("srcset"));
+        assert!(inlined.contains("<base href=\"https://example.org/posts/1\">"));
+        assert!(inlined
+            .contains(r#"<style>body { background: url("data:image/gif;base64,R0lG") }</style>"#));
+        assert!(inlined.contains(r#"src="data:image/png;base64,UE5H""#));
+        assert!(inlined.contains(r#"src="https://cdn.example.org/missing.png""#));
+    }
+
+    #[test]
+    fn test_inline_page_caps_resources() {
+        let html: String = (0..MAX_PAGE_RESOURCES + 5)
+            .map(|i| format!("<img src=\"/{i}.png\">"))
+            .collect();
+        let base = Url::parse("https://example.org/").unwrap();
+
+        let mut fetches = 0;
+        let inlined = inline_page(&html, &base, |_| {
+            fetches += 1;
+            Some(("image/png".to_string(), b"PNG".to_vec()))
+        });
+
+        assert_eq!(fetches, MAX_PAGE_RESOURCES);
+        assert_eq!(
+            inlined.matches("data:image/png").count(),
+            MAX_PAGE_RESOURCES
+        );
+    }
+
+    #[test]
+    fn test_warc_round_trip() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("snapshot-test-{}", std::process::id()));
+        let snapshotter = Snapshotter::new(
+            SnapshotFormat::Warc,
+            &dir.to_string_lossy(),
+            HashSet::new(),
+            Duration::ZERO,
+        );
+        let captured = |body: &str| CapturedResponse {
+            url: "https://example.org/post".to_string(),
+            status: 200,
+            headers: vec![
+                ("content-type".to_string(), "text/html".to_string()),
+                ("content-encoding".to_string(), "gzip".to_string()),
+            ],
+            body: body.as_bytes().to_vec(),
+        };
+
+        let first = snapshotter.append_warc("https://example.org/post", &captured("<p>one</p>"))?;
+        let second =
+            snapshotter.append_warc("https://example.org/post", &captured("<p>two</p>"))?;
+        assert_eq!(first.path, second.path);
+        assert!(second.warc_offset > first.warc_offset);
+
+        let (content_type, body) =
+            read_warc_response(Path::new(&second.path), second.warc_offset.unwrap())?;
+        assert_eq!(content_type.as_deref(), Some("text/html"));
+        assert_eq!(body, b"<p>two</p>");
+
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::cache::{Cache, CacheType, MAX_FETCH_ATTEMPTS};
+use crate::feed::parse_date;
+use crate::fetch::banned_hosts;
+use crate::links::read_links;
+use crate::models::SerializedLink;
+use crate::output::{emit, Report};
+use crate::scheduler::host_of;
+
+pub struct StatsOptions {
+    /// Number of tags and hosts to list
+    pub top: usize,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Count {
+    name: String,
+    links: usize,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct WordStats {
+    /// Cached articles the totals are over
+    articles: usize,
+    total: usize,
+    average: usize,
+}
+
+/// Where each link in links.json stands with fetching.
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct FetchCoverage {
+    cached: usize,
+    /// Not cached because their host is banned
+    banned: usize,
+    /// Given up on after MAX_FETCH_ATTEMPTS failures
+    failed: usize,
+    /// Not fetched yet, or still being retried
+    uncached: usize,
+}
+
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct ArchiveCoverage {
+    /// Links with a local snapshot
+    snapshot: usize,
+    /// Links a check found in the Wayback Machine
+    wayback: usize,
+    /// Links with neither
+    none: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct Stats {
+    /// How many tags and hosts were kept
+    #[serde(skip)]
+    top: usize,
+    links: usize,
+    by_source: Vec<Count>,
+    by_tag: Vec<Count>,
+    by_host: Vec<Count>,
+    /// Links by the month they were read, or else first cached
+    by_month: Vec<Count>,
+    words: WordStats,
+    fetch: FetchCoverage,
+    archive: ArchiveCoverage,
+}
+
+/// Counts sorted by descending count then name, keeping the first `top` if given.
+fn ranked(counts: HashMap<String, usize>, top: Option<usize>) -> Vec<Count> {
+    let mut counts: Vec<Count> = counts
+        .into_iter()
+        .map(|(name, links)| Count { name, links })
+        .collect();
+    counts.sort_by(|a, b| b.links.cmp(&a.links).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(top.unwrap_or(counts.len()));
+    counts
+}
+
+fn compute_stats(links: &[SerializedLink], cache: &Cache, top: usize) -> anyhow::Result<Stats> {
+    let word_counts: HashMap<String, usize> = cache
+        .query_all()?
+        .into_iter()
+        .map(|link| (link.url, link.text_content.split_whitespace().count()))
+        .collect();
+    let added_dates = cache.query_added_dates()?;
+    let banned = banned_hosts(cache)?;
+    let failed: HashSet<String> = cache
+        .query_fetch_failures(MAX_FETCH_ATTEMPTS)?
+        .into_iter()
+        .map(|failure| failure.url)
+        .collect();
+    let snapshotted = cache.query_snapshotted_urls()?;
+    let wayback = cache.query_wayback_urls()?;
+
+    let mut by_source = HashMap::new();
+    let mut by_tag = HashMap::new();
+    let mut by_host = HashMap::new();
+    let mut by_month = HashMap::new();
+    let mut words = WordStats::default();
+    let mut fetch = FetchCoverage::default();
+    let mut archive = ArchiveCoverage::default();
+    for link in links {
+        *by_source
+            .entry(link.source.as_str().to_string())
+            .or_default() += 1;
+        for tag in &link.tags {
+            *by_tag.entry(tag.clone()).or_default() += 1;
+        }
+        let host = host_of(&link.url);
+        let domain = host.trim_start_matches("www.");
+        if !domain.is_empty() {
+            *by_host.entry(domain.to_string()).or_default() += 1;
+        }
+        let date = link
+            .read_at
+            .as_deref()
+            .and_then(parse_date)
+            .or_else(|| added_dates.get(&link.url).and_then(|date| parse_date(date)));
+        if let Some(date) = date {
+            let month = format!("{}-{:02}", date.year(), u8::from(date.month()));
+            *by_month.entry(month).or_default() += 1;
+        }
+
+        if let Some(count) = word_counts.get(&link.url) {
+            words.articles += 1;
+            words.total += count;
+            fetch.cached += 1;
+        } else if banned.contains(&host) {
+            fetch.banned += 1;
+        } else if failed.contains(&link.url) {
+            fetch.failed += 1;
+        } else {
+            fetch.uncached += 1;
+        }
+
+        let in_snapshot = snapshotted.contains(&link.url);
+        let in_wayback = wayback.contains(&link.url);
+        archive.snapshot += usize::from(in_snapshot);
+        archive.wayback += usize::from(in_wayback);
+        archive.none += usize::from(!in_snapshot && !in_wayback);
+    }
+    words.average = words.total.checked_div(words.articles).unwrap_or(0);
+
+    let mut by_month = ranked(by_month, None);
+    by_month.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Stats {
+        top,
+        links: links.len(),
+        by_source: ranked(by_source, None),
+        by_tag: ranked(by_tag, Some(top)),
+        by_host: ranked(by_host, Some(top)),
+        by_month,
+        words,
+        fetch,
+        archive,
+    })
+}
+
+fn write_counts(out: &mut String, heading: &str, counts: &[Count]) {
+    out.push_str(&format!("\n\n{heading}"));
+    let width = counts
+        .iter()
+        .map(|count| count.name.len())
+        .max()
+        .unwrap_or(0);
+    for count in counts {
+        out.push_str(&format!("\n  {:<width$}  {:>6}", count.name, count.links));
+    }
+}
+
+impl Report for Stats {
+    const KIND: &'static str = "stats";
+
+    fn text(&self) -> String {
+        let mut out = format!("{} links", self.links);
+        write_counts(&mut out, "By source", &self.by_source);
+        write_counts(&mut out, &format!("Top {} tags", self.top), &self.by_tag);
+        write_counts(
+            &mut out,
+            &format!("Top {} domains", self.top),
+            &self.by_host,
+        );
+        write_counts(&mut out, "By month", &self.by_month);
+
+        let words = &self.words;
+        out.push_str(&format!(
+            "\n\nWords\n  {} total over {} cached articles, {} on average",
+            words.total, words.articles, words.average
+        ));
+        let fetch = &self.fetch;
+        out.push_str(&format!(
+            "\n\nFetch coverage\n  {} cached, {} uncached, {} failed, {} banned",
+            fetch.cached, fetch.uncached, fetch.failed, fetch.banned
+        ));
+        let archive = &self.archive;
+        out.push_str(&format!(
+            "\n\nArchive coverage\n  {} with a local snapshot, {} in the Wayback Machine, {} with neither",
+            archive.snapshot, archive.wayback, archive.none
+        ));
+        out
+    }
+}
+
+pub fn report_stats(options: &StatsOptions) -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    emit(&compute_stats(&read_links()?, &cache, options.top)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CachedLink, ErrorClass, FetchError, LinkSource};
+
+    #[test]
+    fn test_compute_stats() -> anyhow::Result<()> {
+        let cache = Cache::new(CacheType::Memory)?;
+        cache.insert(&CachedLink::new(
+            "https://www.example.org/a".to_string(),
+            "A".to_string(),
+            LinkSource::Manual,
+            Vec::new(),
+            "four words of text".to_string(),
+        ))?;
+        cache.ban_host("banned.example")?;
+        let error = FetchError {
+            status_code: Some(404),
+            class: ErrorClass::Http,
+            message: "http status: 404".to_string(),
+        };
+        for _ in 0..MAX_FETCH_ATTEMPTS {
+            cache.record_fetch_failure("https://example.org/gone", &error)?;
+        }
+
+        let links = vec![
+            SerializedLink {
+                read_at: Some("2024-03-05T10:00:00Z".to_string()),
+                ..SerializedLink::new(
+                    "https://www.example.org/a".to_string(),
+                    "A".to_string(),
+                    vec!["rust".to_string()],
+                    LinkSource::GoodLinks,
+                )
+            },
+            SerializedLink::new(
+                "https://example.org/gone".to_string(),
+                "Gone".to_string(),
+                vec!["rust".to_string(), "web".to_string()],
+                LinkSource::Manual,
+            ),
+            SerializedLink::new(
+                "https://banned.example/b".to_string(),
+                "B".to_string(),
+                Vec::new(),
+                LinkSource::Manual,
+            ),
+            SerializedLink::new(
+                "https://new.example/c".to_string(),
+                "C".to_string(),
+                Vec::new(),
+                LinkSource::Manual,
+            ),
+        ];
+
+        let stats = compute_stats(&links, &cache, 1)?;
+        assert_eq!(stats.links, 4);
+        assert_eq!(
+            stats.by_source,
+            [
+                Count {
+                    name: "Manual".to_string(),
+                    links: 3
+                },
+                Count {
+                    name: "GoodLinks".to_string(),
+                    links: 1
+                },
+            ]
+        );
+        assert_eq!(
+            stats.by_tag,
+            [Count {
+                name: "rust".to_string(),
+                links: 2
+            }]
+        );
+        assert_eq!(
+            stats.by_host,
+            [Count {
+                name: "example.org".to_string(),
+                links: 2
+            }]
+        );
+        assert_eq!(
+            stats.by_month,
+            [Count {
+                name: "2024-03".to_string(),
+                links: 1
+            }]
+        );
+        assert_eq!(
+            stats.words,
+            WordStats {
+                articles: 1,
+                total: 4,
+                average: 4
+            }
+        );
+        assert_eq!(
+            stats.fetch,
+            FetchCoverage {
+                cached: 1,
+                banned: 1,
+                failed: 1,
+                uncached: 1
+            }
+        );
+        assert_eq!(stats.archive.none, 4);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use crate::autotag::terms;
+use crate::cache::Cache;
+
+/// Sentences picked for a summary
+const SUMMARY_SENTENCES: usize = 2;
+/// Sentences outside this many words are usually captions, headings or run-ons
+const MIN_SENTENCE_WORDS: usize = 6;
+const MAX_SENTENCE_WORDS: usize = 50;
+
+/// Splits text into sentences at `.`, `!` or `?` followed by whitespace.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_break = matches!(c, '.' | '!' | '?')
+            && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_break {
+            let end = i + c.len_utf8();
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|sentence| !sentence.is_empty());
+    sentences
+}
+
+/// Content words of a sentence, without the word pairs `terms` also returns.
+fn words(sentence: &str) -> Vec<String> {
+    terms(sentence)
+        .into_iter()
+        .filter(|term| !term.contains(' '))
+        .collect()
+}
+
+/// An extractive summary of `text`: the sentences whose words are most
+/// frequent across the article, kept in their original order.
+pub fn summarize(text: &str) -> Option<String> {
+    let candidates: Vec<(usize, &str)> = sentences(text)
+        .into_iter()
+        .enumerate()
+        .filter(|(_, sentence)| {
+            let count = sentence.split_whitespace().count();
+            (MIN_SENTENCE_WORDS..=MAX_SENTENCE_WORDS).contains(&count)
+        })
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let mut frequencies: HashMap<String, usize> = HashMap::new();
+    for word in words(text) {
+        *frequencies.entry(word).or_default() += 1;
+    }
+
+    let mut scored: Vec<(usize, &str, f64)> = candidates
+        .into_iter()
+        .map(|(position, sentence)| {
+            let words = words(sentence);
+            let total: usize = words.iter().map(|word| frequencies[word]).sum();
+            let score = total as f64 / words.len().max(1) as f64;
+            (position, sentence, score)
+        })
+        .collect();
+    scored.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+    scored.truncate(SUMMARY_SENTENCES);
+    scored.sort_by_key(|(position, _, _)| *position);
+
+    Some(
+        scored
+            .into_iter()
+            .map(|(_, sentence, _)| sentence)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// The stored summary of a cached link, summarizing and storing it first if
+/// it was cached before summaries were kept or its text has since changed.
+pub fn summary_for(cache: &Cache, url: &str) -> anyhow::Result<Option<String>> {
+    if let Some(summary) = cache.query_summary(url)? {
+        return Ok((!summary.is_empty()).then_some(summary));
+    }
+    let Some(cached) = cache.query(url)? else {
+        return Ok(None);
+    };
+    let summary = summarize(&cached.text_content);
+    cache.record_summary(url, summary.as_deref())?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_picks_central_sentences_in_order() {
+        let text = "Photo: the harbour at dawn. \
+            Tide pools along the harbour shelter crabs, anemones and small fish. \
+            Our ferry was late again, which nobody on board seemed to mind. \
+            The harbour tide pools empty twice a day, stranding the small fish until the tide returns. \
+            Thanks for reading!";
+
+        assert_eq!(
+            summarize(text).as_deref(),
+            Some(
+                "Tide pools along the harbour shelter crabs, anemones and small fish. \
+                 The harbour tide pools empty twice a day, stranding the small fish until the tide returns."
+            )
+        );
+        assert_eq!(summarize("Too short. Also short."), None);
+    }
+}
@@ -4,11 +4,17 @@ use std::process::Command;
 use std::time::Duration;
 
 use anyhow::{bail, Context};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use ureq::http;
 
-use crate::fetch::BANNED_HOSTS;
+use crate::cache::{Cache, CacheType};
+use crate::fetch::banned_hosts;
+use crate::links::normalize_url;
+use crate::metadata::byline;
 use crate::models::{LinkSource, SerializedLink};
+use crate::output::{emit, progress, Report};
+use crate::scheduler::host_of;
+use crate::summary::summary_for;
 
 const RAINDROP_API_BASE: &str = "https://api.raindrop.io/rest/v1";
 const BATCH_SIZE: usize = 100;
@@ -32,6 +38,7 @@ struct RaindropItem {
     link: String,
     title: String,
     folder: String,
+    tags: Vec<String>,
 }
 
 fn get_token() -> anyhow::Result<String> {
@@ -57,9 +64,21 @@ fn source_to_collection_name(source: &LinkSource) -> &'static str {
     match source {
         LinkSource::GoodLinks => "GoodLinks",
         LinkSource::Obsidian => "Obsidian",
+        LinkSource::Zotero => "Zotero",
+        LinkSource::Mastodon => "Mastodon",
+        LinkSource::Bluesky => "Bluesky",
+        LinkSource::GitHub => "GitHub",
+        LinkSource::Manual => "Manual",
     }
 }
 
+/// The collection a link is filed in: the one a rule routed it to, else its source's.
+fn collection_name(link: &SerializedLink) -> &str {
+    link.collection
+        .as_deref()
+        .unwrap_or_else(|| source_to_collection_name(&link.source))
+}
+
 fn fetch_or_create_collection(
     agent: &ureq::Agent,
     token: &str,
@@ -82,7 +101,7 @@ fn fetch_or_create_collection(
         .as_i64()
         .with_context(|| format!("Missing _id in create-collection response for '{name}'"))?;
 
-    println!("Created Raindrop collection '{}' (id {})", name, id);
+    progress!("Created Raindrop collection '{}' (id {})", name, id);
     collections.insert(name.to_string(), id);
     Ok(id)
 }
@@ -101,6 +120,7 @@ fn parse_export_csv(csv_text: &str) -> anyhow::Result<Vec<RaindropItem>> {
         .context("CSV export is missing a 'url' column")?;
     let title_col = headers.iter().position(|h| h == "title");
     let folder_col = headers.iter().position(|h| h == "folder");
+    let tags_col = headers.iter().position(|h| h == "tags");
 
     let mut items = Vec::new();
     for result in rdr.records() {
@@ -127,7 +147,16 @@ fn parse_export_csv(csv_text: &str) -> anyhow::Result<Vec<RaindropItem>> {
             .unwrap_or("")
             .to_string();
 
-        items.push(RaindropItem { id, link, title, folder });
+        let tags = tags_col
+            .and_then(|col| record.get(col))
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        items.push(RaindropItem { id, link, title, folder, tags });
     }
 
     Ok(items)
@@ -150,7 +179,7 @@ fn fetch_all_raindrops(
         let csv_text = resp.body_mut().read_to_string()?;
         let items = parse_export_csv(&csv_text)
             .with_context(|| format!("Failed to parse CSV export for collection {id}"))?;
-        println!("  Collection {id}: {} raindrops", items.len());
+        progress!("  Collection {id}: {} raindrops", items.len());
         all.extend(items);
     }
 
@@ -160,26 +189,6 @@ fn fetch_all_raindrops(
     Ok(all)
 }
 
-fn is_banned(url: &str) -> bool {
-    url::Url::parse(url)
-        .ok()
-        .and_then(|u| u.host_str().map(|h| h.to_string()))
-        .is_some_and(|host| BANNED_HOSTS.contains(&host.as_str()))
-}
-
-fn normalize_url(url: &str) -> String {
-    match url::Url::parse(url) {
-        Ok(mut parsed) => {
-            parsed.set_query(None);
-            parsed.set_fragment(None);
-            let path = parsed.path().trim_end_matches('/').to_string();
-            parsed.set_path(if path.is_empty() { "/" } else { &path });
-            parsed.to_string()
-        }
-        Err(_) => url.to_string(),
-    }
-}
-
 fn send_with_retry<F>(label: &str, mut send: F) -> anyhow::Result<()>
 where
     F: FnMut() -> Result<ureq::http::Response<ureq::Body>, ureq::Error>,
@@ -200,13 +209,61 @@ where
 
 const DRY_RUN_PREVIEW_LIMIT: usize = 20;
 
+#[derive(Serialize)]
+struct PlannedLink {
+    url: String,
+    title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    collection: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+/// The changes a sync would make to Raindrop.
+#[derive(Serialize)]
+struct SyncPlan {
+    dry_run: bool,
+    to_add: Vec<PlannedLink>,
+    to_delete: Vec<PlannedLink>,
+    /// Raindrops with the tags they are missing
+    to_tag: Vec<PlannedLink>,
+}
+
+impl Report for SyncPlan {
+    const KIND: &'static str = "sync_plan";
+
+    fn text(&self) -> String {
+        format!(
+            "\nTo add:    {}\nTo delete: {}\nTo tag:    {}",
+            self.to_add.len(),
+            self.to_delete.len(),
+            self.to_tag.len()
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct SyncResult {
+    added: usize,
+    deleted: usize,
+    tagged: usize,
+}
+
+impl Report for SyncResult {
+    const KIND: &'static str = "sync";
+
+    fn text(&self) -> String {
+        "\nSync complete!".to_string()
+    }
+}
+
 fn print_preview<T>(items: &[T], label: &str, fmt: impl Fn(&T) -> String) {
     let shown = items.len().min(DRY_RUN_PREVIEW_LIMIT);
     for item in &items[..shown] {
-        println!("  {label} {}", fmt(item));
+        progress!("  {label} {}", fmt(item));
     }
     if items.len() > DRY_RUN_PREVIEW_LIMIT {
-        println!("  ... and {} more", items.len() - DRY_RUN_PREVIEW_LIMIT);
+        progress!("  ... and {} more", items.len() - DRY_RUN_PREVIEW_LIMIT);
     }
 }
 
@@ -215,7 +272,7 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
     let links: Vec<SerializedLink> =
         serde_json::from_str(&links_json).context("Failed to parse links.json")?;
 
-    println!("Loaded {} links from links.json", links.len());
+    progress!("Loaded {} links from links.json", links.len());
 
     let token = get_token().context("Failed to get Raindrop API token from 1Password")?;
     let agent = ureq::Agent::new_with_defaults();
@@ -253,11 +310,11 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
         .map(|c| (c.title, c.id))
         .collect();
 
-    println!("Found {} existing Raindrop collections", collections.len());
+    progress!("Found {} existing Raindrop collections", collections.len());
 
-    println!("Fetching all raindrops via export API...");
+    progress!("Fetching all raindrops via export API...");
     let existing = fetch_all_raindrops(&agent, &token, &collection_ids)?;
-    println!("Found {} existing raindrops", existing.len());
+    progress!("Found {} existing raindrops", existing.len());
 
     // Build lookup maps keyed by normalized URL
     let links_by_url: HashMap<String, &SerializedLink> =
@@ -266,6 +323,10 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
     let existing_by_url: HashMap<String, &RaindropItem> =
         existing.iter().map(|r| (normalize_url(&r.link), r)).collect();
 
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let banned = banned_hosts(&cache)?;
+    let is_banned = |url: &str| banned.contains(&host_of(url));
+
     // Compute diff
     let to_add: Vec<&SerializedLink> = links
         .iter()
@@ -277,38 +338,79 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
         .filter(|r| !is_banned(&r.link) && !links_by_url.contains_key(&normalize_url(&r.link)))
         .collect();
 
-    println!("\nTo add:    {}", to_add.len());
-    println!("To delete: {}", to_delete.len());
+    // Tags added to links.json since a link was synced, e.g. by autotag
+    let to_tag: Vec<(&RaindropItem, Vec<String>)> = existing
+        .iter()
+        .filter_map(|r| {
+            let link = links_by_url.get(&normalize_url(&r.link))?;
+            let missing: Vec<String> = link
+                .tags
+                .iter()
+                .filter(|tag| !r.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+                .cloned()
+                .collect();
+            (!missing.is_empty()).then_some((r, missing))
+        })
+        .collect();
 
-    if to_add.is_empty() && to_delete.is_empty() {
-        println!("\nRaindrop is already in sync.");
+    emit(&SyncPlan {
+        dry_run,
+        to_add: to_add
+            .iter()
+            .map(|l| PlannedLink {
+                url: l.url.clone(),
+                title: l.title.clone(),
+                collection: Some(collection_name(l).to_string()),
+                tags: l.tags.clone(),
+            })
+            .collect(),
+        to_delete: to_delete
+            .iter()
+            .map(|r| PlannedLink {
+                url: r.link.clone(),
+                title: r.title.clone(),
+                collection: Some(r.folder.clone()),
+                tags: Vec::new(),
+            })
+            .collect(),
+        to_tag: to_tag
+            .iter()
+            .map(|(r, missing)| PlannedLink {
+                url: r.link.clone(),
+                title: r.title.clone(),
+                collection: None,
+                tags: missing.clone(),
+            })
+            .collect(),
+    })?;
+
+    if to_add.is_empty() && to_delete.is_empty() && to_tag.is_empty() {
+        progress!("\nRaindrop is already in sync.");
         return Ok(());
     }
 
     if dry_run {
-        println!("\n--- DRY RUN: no changes will be made ---");
+        progress!("\n--- DRY RUN: no changes will be made ---");
 
         if !to_add.is_empty() {
             let mut by_collection: HashMap<&str, usize> = HashMap::new();
             for l in &to_add {
-                *by_collection.entry(source_to_collection_name(&l.source)).or_default() += 1;
+                *by_collection.entry(collection_name(l)).or_default() += 1;
             }
             let mut counts: Vec<_> = by_collection.iter().collect();
             counts.sort_by_key(|(name, _)| *name);
 
-            println!("\nTo add by collection:");
+            progress!("\nTo add by collection:");
             for (name, count) in &counts {
-                println!("  {name}: {count}");
+                progress!("  {name}: {count}");
             }
 
-            println!("\nFirst {} links to add:", DRY_RUN_PREVIEW_LIMIT.min(to_add.len()));
+            progress!(
+                "\nFirst {} links to add:",
+                DRY_RUN_PREVIEW_LIMIT.min(to_add.len())
+            );
             print_preview(&to_add, "ADD", |l| {
-                format!(
-                    "[{}] {} ({})",
-                    source_to_collection_name(&l.source),
-                    l.title,
-                    l.url
-                )
+                format!("[{}] {} ({})", collection_name(l), l.title, l.url)
             });
         }
 
@@ -320,15 +422,28 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
             let mut counts: Vec<_> = by_collection.iter().collect();
             counts.sort_by_key(|(name, _)| *name);
 
-            println!("\nTo delete by collection:");
+            progress!("\nTo delete by collection:");
             for (name, count) in &counts {
-                println!("  {name}: {count}");
+                progress!("  {name}: {count}");
             }
 
-            println!("\nFirst {} raindrops to delete:", DRY_RUN_PREVIEW_LIMIT.min(to_delete.len()));
+            progress!(
+                "\nFirst {} raindrops to delete:",
+                DRY_RUN_PREVIEW_LIMIT.min(to_delete.len())
+            );
             print_preview(&to_delete, "DEL", |r| format!("{} ({})", r.title, r.link));
         }
 
+        if !to_tag.is_empty() {
+            progress!(
+                "\nFirst {} raindrops to tag:",
+                DRY_RUN_PREVIEW_LIMIT.min(to_tag.len())
+            );
+            print_preview(&to_tag, "TAG", |(r, missing)| {
+                format!("{} ({}) +{}", r.title, r.link, missing.join(" +"))
+            });
+        }
+
         return Ok(());
     }
 
@@ -336,7 +451,7 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
     let mut by_collection: HashMap<&str, Vec<&SerializedLink>> = HashMap::new();
     for link in &to_add {
         by_collection
-            .entry(source_to_collection_name(&link.source))
+            .entry(collection_name(link))
             .or_default()
             .push(link);
     }
@@ -347,17 +462,34 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
             fetch_or_create_collection(&agent, &token, collection_name, &mut collections)?;
 
         for chunk in collection_links.chunks(BATCH_SIZE) {
-            let items: Vec<serde_json::Value> = chunk
-                .iter()
-                .map(|link| {
-                    serde_json::json!({
-                        "link": link.url,
-                        "title": link.title,
-                        "tags": link.tags,
-                        "collection": { "$id": collection_id }
-                    })
-                })
-                .collect();
+            let mut items: Vec<serde_json::Value> = Vec::with_capacity(chunk.len());
+            for link in chunk {
+                let mut item = serde_json::json!({
+                    "link": link.url,
+                    "title": link.title,
+                    "tags": link.tags,
+                    "collection": { "$id": collection_id }
+                });
+                // Fill in what was learned when the page was fetched
+                if let Some(metadata) = cache.query_page_metadata(&link.url)? {
+                    if let Some(note) = byline(&metadata) {
+                        item["note"] = note.into();
+                    }
+                    if let Some(description) = metadata.description {
+                        item["excerpt"] = description.into();
+                    }
+                    if let Some(cover) = metadata.lead_image {
+                        item["cover"] = cover.into();
+                    }
+                }
+                // Pages without a description get a summary of their text instead
+                if item.get("excerpt").is_none() {
+                    if let Some(summary) = summary_for(&cache, &link.url)? {
+                        item["excerpt"] = summary.into();
+                    }
+                }
+                items.push(item);
+            }
 
             send_with_retry(
                 &format!("create {} links in '{collection_name}'", chunk.len()),
@@ -369,7 +501,7 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
                 },
             )?;
 
-            println!("Added {} links to '{}'", chunk.len(), collection_name);
+            progress!("Added {} links to '{}'", chunk.len(), collection_name);
         }
     }
 
@@ -386,9 +518,24 @@ pub fn sync_raindrop(dry_run: bool) -> anyhow::Result<()> {
                         .expect("failed to build DELETE request"),
                 )
         })?;
-        println!("Deleted ({}/{}) {}", i + 1, to_delete.len(), raindrop.link);
+        progress!("Deleted ({}/{}) {}", i + 1, to_delete.len(), raindrop.link);
+    }
+
+    // Update tags one at a time, keeping any tags added in Raindrop
+    for (i, (raindrop, missing)) in to_tag.iter().enumerate() {
+        let tags: Vec<&String> = raindrop.tags.iter().chain(missing).collect();
+        send_with_retry(&format!("tag raindrop {}", raindrop.id), || {
+            agent
+                .put(&format!("{RAINDROP_API_BASE}/raindrop/{}", raindrop.id))
+                .header("Authorization", &format!("Bearer {token}"))
+                .send_json(serde_json::json!({ "tags": tags }))
+        })?;
+        progress!("Tagged ({}/{}) {}", i + 1, to_tag.len(), raindrop.link);
     }
 
-    println!("\nSync complete!");
-    Ok(())
+    emit(&SyncResult {
+        added: to_add.len(),
+        deleted: to_delete.len(),
+        tagged: to_tag.len(),
+    })
 }
@@ -0,0 +1,318 @@
+//! A small Mustache-style renderer for the highlight export format and the
+//! pages of `serve`.
+//!
+//! Supports `{{name}}` variables with `| filter` pipes, `{{#section}}` and
+//! `{{^inverted}}` sections, and `{{! comments}}`. Section tags on a line of
+//! their own are removed along with the line, as in Mustache.
+
+use anyhow::{bail, Context};
+use serde_json::Value;
+
+enum Token<'a> {
+    Text(&'a str),
+    Tag(&'a str),
+}
+
+enum Node {
+    Text(String),
+    Variable {
+        name: String,
+        filters: Vec<Filter>,
+    },
+    Section {
+        name: String,
+        inverted: bool,
+        children: Vec<Node>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Filter {
+    Blockquote,
+    Html,
+    Url,
+}
+
+impl Filter {
+    fn parse(name: &str) -> anyhow::Result<Self> {
+        match name {
+            "blockquote" => Ok(Filter::Blockquote),
+            "html" => Ok(Filter::Html),
+            "url" => Ok(Filter::Url),
+            _ => bail!("Unknown template filter '{name}'"),
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Filter::Blockquote => text
+                .lines()
+                .map(|line| {
+                    if line.is_empty() {
+                        ">".to_string()
+                    } else {
+                        format!("> {line}")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Filter::Html => text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+                .replace('\'', "&#39;"),
+            Filter::Url => url::form_urlencoded::byte_serialize(text.as_bytes()).collect(),
+        }
+    }
+}
+
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+fn tokenize(src: &str) -> anyhow::Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut pos = 0;
+
+    while let Some(offset) = src[pos..].find("{{") {
+        let open = pos + offset;
+        let close = open
+            + src[open..]
+                .find("}}")
+                .with_context(|| format!("Unclosed template tag at byte {open}"))?;
+        let tag = src[open + 2..close].trim();
+
+        let mut text_end = open;
+        let mut next = close + 2;
+
+        if tag.starts_with(['#', '^', '/', '!']) {
+            let line_start = src[..open].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = src[next..].find('\n').map_or(src.len(), |i| next + i + 1);
+            let standalone = line_start >= text_start
+                && src[line_start..open].trim().is_empty()
+                && src[next..line_end].trim().is_empty();
+            if standalone {
+                text_end = line_start;
+                next = line_end;
+            }
+        }
+
+        if text_end > text_start {
+            tokens.push(Token::Text(&src[text_start..text_end]));
+        }
+        tokens.push(Token::Tag(tag));
+        text_start = next;
+        pos = next;
+    }
+
+    if text_start < src.len() {
+        tokens.push(Token::Text(&src[text_start..]));
+    }
+
+    Ok(tokens)
+}
+
+fn parse_nodes<'a>(
+    tokens: &mut impl Iterator<Item = Token<'a>>,
+    closing: Option<&str>,
+) -> anyhow::Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text.to_string())),
+            Token::Tag(tag) => {
+                if let Some(name) = tag.strip_prefix('/') {
+                    let name = name.trim();
+                    if closing != Some(name) {
+                        bail!("Unexpected closing tag '{name}'");
+                    }
+                    return Ok(nodes);
+                } else if tag.starts_with('!') {
+                    continue;
+                } else if let Some(name) = tag.strip_prefix('#') {
+                    let name = name.trim();
+                    nodes.push(Node::Section {
+                        name: name.to_string(),
+                        inverted: false,
+                        children: parse_nodes(tokens, Some(name))?,
+                    });
+                } else if let Some(name) = tag.strip_prefix('^') {
+                    let name = name.trim();
+                    nodes.push(Node::Section {
+                        name: name.to_string(),
+                        inverted: true,
+                        children: parse_nodes(tokens, Some(name))?,
+                    });
+                } else {
+                    let mut parts = tag.split('|').map(str::trim);
+                    let name = parts.next().unwrap_or_default().to_string();
+                    let filters = parts.map(Filter::parse).collect::<anyhow::Result<_>>()?;
+                    nodes.push(Node::Variable { name, filters });
+                }
+            }
+        }
+    }
+
+    if let Some(name) = closing {
+        bail!("Section '{name}' is never closed");
+    }
+    Ok(nodes)
+}
+
+fn lookup<'a>(stack: &[&'a Value], name: &str) -> Option<&'a Value> {
+    if name == "." {
+        return stack.last().copied();
+    }
+    stack.iter().rev().find_map(|value| {
+        name.split('.')
+            .try_fold(*value, |value, key| value.get(key))
+    })
+}
+
+fn is_truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::String(s)) => !s.is_empty(),
+        Some(_) => true,
+    }
+}
+
+fn render_nodes<'a>(nodes: &[Node], stack: &mut Vec<&'a Value>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Variable { name, filters } => {
+                let text = match lookup(stack, name) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => s.clone(),
+                    Some(value) => value.to_string(),
+                };
+                out.push_str(
+                    &filters
+                        .iter()
+                        .fold(text, |text, filter| filter.apply(&text)),
+                );
+            }
+            Node::Section {
+                name,
+                inverted: true,
+                children,
+            } => {
+                if !is_truthy(lookup(stack, name)) {
+                    render_nodes(children, stack, out);
+                }
+            }
+            Node::Section {
+                name,
+                inverted: false,
+                children,
+            } => {
+                let value = lookup(stack, name);
+                if !is_truthy(value) {
+                    continue;
+                }
+                let items: Vec<&'a Value> = match value {
+                    Some(Value::Array(items)) => items.iter().collect(),
+                    Some(value) => vec![value],
+                    None => Vec::new(),
+                };
+                for item in items {
+                    stack.push(item);
+                    render_nodes(children, stack, out);
+                    stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(src: &str) -> anyhow::Result<Self> {
+        let tokens = tokenize(src)?;
+        let nodes = parse_nodes(&mut tokens.into_iter(), None)?;
+        Ok(Template { nodes })
+    }
+
+    pub fn render(&self, context: &Value) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &mut vec![context], &mut out);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_render_highlight_export_format() -> anyhow::Result<()> {
+        let template = Template::parse(include_str!("../highlight-export-format.txt"))?;
+
+        let rendered = template.render(&json!({
+            "title": "An Article",
+            "url": "https://example.com/article",
+            "highlights": [
+                { "content_md": "First line\n\nSecond line", "note": "My note", "is_last": false },
+                { "content_md": "Another quote", "note": null, "is_last": true },
+            ],
+        }));
+
+        assert_eq!(
+            rendered,
+            "---\ntags: []\n---\n\
+             > First line\n>\n> Second line\n\nMy note\n\n---\n\n\
+             > Another quote\n\n\
+             ## References\n\n- [An Article](https://example.com/article)\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_default_highlights_template() -> anyhow::Result<()> {
+        let template = Template::parse(include_str!("../templates/highlights.md"))?;
+
+        let rendered = template.render(&json!({
+            "title": "An Article",
+            "url": "https://example.com/article",
+            "byline": "Ada",
+            "highlights": [],
+            "has_related": true,
+            "related": [{ "title": "Another", "url": "https://example.com/another" }],
+        }));
+        assert!(rendered.ends_with(
+            "- [An Article](https://example.com/article) — Ada\n\n\
+             ## Related\n\n- [Another](https://example.com/another)\n"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_filter_and_inline_sections() -> anyhow::Result<()> {
+        let template =
+            Template::parse("{{#tags}}<b>{{. | html}}</b>{{/tags}}{{^tags}}none{{/tags}}")?;
+
+        assert_eq!(
+            template.render(&json!({ "tags": ["a&b", "<c>"] })),
+            "<b>a&amp;b</b><b>&lt;c&gt;</b>"
+        );
+        assert_eq!(template.render(&json!({ "tags": [] })), "none");
+
+        let template = Template::parse("/?tag={{tag | url}}")?;
+        assert_eq!(template.render(&json!({ "tag": "a b&c" })), "/?tag=a+b%26c");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unclosed_section_is_an_error() {
+        assert!(Template::parse("{{#highlights}}oops").is_err());
+    }
+}
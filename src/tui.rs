@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use regex::{Regex, RegexBuilder};
+
+use crate::cache::{Cache, CacheType};
+use crate::fetch::fetch_link;
+use crate::links::{link_key, read_links};
+use crate::manual::{remove_links, retag_link};
+use crate::models::{CachedLink, SerializedLink};
+use crate::scheduler::host_of;
+use crate::snapshot::open_in_browser;
+
+const HELP: &str =
+    "q quit  / filter  t tags  b ban host  d remove  r re-fetch  o open  J/K scroll preview";
+
+/// A parsed filter line: `tag:` and `source:` terms must match exactly,
+/// other words anywhere in the title, URL or article text.
+#[derive(Default)]
+struct Filter {
+    tags: Vec<String>,
+    sources: Vec<String>,
+    words: Vec<Regex>,
+}
+
+impl Filter {
+    fn parse(input: &str) -> Self {
+        let mut filter = Filter::default();
+        for term in input.split_whitespace() {
+            if let Some(tag) = term.strip_prefix("tag:") {
+                filter.tags.push(tag.to_lowercase());
+            } else if let Some(source) = term.strip_prefix("source:") {
+                filter.sources.push(source.to_lowercase());
+            } else if let Ok(word) = RegexBuilder::new(&regex::escape(term))
+                .case_insensitive(true)
+                .build()
+            {
+                filter.words.push(word);
+            }
+        }
+        filter
+    }
+
+    fn matches(&self, link: &CachedLink) -> bool {
+        self.tags
+            .iter()
+            .all(|tag| link.tags.iter().any(|t| t.to_lowercase() == *tag))
+            && self
+                .sources
+                .iter()
+                .all(|source| link.source.as_str().to_lowercase() == *source)
+            && self.words.iter().all(|word| {
+                word.is_match(&link.title)
+                    || word.is_match(&link.url)
+                    || word.is_match(&link.text_content)
+            })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Confirm {
+    Ban,
+    Remove,
+}
+
+enum Mode {
+    Browse,
+    Filter,
+    EditTags(String),
+    Confirm(Confirm),
+}
+
+struct App {
+    cache: Cache,
+    links: Vec<CachedLink>,
+    /// How many links.json entries each link's `link_key` matches
+    entries: HashMap<String, usize>,
+    /// Indices into `links` that match the filter, in display order
+    visible: Vec<usize>,
+    filter: String,
+    list: ListState,
+    mode: Mode,
+    status: Option<String>,
+    preview_scroll: u16,
+}
+
+impl App {
+    /// Lists cached links that are still in links.json, newest first, with
+    /// the tags from links.json since that is where edits are written.
+    fn load(cache: Cache) -> anyhow::Result<Self> {
+        let mut tags_by_url: HashMap<String, Vec<String>> = HashMap::new();
+        let mut entries: HashMap<String, usize> = HashMap::new();
+        for link in read_links()? {
+            let key = link_key(&link.url);
+            *entries.entry(key.clone()).or_default() += 1;
+            tags_by_url.entry(key).or_insert(link.tags);
+        }
+
+        let mut links: Vec<CachedLink> = cache
+            .query_all()?
+            .into_iter()
+            .filter_map(|mut link| {
+                link.tags = tags_by_url.get(&link_key(&link.url))?.clone();
+                Some(link)
+            })
+            .collect();
+        links.reverse();
+
+        let mut app = App {
+            cache,
+            links,
+            entries,
+            visible: Vec::new(),
+            filter: String::new(),
+            list: ListState::default(),
+            mode: Mode::Browse,
+            status: None,
+            preview_scroll: 0,
+        };
+        app.apply_filter();
+        Ok(app)
+    }
+
+    fn apply_filter(&mut self) {
+        let filter = Filter::parse(&self.filter);
+        self.visible = (0..self.links.len())
+            .filter(|&i| filter.matches(&self.links[i]))
+            .collect();
+        self.list.select((!self.visible.is_empty()).then_some(0));
+        self.preview_scroll = 0;
+    }
+
+    fn selected(&self) -> Option<usize> {
+        self.list
+            .selected()
+            .and_then(|i| self.visible.get(i).copied())
+    }
+
+    fn select(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let current = self.list.selected().unwrap_or(0) as isize;
+        let last = self.visible.len() as isize - 1;
+        self.list
+            .select(Some((current + offset).clamp(0, last) as usize));
+        self.preview_scroll = 0;
+    }
+
+    fn report(&mut self, result: anyhow::Result<String>) {
+        self.status = Some(match result {
+            Ok(message) => message,
+            Err(e) => format!("Error: {e:#}"),
+        });
+    }
+
+    fn edit_tags(&mut self, input: &str) -> anyhow::Result<String> {
+        let Some(i) = self.selected() else {
+            return Ok(String::new());
+        };
+        let link = &mut self.links[i];
+        let new: Vec<String> = input
+            .split(',')
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        let add: Vec<String> = new
+            .iter()
+            .filter(|tag| !link.tags.contains(tag))
+            .cloned()
+            .collect();
+        let remove: Vec<String> = link
+            .tags
+            .iter()
+            .filter(|tag| !new.contains(tag))
+            .cloned()
+            .collect();
+        link.tags = retag_link(&link.url, &add, &remove)?;
+        Ok(format!("Tagged [{}]", link.tags.join(", ")))
+    }
+
+    fn ban_host(&mut self) -> anyhow::Result<String> {
+        let Some(i) = self.selected() else {
+            return Ok(String::new());
+        };
+        let host = host_of(&self.links[i].url);
+        self.cache.ban_host(&host)?;
+        Ok(format!("Banned {host}"))
+    }
+
+    fn remove(&mut self) -> anyhow::Result<String> {
+        let Some(i) = self.selected() else {
+            return Ok(String::new());
+        };
+        let removed = remove_links(&self.links[i].url)?;
+        let link = self.links.remove(i);
+        let selected = self.list.selected();
+        self.apply_filter();
+        self.list
+            .select(selected.filter(|_| !self.visible.is_empty()));
+        self.select(0);
+        Ok(format!(
+            "Removed {} ({} entries from links.json)",
+            link.title,
+            removed.len()
+        ))
+    }
+
+    fn refetch(&mut self) -> anyhow::Result<String> {
+        let Some(i) = self.selected() else {
+            return Ok(String::new());
+        };
+        let link = &self.links[i];
+        fetch_link(
+            &self.cache,
+            &SerializedLink::new(
+                link.url.clone(),
+                link.title.clone(),
+                link.tags.clone(),
+                link.source,
+            ),
+        )?;
+        if let Some(mut fetched) = self.cache.query(&link.url)? {
+            fetched.tags = std::mem::take(&mut self.links[i].tags);
+            self.links[i] = fetched;
+        }
+        Ok(format!("Re-fetched {}", self.links[i].url))
+    }
+
+    fn open(&self) -> anyhow::Result<String> {
+        let Some(i) = self.selected() else {
+            return Ok(String::new());
+        };
+        open_in_browser(&self.links[i].url)?;
+        Ok(format!("Opened {}", self.links[i].url))
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match &mut self.mode {
+                Mode::Filter => match key.code {
+                    KeyCode::Enter => self.mode = Mode::Browse,
+                    KeyCode::Esc => {
+                        self.filter.clear();
+                        self.apply_filter();
+                        self.mode = Mode::Browse;
+                    }
+                    KeyCode::Backspace => {
+                        self.filter.pop();
+                        self.apply_filter();
+                    }
+                    KeyCode::Char(c) => {
+                        self.filter.push(c);
+                        self.apply_filter();
+                    }
+                    _ => {}
+                },
+                Mode::EditTags(input) => match key.code {
+                    KeyCode::Enter => {
+                        let input = std::mem::take(input);
+                        self.mode = Mode::Browse;
+                        let result = self.edit_tags(&input);
+                        self.report(result);
+                    }
+                    KeyCode::Esc => self.mode = Mode::Browse,
+                    KeyCode::Backspace => {
+                        input.pop();
+                    }
+                    KeyCode::Char(c) => input.push(c),
+                    _ => {}
+                },
+                Mode::Confirm(action) => {
+                    let action = *action;
+                    self.mode = Mode::Browse;
+                    if key.code == KeyCode::Char('y') {
+                        let result = match action {
+                            Confirm::Ban => self.ban_host(),
+                            Confirm::Remove => self.remove(),
+                        };
+                        self.report(result);
+                    }
+                }
+                Mode::Browse => {
+                    self.status = None;
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                        KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                        KeyCode::PageDown => self.select(20),
+                        KeyCode::PageUp => self.select(-20),
+                        KeyCode::Home | KeyCode::Char('g') => self.select(isize::MIN / 2),
+                        KeyCode::End | KeyCode::Char('G') => self.select(isize::MAX / 2),
+                        KeyCode::Char('J') => {
+                            self.preview_scroll = self.preview_scroll.saturating_add(5)
+                        }
+                        KeyCode::Char('K') => {
+                            self.preview_scroll = self.preview_scroll.saturating_sub(5)
+                        }
+                        KeyCode::Char('/') => self.mode = Mode::Filter,
+                        KeyCode::Char('t') => {
+                            if let Some(i) = self.selected() {
+                                self.mode = Mode::EditTags(self.links[i].tags.join(", "));
+                            }
+                        }
+                        KeyCode::Char('b') => self.mode = Mode::Confirm(Confirm::Ban),
+                        KeyCode::Char('d') => self.mode = Mode::Confirm(Confirm::Remove),
+                        KeyCode::Char('o') => {
+                            let result = self.open();
+                            self.report(result);
+                        }
+                        KeyCode::Char('r') => {
+                            // Fetching blocks, so show what is happening first
+                            self.status = Some("Fetching…".to_string());
+                            terminal.draw(|frame| self.draw(frame))?;
+                            let result = self.refetch();
+                            self.report(result);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, preview_area] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let link = &self.links[i];
+                let mut spans = vec![Span::raw(link.title.clone())];
+                if !link.tags.is_empty() {
+                    spans.push(format!(" [{}]", link.tags.join(", ")).dark_gray());
+                }
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(format!(
+                " Links ({}/{}) ",
+                self.visible.len(),
+                self.links.len()
+            )))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let preview = match self.selected() {
+            Some(i) => {
+                let link = &self.links[i];
+                let mut lines = vec![
+                    Line::from(link.title.clone().bold()),
+                    Line::from(
+                        format!("{} · {}", link.source.as_str(), link.tags.join(", ")).dark_gray(),
+                    ),
+                    Line::default(),
+                ];
+                lines.extend(
+                    link.text_content
+                        .lines()
+                        .map(|line| Line::raw(line.to_string())),
+                );
+                Paragraph::new(lines)
+                    .block(
+                        Block::default()
+                            .borders(Borders::ALL)
+                            .title(format!(" {} ", link.url)),
+                    )
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.preview_scroll, 0))
+            }
+            None => Paragraph::new("No links match").block(Block::default().borders(Borders::ALL)),
+        };
+        frame.render_widget(preview, preview_area);
+
+        let status_line = match &self.mode {
+            Mode::Filter => format!("/{}", self.filter),
+            Mode::EditTags(input) => format!("Tags (comma separated): {input}"),
+            Mode::Confirm(Confirm::Ban) => match self.selected() {
+                Some(i) => format!("Ban {}? (y/n)", host_of(&self.links[i].url)),
+                None => String::new(),
+            },
+            Mode::Confirm(Confirm::Remove) => match self.selected() {
+                Some(i) => {
+                    let entries = self.entries.get(&link_key(&self.links[i].url));
+                    format!(
+                        "Remove {} entries from links.json? (y/n)",
+                        entries.copied().unwrap_or(0)
+                    )
+                }
+                None => String::new(),
+            },
+            Mode::Browse => match &self.status {
+                Some(status) => status.clone(),
+                None if !self.filter.is_empty() => format!("Filter: {}  ·  {HELP}", self.filter),
+                None => HELP.to_string(),
+            },
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+    }
+}
+
+pub fn run_tui() -> anyhow::Result<()> {
+    let cache = Cache::new(CacheType::Disk("cache.db".to_string()))?;
+    let mut app = App::load(cache)?;
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::LinkSource;
+
+    #[test]
+    fn test_filter_matches_tags_sources_and_text() {
+        let link = CachedLink::new(
+            "https://example.org/rust-async".to_string(),
+            "Async Rust".to_string(),
+            LinkSource::Zotero,
+            vec!["Programming".to_string()],
+            "Futures are polled by an executor.".to_string(),
+        );
+
+        assert!(Filter::parse("").matches(&link));
+        assert!(Filter::parse("async").matches(&link));
+        assert!(Filter::parse("EXECUTOR tag:programming").matches(&link));
+        assert!(Filter::parse("source:zotero example.org").matches(&link));
+        assert!(!Filter::parse("tag:rust").matches(&link));
+        assert!(!Filter::parse("source:goodlinks").matches(&link));
+        assert!(!Filter::parse("async tokio").matches(&link));
+    }
+}